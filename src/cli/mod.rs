@@ -0,0 +1,300 @@
+use failure::{Error, format_err};
+use chrono::{DateTime, Utc};
+use crate::io::format::OutputFormat;
+use crate::io::snapshot::{FieldSet, OutputField};
+use crate::io::trajectory::TrajectoryOptions;
+use crate::io::export::{ExportFormat, SnapshotExportOptions};
+use crate::nbody::history::ArchiveOptions;
+use crate::integrator::IntegrationScheme;
+use crate::orbital::osculating::ElementsReference;
+use crate::orbital::series::ElementsSeriesOptions;
+use crate::rendering::trails::{TrailOptions, TrailColouring};
+use crate::util::temporal;
+
+pub const DEFAULT_SYSTEM: &str = "resources/systems/test-system.json";
+pub const DEFAULT_HISTORY_LENGTH: usize = 400;
+pub const DEFAULT_DIAGNOSTICS_INTERVAL: usize = 100;
+
+pub enum Command {
+    Run(RunOptions),
+    Generate { model: String, seed: u64, system: Option<String>, output: Option<String>, params: serde_json::Value },
+    Replay { files: Vec<String>, speed: f64, trails: TrailOptions }
+}
+
+pub struct RunOptions {
+    pub system: String,
+    pub horizons: Option<String>,           // Builds the system from a Horizons import description instead
+    pub barycentric: bool,
+    pub diagnostics_interval: Option<usize>,
+    pub abort_threshold: Option<f64>,
+    pub until: Option<DateTime<Utc>>,       // Halts the run once the simulation clock reaches this date
+    pub headless: bool,                     // Runs to the 'until' date without a window
+    pub restore: Option<String>,
+    pub checkpoint: Option<String>,
+    pub checkpoint_interval: Option<usize>,
+    pub output: Option<TrajectoryOptions>,
+    pub snapshot: Option<SnapshotExportOptions>,
+    pub elements_output: Option<ElementsSeriesOptions>,
+    pub history_length: usize,
+    pub archive: Option<ArchiveOptions>,
+    pub integrator: Option<IntegrationScheme>,
+    pub trails: TrailOptions
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        Self {
+            system: DEFAULT_SYSTEM.to_string(),
+            horizons: None,
+            barycentric: false,
+            diagnostics_interval: None,
+            abort_threshold: None,
+            until: None,
+            headless: false,
+            restore: None,
+            checkpoint: None,
+            checkpoint_interval: None,
+            output: None,
+            snapshot: None,
+            elements_output: None,
+            history_length: DEFAULT_HISTORY_LENGTH,
+            archive: None,
+            integrator: None,
+            trails: TrailOptions::default()
+        }
+    }
+}
+
+pub const USAGE: &str = "\
+Usage:
+    stellar-nbody-simulation [run] [--barycentric] [--diagnostics <steps>] [--abort-threshold <error>]
+                             [--until <date> [--headless]]
+                             [--restore <checkpoint>] [--checkpoint <file> --checkpoint-interval <steps>]
+                             [--output <path> [--format csv|jsonl|binary] [--output-interval <steps>]
+                              [--entities <id,...>] [--fields <field,...>] [--rotate <snapshots>]]
+                             [--snapshot <path> [--snapshot-format npz|npy|hdf5] [--snapshot-interval <steps>]]
+                             [--elements-output <file> [--elements-interval <steps>]]
+                             [--history <states>] [--archive-interval <steps>] [--archive-memory <states>]
+                             [--archive-spill <file>] [--integrator euler|leapfrog]
+                             [--trail-length <time>] [--trail-colour body|speed|energy] [--no-trail-fade]
+                             [--horizons <import.json> | <system.json>]
+    stellar-nbody-simulation replay <recording>... [--speed <frames per update>] [<trail options>]
+    stellar-nbody-simulation generate <model> [--seed <n>] [--out <file>] [--<param> <value>]...
+    stellar-nbody-simulation generate belt --system <system.json> [--primary <id>] [--seed <n>] [--out <file>]
+                                           [--<param> <value>]...
+
+Generator models: plummer, king, hernquist, disc, collision, planetary.  Model parameters are given by their
+JSON field names, e.g. \"generate plummer --n 2000 --scale_radius 0.5 --seed 7\".  The belt generator instead
+adds a debris belt around the --primary entity of an existing --system file.  The --barycentric option moves
+the loaded system into its centre-of-mass frame.  --horizons builds the system from JPL Horizons vector
+tables, as listed with body masses in the import description, resolving table files relative to it.
+--diagnostics records conserved quantities every given number of steps (100 when only --abort-threshold is
+given), halting the run if any relative error exceeds the --abort-threshold.  --until halts the run once the
+simulation clock reaches a date (YYYY-MM-DD, RFC 3339 or JD<number>), or with --headless runs to it without a
+window, writing the final state to any --checkpoint file.  --restore resumes a run from a checkpoint file, and
+--checkpoint periodically writes one.  --output streams body trajectories to <path>.<ext>, optionally
+restricted to the given entities and fields (id, time, mass, position, velocity, elements), and starting a new
+numbered file every --rotate snapshots.  --snapshot exports positions, velocities, masses and ids for NumPy,
+as a single .npz archive or a directory of .npy files; HDF5 export is available when built with the \"hdf5\"
+feature.  --elements-output writes the osculating elements of each body about body 0 to a CSV time series,
+every --elements-interval steps.  --history sets the number of recent states held at full resolution; the
+--archive options retain every given step of older history, holding a number of states in memory before
+spilling them to a file, or discarding them if no --archive-spill file is given.  The leapfrog integrator is
+time-reversible, allowing the run to be reversed with B; Backspace rewinds through the in-memory history and
+branches a new run from there.  Trails cover the given --trail-length of simulation time, reading from the
+archive where the in-memory history is shorter, or otherwise the whole in-memory history, and are coloured by
+body, or by speed or specific energy over the length of each trail.
+
+Replay plays back trajectory output (.csv, .jsonl, .nbt, or the base path of a rotated series) or a series of
+checkpoint files: Space play/pause, Left/Right step a frame, Up/Down speed, R reverse, PageUp/PageDown or
+clicking the timeline to scrub";
+
+pub fn parse(args: &[String]) -> Result<Command, Error> {
+    match args.first().map(String::as_str) {
+        Some("run") => parse_run(&args[1..]),
+        Some("generate") => parse_generate(&args[1..]),
+        Some("replay") => parse_replay(&args[1..]),
+        _ => parse_run(args)
+    }
+}
+
+fn parse_run(args: &[String]) -> Result<Command, Error> {
+    let mut options = RunOptions::default();
+    let mut system = None;
+    let (mut output, mut format, mut output_interval, mut entities, mut fields, mut rotate) =
+        (None, OutputFormat::Csv, None, None, None, None);
+    let (mut snapshot, mut snapshot_format, mut snapshot_interval) = (None, ExportFormat::Npz, None);
+    let (mut elements_output, mut elements_interval) = (None, None);
+    let mut remaining = args.iter();
+
+    while let Some(arg) = remaining.next() {
+        match arg.as_str() {
+            "--barycentric" => options.barycentric = true,
+            "--horizons" => options.horizons = Some(parse_next(arg, remaining.next())?),
+            "--diagnostics" => options.diagnostics_interval = Some(parse_next(arg, remaining.next())?),
+            "--abort-threshold" => options.abort_threshold = Some(parse_next(arg, remaining.next())?),
+            "--headless" => options.headless = true,
+            "--until" => options.until = Some(temporal::parse_epoch(&parse_next::<String>(arg, remaining.next())?)
+                .map_err(|e| format_err!("{}", e))?),
+            "--restore" => options.restore = Some(parse_next(arg, remaining.next())?),
+            "--checkpoint" => options.checkpoint = Some(parse_next(arg, remaining.next())?),
+            "--checkpoint-interval" => options.checkpoint_interval = Some(parse_next(arg, remaining.next())?),
+            "--output" => output = Some(parse_next::<String>(arg, remaining.next())?),
+            "--format" => format = OutputFormat::parse(&parse_next::<String>(arg, remaining.next())?)
+                .map_err(|e| format_err!("{}", e))?,
+            "--output-interval" => output_interval = Some(parse_next(arg, remaining.next())?),
+            "--entities" => entities = Some(parse_list(&parse_next::<String>(arg, remaining.next())?)),
+            "--fields" => fields = Some(parse_list(&parse_next::<String>(arg, remaining.next())?).iter()
+                .map(|x| OutputField::parse(x))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| format_err!("{}", e))?),
+            "--rotate" => rotate = Some(parse_next(arg, remaining.next())?),
+            "--snapshot" => snapshot = Some(parse_next::<String>(arg, remaining.next())?),
+            "--snapshot-format" => snapshot_format = ExportFormat::parse(&parse_next::<String>(arg, remaining.next())?)
+                .map_err(|e| format_err!("{}", e))?,
+            "--snapshot-interval" => snapshot_interval = Some(parse_next(arg, remaining.next())?),
+            "--elements-output" => elements_output = Some(parse_next::<String>(arg, remaining.next())?),
+            "--elements-interval" => elements_interval = Some(parse_next(arg, remaining.next())?),
+            "--history" => options.history_length = parse_next::<usize>(arg, remaining.next())?.max(1),
+            "--archive-interval" => archive_options(&mut options).interval = parse_next(arg, remaining.next())?,
+            "--archive-memory" => archive_options(&mut options).memory_capacity = parse_next(arg, remaining.next())?,
+            "--archive-spill" => archive_options(&mut options).spill_path = Some(parse_next(arg, remaining.next())?),
+            "--integrator" => options.integrator = Some(IntegrationScheme::parse(&parse_next::<String>(arg, remaining.next())?)
+                .map_err(|e| format_err!("{}", e))?),
+            trail if trail.starts_with("--trail-") || trail == "--no-trail-fade" =>
+                parse_trail_option(arg, &mut remaining, &mut options.trails)?,
+            file if !file.starts_with('-') && system.is_none() => system = Some(file.to_string()),
+            other => return Err(format_err!("Unrecognised argument \"{}\"", other))
+        }
+    }
+
+    if options.abort_threshold.is_some() && options.diagnostics_interval.is_none() {
+        options.diagnostics_interval = Some(DEFAULT_DIAGNOSTICS_INTERVAL);
+    }
+
+    options.output = output.map(|path| {
+        let mut trajectory = TrajectoryOptions::new(path, format);
+        trajectory.interval = output_interval.unwrap_or(1);
+        trajectory.entities = entities.unwrap_or_default();
+        trajectory.fields = fields.map(FieldSet::new).unwrap_or_else(FieldSet::all);
+        trajectory.rotate_after = rotate;
+        trajectory
+    });
+
+    options.snapshot = snapshot.map(|path| SnapshotExportOptions {
+        path,
+        format: snapshot_format,
+        interval: snapshot_interval.unwrap_or(100)
+    });
+
+    if options.horizons.is_some() && system.is_some() {
+        return Err(format_err!("Cannot load both a system file and a Horizons import"));
+    }
+
+    options.elements_output = elements_output.map(|path| ElementsSeriesOptions {
+        path,
+        interval: elements_interval.unwrap_or(1),
+        reference: ElementsReference::Primary(0),
+        output_units: None
+    });
+
+    if options.headless {
+        if options.until.is_none() {
+            return Err(format_err!("--headless requires --until"));
+        }
+        if options.output.is_some() || options.snapshot.is_some() || options.elements_output.is_some() {
+            return Err(format_err!("--headless runs write only a final --checkpoint"));
+        }
+    }
+
+    options.system = system.unwrap_or(options.system);
+    Ok(Command::Run(options))
+}
+
+fn archive_options(options: &mut RunOptions) -> &mut ArchiveOptions {
+    options.archive.get_or_insert_with(ArchiveOptions::default)
+}
+
+fn parse_next<T: std::str::FromStr>(arg: &str, value: Option<&String>) -> Result<T, Error> {
+    let value = value.ok_or_else(|| format_err!("No value given for {}", arg))?;
+    value.parse().map_err(|_| format_err!("Invalid value \"{}\" for {}", value, arg))
+}
+
+fn parse_list(value: &str) -> Vec<String> {
+    value.split(',').map(str::trim).filter(|x| !x.is_empty()).map(str::to_string).collect()
+}
+
+fn parse_trail_option<'a>(arg: &str, remaining: &mut impl Iterator<Item = &'a String>, trails: &mut TrailOptions) -> Result<(), Error> {
+    match arg {
+        "--trail-length" => {
+            let length = parse_next::<f64>(arg, remaining.next())?;
+            if length <= 0.0 { return Err(format_err!("Invalid value \"{}\" for {}", length, arg)); }
+            trails.length = Some(length);
+        },
+        "--trail-colour" => trails.colouring = TrailColouring::parse(&parse_next::<String>(arg, remaining.next())?)
+            .map_err(|e| format_err!("{}", e))?,
+        "--no-trail-fade" => trails.fade = false,
+        other => return Err(format_err!("Unrecognised argument \"{}\"", other))
+    }
+    Ok(())
+}
+
+fn parse_replay(args: &[String]) -> Result<Command, Error> {
+    let (mut files, mut speed, mut trails) = (vec![], 1.0, TrailOptions::default());
+    let mut remaining = args.iter();
+
+    while let Some(arg) = remaining.next() {
+        match arg.as_str() {
+            "--speed" => speed = parse_next(arg, remaining.next())?,
+            trail if trail.starts_with("--trail-") || trail == "--no-trail-fade" =>
+                parse_trail_option(arg, &mut remaining, &mut trails)?,
+            file if !file.starts_with('-') => files.push(file.to_string()),
+            other => return Err(format_err!("Unrecognised argument \"{}\"", other))
+        }
+    }
+
+    if files.is_empty() {
+        return Err(format_err!("No recording specified"));
+    }
+
+    Ok(Command::Replay { files, speed, trails })
+}
+
+fn parse_generate(args: &[String]) -> Result<Command, Error> {
+    let model = args.first()
+        .ok_or_else(|| format_err!("No generator model specified"))?
+        .clone();
+
+    let (mut seed, mut system, mut output, mut params) = (0, None, None, serde_json::Map::new());
+    let mut remaining = args[1..].iter();
+
+    while let Some(arg) = remaining.next() {
+        let key = arg.trim_start_matches("--");
+        if key == arg.as_str() {
+            return Err(format_err!("Expected a --parameter, found \"{}\"", arg));
+        }
+
+        let value = remaining.next()
+            .ok_or_else(|| format_err!("No value given for {}", arg))?;
+
+        match key {
+            "seed" => seed = value.parse().map_err(|_| format_err!("Invalid seed \"{}\"", value))?,
+            "out" => output = Some(value.clone()),
+            "system" => system = Some(value.clone()),
+            _ => { params.insert(key.to_string(), parse_value(value)); }
+        }
+    }
+
+    match (model.as_str(), &system) {
+        ("belt", None) => return Err(format_err!("The belt generator requires a --system to add the belt to")),
+        (model, Some(_)) if model != "belt" => return Err(format_err!("--system is only used by the belt generator")),
+        _ => ()
+    }
+
+    Ok(Command::Generate { model, seed, system, output, params: serde_json::Value::Object(params) })
+}
+
+// Interprets a parameter as JSON where possible (numbers, objects), or otherwise as a plain string
+fn parse_value(value: &str) -> serde_json::Value {
+    serde_json::from_str(value).unwrap_or_else(|_| serde_json::Value::String(value.to_string()))
+}
@@ -0,0 +1,109 @@
+use std::time::Instant;
+
+const BASE_STEP_RATE: f64 = 60.0;                  // Steps per second at a warp factor of one
+const MAX_BACKLOG_SECS: f64 = 0.25;                 // Steps overdue by more than this are dropped rather than caught up
+const MAX_WARP: usize = 1024;
+const MIN_TIMESTEP_FACTOR: f64 = 1.0 / 1024.0;     // Limits of dt, relative to the initial timestep
+const MAX_TIMESTEP_FACTOR: f64 = 1024.0;
+const RATE_SAMPLE_SECS: f64 = 0.5;
+
+// User control of the running simulation: pausing, single steps, the step rate, the timestep and its
+// direction.  Steps fall due at a fixed rate in real time, independent of rendering, and the achieved
+// step rate is measured
+pub struct SimulationControls {
+    paused: bool,
+    pending_steps: usize,           // Single steps requested while paused
+    warp: usize,                    // Multiple of the base step rate
+    timestep: f64,
+    initial_timestep: f64,
+    reversed: bool,
+    accumulator: f64,               // Real time, in seconds, not yet simulated
+
+    rate_sample_start: Instant,
+    rate_sample_steps: usize,
+    steps_per_second: f64
+}
+
+impl SimulationControls {
+    pub fn new(timestep: f64) -> Self {
+        Self {
+            paused: false,
+            pending_steps: 0,
+            warp: 1,
+            timestep,
+            initial_timestep: timestep,
+            reversed: false,
+            accumulator: 0.0,
+
+            rate_sample_start: Instant::now(),
+            rate_sample_steps: 0,
+            steps_per_second: 0.0
+        }
+    }
+
+    pub fn is_paused(&self) -> bool { self.paused }
+    pub fn warp(&self) -> usize { self.warp }
+    pub fn timestep(&self) -> f64 { self.timestep }
+    pub fn steps_per_second(&self) -> f64 { self.steps_per_second }
+    pub fn is_reversed(&self) -> bool { self.reversed }
+    pub fn toggle_reversed(&mut self) { self.reversed = !self.reversed; }
+
+    // Signed timestep of the next step
+    pub fn step_dt(&self) -> f64 {
+        if self.reversed { -self.timestep } else { self.timestep }
+    }
+
+    pub fn toggle_paused(&mut self) {
+        self.paused = !self.paused;
+        self.pending_steps = 0;
+    }
+
+    // Advances by a single step, pausing the simulation if it is running
+    pub fn single_step(&mut self) {
+        if self.paused { self.pending_steps += 1; } else { self.paused = true; }
+    }
+
+    pub fn faster(&mut self) { self.warp = (self.warp * 2).min(MAX_WARP); }
+    pub fn slower(&mut self) { self.warp = (self.warp / 2).max(1); }
+
+    pub fn increase_timestep(&mut self) {
+        self.timestep = (self.timestep * 2.0).min(self.initial_timestep * MAX_TIMESTEP_FACTOR);
+    }
+
+    pub fn decrease_timestep(&mut self) {
+        self.timestep = (self.timestep / 2.0).max(self.initial_timestep * MIN_TIMESTEP_FACTOR);
+    }
+
+    // Returns the number of steps that have fallen due after a further 'elapsed' seconds of real time,
+    // consuming any pending single steps
+    pub fn steps_due(&mut self, elapsed: f64) -> usize {
+        if self.paused {
+            self.accumulator = 0.0;
+            return std::mem::replace(&mut self.pending_steps, 0);
+        }
+
+        let rate = BASE_STEP_RATE * self.warp as f64;
+        self.accumulator = (self.accumulator + elapsed).min(MAX_BACKLOG_SECS);
+
+        let steps = (self.accumulator * rate).floor();
+        self.accumulator -= steps / rate;
+        steps as usize
+    }
+
+    // Records steps taken, updating the measured step rate once enough time has passed
+    pub fn record_steps(&mut self, steps: usize) {
+        self.rate_sample_steps += steps;
+
+        let elapsed = self.rate_sample_start.elapsed();
+        let secs = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9;
+        if secs >= RATE_SAMPLE_SECS {
+            self.steps_per_second = self.rate_sample_steps as f64 / secs;
+            self.rate_sample_start = Instant::now();
+            self.rate_sample_steps = 0;
+        }
+    }
+
+    pub fn status_text(&self) -> String {
+        format!("{:.0} steps/s, warp x{}{}", self.steps_per_second, self.warp, if self.paused { ", PAUSED" } else { "" })
+    }
+}
@@ -2,3 +2,6 @@ pub mod types;
 pub mod constants;
 pub mod defaults;
 pub mod simulation;
+pub mod replay;
+pub mod controls;
+pub mod physics;
@@ -0,0 +1,451 @@
+use std::ops::{Add, Sub, Mul, Div, AddAssign};
+use core::iter::Sum;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Sender, Receiver, TryRecvError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use crate::core::types::{Numeric, Time};
+use crate::core::controls::SimulationControls;
+use crate::nbody::nbody_system::NBodySystem;
+use crate::nbody::history::{HistoryRecord, HistoryStamp};
+use crate::state::State;
+use crate::orbital::series::{ElementsSeriesWriter, ElementsSeriesOptions};
+use crate::io::trajectory::{TrajectoryWriter, TrajectoryOptions};
+use crate::io::export::{SnapshotExport, SnapshotExportOptions, SnapshotMetadata};
+use chrono::{DateTime, Utc};
+
+const IDLE_SLEEP: Duration = Duration::from_millis(1);
+const MAX_TICK_SECS: f64 = 1.0 / 30.0;     // Steps still due after this long are dropped, so that snapshots and commands are not delayed
+
+// Requests from the user interface, applied by the physics thread between steps
+pub enum PhysicsCommand<TNum>
+    where TNum: Numeric {
+    TogglePaused,
+    SingleStep,
+    Faster,
+    Slower,
+    IncreaseTimestep,
+    DecreaseTimestep,
+    ReverseTime,
+    Rewind(usize),
+    Edit(Box<dyn FnOnce(&mut State<TNum>) + Send>),
+    SaveCheckpoint(String),
+    QueryHistory(Time, Time),           // Retained states in a time range, returned with a following snapshot
+    Stop
+}
+
+// State of the physics thread at the time of a snapshot
+#[derive(Debug, Clone)]
+pub struct PhysicsStatus {
+    pub timestep: f64,
+    pub reversed: bool,
+    pub controls: String,
+    pub diagnostics: Option<String>,
+    pub archive: Option<String>,        // Extent of the history archive, or that it has been disabled
+    pub failure: Option<String>         // Output or checkpoint write failure that halted stepping
+}
+
+// States produced since the previous snapshot, oldest first.  If 'reset' is set the history has been
+// replaced, e.g. by an edit or rewind, and the records are the complete in-memory history.  Replies to history
+// queries made before a reset are dropped, as the states they hold may no longer be part of the history
+pub struct PhysicsSnapshot<TNum>
+    where TNum: Numeric {
+    pub records: Vec<HistoryRecord<TNum>>,
+    pub reset: bool,
+    pub history: Option<Vec<HistoryRecord<TNum>>>,    // Reply to the latest history query, oldest first
+    pub status: PhysicsStatus
+}
+
+// Outputs recorded by the physics thread as the system is stepped
+pub struct PhysicsOptions {
+    pub elements_output: Option<ElementsSeriesOptions>,
+    pub trajectory_output: Option<TrajectoryOptions>,
+    pub snapshot_export: Option<SnapshotExportOptions>,
+    pub stop_at: Option<DateTime<Utc>>,         // Stepping halts once the clock reaches this date
+    pub checkpoint: Option<CheckpointOptions>
+}
+
+// Periodic checkpointing of the running system, overwriting the same file every 'interval' steps
+pub struct CheckpointOptions {
+    pub path: String,
+    pub interval: usize
+}
+
+// Double buffer between the threads.  The physics thread fills a back snapshot and swaps it to the front
+// when publishing, merging with any front snapshot not yet taken; the renderer takes the front snapshot
+// when one is available.  The lock is held only to move snapshots in and out
+struct SnapshotBuffer<TNum>
+    where TNum: Numeric {
+    front: Mutex<Option<PhysicsSnapshot<TNum>>>,
+    capacity: usize             // States beyond the in-memory history length would be discarded by the renderer
+}
+
+impl <TNum> SnapshotBuffer<TNum>
+    where TNum: Numeric {
+
+    fn publish(&self, mut snapshot: PhysicsSnapshot<TNum>) {
+        let mut front = self.front.lock().unwrap_or_else(|e| panic!("Snapshot buffer poisoned ({})", e));
+
+        if let Some(previous) = front.take() {
+            if !snapshot.reset {
+                let mut records = previous.records;
+                records.extend(snapshot.records);
+                snapshot.records = records;
+                snapshot.reset = previous.reset;
+                snapshot.history = snapshot.history.or(previous.history);
+            }
+        }
+
+        let excess = snapshot.records.len().saturating_sub(self.capacity);
+        snapshot.records.drain(..excess);
+        *front = Some(snapshot);
+    }
+
+    fn take(&self) -> Option<PhysicsSnapshot<TNum>> {
+        self.front.lock().unwrap_or_else(|e| panic!("Snapshot buffer poisoned ({})", e)).take()
+    }
+}
+
+// Runs an n-body system on a dedicated thread, stepping at a fixed rate in real time so that simulation
+// speed does not depend on rendering, and publishing the resulting states for display
+pub struct PhysicsThread<TNum>
+    where TNum: Numeric {
+    commands: Sender<PhysicsCommand<TNum>>,
+    snapshots: Arc<SnapshotBuffer<TNum>>,
+    handle: Option<JoinHandle<()>>
+}
+
+impl <TNum> PhysicsThread<TNum>
+    where TNum: Numeric + Add<Output = TNum> + Sub<Output = TNum> + Mul<Output = TNum> + Div<Output = TNum> + AddAssign + Sum + Send + 'static {
+
+    // Moves the system to a new thread.  Outputs are created on that thread, and any failure to create
+    // them is reported here
+    pub fn spawn(nbody_system: NBodySystem<TNum>, options: PhysicsOptions, timestep: f64) -> Self {
+        let snapshots = Arc::new(SnapshotBuffer {
+            front: Mutex::new(None),
+            capacity: nbody_system.get_max_state_history_length()
+        });
+        let (commands, receiver) = mpsc::channel();
+        let (ready_sender, ready) = mpsc::channel();
+
+        let buffer = Arc::clone(&snapshots);
+        let handle = thread::Builder::new()
+            .name("physics".to_string())
+            .spawn(move || {
+                match PhysicsRunner::create(nbody_system, options, timestep, buffer) {
+                    Ok(runner) => {
+                        ready_sender.send(Ok(())).unwrap_or_else(|e| panic!("Physics thread cannot report startup ({})", e));
+                        runner.run(receiver);
+                    },
+                    Err(e) => ready_sender.send(Err(e)).unwrap_or_else(|e| panic!("Physics thread cannot report startup ({})", e))
+                }
+            })
+            .unwrap_or_else(|e| panic!("Cannot start physics thread ({})", e));
+
+        ready.recv()
+            .unwrap_or_else(|e| panic!("Physics thread failed during startup ({})", e))
+            .unwrap_or_else(|e| panic!("{}", e));
+
+        Self { commands, snapshots, handle: Some(handle) }
+    }
+
+    pub fn send(&self, command: PhysicsCommand<TNum>) {
+        // The thread only exits once stopped, or after a failure that it will already have reported
+        let _ = self.commands.send(command);
+    }
+
+    // Returns the states and status published since the last call, if any
+    pub fn take_snapshot(&self) -> Option<PhysicsSnapshot<TNum>> {
+        self.snapshots.take()
+    }
+}
+
+impl <TNum> Drop for PhysicsThread<TNum>
+    where TNum: Numeric {
+    fn drop(&mut self) {
+        let _ = self.commands.send(PhysicsCommand::Stop);
+        if let Some(handle) = self.handle.take() {
+            if handle.join().is_err() {
+                println!("Physics thread terminated abnormally");
+            }
+        }
+    }
+}
+
+// Owns the system and its outputs on the physics thread
+struct PhysicsRunner<TNum>
+    where TNum: Numeric {
+    nbody_system: NBodySystem<TNum>,
+    controls: SimulationControls,
+    elements_writer: Option<ElementsSeriesWriter>,
+    trajectory_writer: Option<TrajectoryWriter>,
+    snapshot_export: Option<SnapshotExport>,
+    stop_at: Option<DateTime<Utc>>,
+    checkpoint: Option<CheckpointOptions>,
+    failure: Option<String>,
+
+    snapshots: Arc<SnapshotBuffer<TNum>>,
+    pending: Vec<HistoryRecord<TNum>>,  // States produced since the last snapshot
+    reset: bool,
+    history: Option<Vec<HistoryRecord<TNum>>>
+}
+
+impl <TNum> PhysicsRunner<TNum>
+    where TNum: Numeric + Add<Output = TNum> + Sub<Output = TNum> + Mul<Output = TNum> + Div<Output = TNum> + AddAssign + Sum {
+
+    fn create(nbody_system: NBodySystem<TNum>, options: PhysicsOptions, timestep: f64,
+              snapshots: Arc<SnapshotBuffer<TNum>>) -> Result<Self, String> {
+        let elements_writer = match options.elements_output.as_ref() {
+            Some(opt) => Some(ElementsSeriesWriter::create(opt, nbody_system.get_gravitational_constant().into_f64(), nbody_system.get_units())
+                .map_err(|e| format!("Cannot create orbital elements output \"{}\" ({:?})", opt.path, e))?),
+            None => None
+        };
+        let trajectory_writer = match options.trajectory_output.as_ref() {
+            Some(opt) => Some(TrajectoryWriter::create(opt, &PhysicsRunner::snapshot_metadata(&nbody_system))
+                .map_err(|e| format!("Cannot create trajectory output \"{}\" ({:?})", opt.path, e))?),
+            None => None
+        };
+        let snapshot_export = match options.snapshot_export.as_ref() {
+            Some(opt) => Some(SnapshotExport::create(opt, &PhysicsRunner::snapshot_metadata(&nbody_system))
+                .map_err(|e| format!("Cannot create snapshot export \"{}\" ({:?})", opt.path, e))?),
+            None => None
+        };
+
+        Ok(Self {
+            nbody_system,
+            controls: SimulationControls::new(timestep),
+            elements_writer,
+            trajectory_writer,
+            snapshot_export,
+            stop_at: options.stop_at,
+            checkpoint: options.checkpoint,
+            failure: None,
+
+            snapshots,
+            pending: vec![],
+            reset: false,
+            history: None
+        })
+    }
+
+    fn snapshot_metadata(nbody_system: &NBodySystem<TNum>) -> SnapshotMetadata {
+        SnapshotMetadata::new::<TNum>(
+            nbody_system.get_units(),
+            nbody_system.get_gravitational_constant().into_f64(),
+            nbody_system.get_softening_constant().into_f64(),
+            nbody_system.get_clock().epoch().to_rfc3339())
+    }
+
+    // Applies commands and takes any steps that have fallen due, publishing a snapshot after each tick,
+    // until stopped
+    fn run(mut self, commands: Receiver<PhysicsCommand<TNum>>) {
+        let mut last_tick = Instant::now();
+
+        loop {
+            loop {
+                match commands.try_recv() {
+                    Ok(PhysicsCommand::Stop) | Err(TryRecvError::Disconnected) => return self.finish(),
+                    Ok(command) => self.apply(command),
+                    Err(TryRecvError::Empty) => break
+                }
+            }
+
+            let tick = Instant::now();
+            let due = self.controls.steps_due(secs(tick.duration_since(last_tick)));
+            last_tick = tick;
+
+            let mut taken = 0;
+            while taken < due && secs(tick.elapsed()) < MAX_TICK_SECS && !self.is_halted() {
+                self.step();
+                taken += 1;
+            }
+            self.controls.record_steps(taken);
+
+            self.publish();
+            if taken == 0 {
+                thread::sleep(IDLE_SLEEP);
+            }
+        }
+    }
+
+    fn apply(&mut self, command: PhysicsCommand<TNum>) {
+        match command {
+            PhysicsCommand::TogglePaused => self.controls.toggle_paused(),
+            PhysicsCommand::SingleStep => self.controls.single_step(),
+            PhysicsCommand::Faster => self.controls.faster(),
+            PhysicsCommand::Slower => self.controls.slower(),
+            PhysicsCommand::IncreaseTimestep => self.controls.increase_timestep(),
+            PhysicsCommand::DecreaseTimestep => self.controls.decrease_timestep(),
+            PhysicsCommand::ReverseTime => self.reverse_time_direction(),
+            PhysicsCommand::Rewind(steps) => self.rewind(steps),
+            PhysicsCommand::Edit(edit) => {
+                self.nbody_system.edit_current_state(edit);
+                self.reset = true;
+            },
+            PhysicsCommand::SaveCheckpoint(file) => {
+                if let Err(e) = self.save_checkpoint(file.as_str()) {
+                    self.fail(e);
+                }
+            },
+            PhysicsCommand::QueryHistory(start, end) => self.query_history(start, end),
+            PhysicsCommand::Stop => ()
+        }
+    }
+
+    fn step(&mut self) {
+        self.nbody_system.step(TNum::from_f64(self.controls.step_dt()));
+        let recorded = self.record_elements_series()
+            .and_then(|_| self.record_trajectory())
+            .and_then(|_| self.record_snapshot_export())
+            .and_then(|_| self.write_periodic_checkpoint());
+        if let Err(e) = recorded {
+            self.fail(e);
+        }
+
+        if !self.reset {
+            if self.pending.len() >= self.snapshots.capacity {
+                self.pending.remove(0);
+            }
+            self.pending.push(HistoryRecord {
+                stamp: HistoryStamp { step: self.nbody_system.get_step_count(), time: self.nbody_system.get_clock().elapsed() },
+                state: self.nbody_system.get_current_state().clone()
+            });
+        }
+    }
+
+    fn publish(&mut self) {
+        let records = if self.reset {
+            self.nbody_system.get_history_records()
+        } else {
+            std::mem::replace(&mut self.pending, vec![])
+        };
+
+        let history = self.history.take().filter(|_| !self.reset);
+        self.snapshots.publish(PhysicsSnapshot {
+            records,
+            reset: self.reset,
+            history,
+            status: PhysicsStatus {
+                timestep: self.controls.timestep(),
+                reversed: self.controls.is_reversed(),
+                controls: self.controls.status_text(),
+                diagnostics: self.nbody_system.get_diagnostics().map(|x| x.report()),
+                archive: self.archive_status(),
+                failure: self.failure.clone()
+            }
+        });
+
+        self.pending.clear();
+        self.reset = false;
+    }
+
+    // Replies with no states if the archive cannot be read, so that the query is still answered
+    fn query_history(&mut self, start: Time, end: Time) {
+        let records = self.nbody_system.get_state_history_range(start, end).unwrap_or_else(|e| {
+            println!("Cannot query state history ({})", e);
+            vec![]
+        });
+        self.history = Some(records);
+    }
+
+    fn archive_status(&self) -> Option<String> {
+        match self.nbody_system.get_history_archive() {
+            Some(archive) => Some(format!("archive {} in memory, {} spilled", archive.memory_count(), archive.spilled_count())),
+            None if self.nbody_system.is_archive_disabled() => Some("archive disabled after a write failure".to_string()),
+            None => None
+        }
+    }
+
+    fn is_halted(&self) -> bool {
+        self.failure.is_some() || self.nbody_system.is_aborted() || self.stop_at.as_ref()
+            .map(|target| self.nbody_system.get_clock().time_until(target) <= 0.0)
+            .unwrap_or(false)
+    }
+
+    fn reverse_time_direction(&mut self) {
+        if self.nbody_system.is_time_reversible() {
+            self.controls.toggle_reversed();
+        } else {
+            println!("Cannot reverse time with the {} integrator; a time-reversible scheme such as leapfrog is required",
+                     self.nbody_system.get_integration_scheme().name());
+        }
+    }
+
+    // Rewinds to an earlier state in the history and continues a new run from there
+    fn rewind(&mut self, steps: usize) {
+        match self.nbody_system.rewind(steps) {
+            Ok(rewound) => println!("Rewound {} steps to step {}", rewound, self.nbody_system.get_step_count()),
+            Err(e) => println!("Cannot rewind ({})", e)
+        }
+        self.reset = true;
+    }
+
+    // Halts stepping after a failure to write an output, keeping the first failure for display
+    fn fail(&mut self, message: String) {
+        println!("{}; stepping halted", message);
+        self.failure.get_or_insert(message);
+    }
+
+    fn record_elements_series(&mut self) -> Result<(), String> {
+        if let Some(writer) = self.elements_writer.as_mut() {
+            let (step, time) = (self.nbody_system.get_step_count(), self.nbody_system.get_clock().elapsed());
+            writer.record(step, time, &*self.nbody_system.get_current_state())
+                .map_err(|e| format!("Failed to write orbital elements ({})", e))?;
+        }
+        Ok(())
+    }
+
+    fn record_trajectory(&mut self) -> Result<(), String> {
+        if let Some(writer) = self.trajectory_writer.as_mut() {
+            let (step, time) = (self.nbody_system.get_step_count(), self.nbody_system.get_clock().elapsed());
+            writer.record(step, time, &*self.nbody_system.get_current_state())
+                .map_err(|e| format!("Failed to write trajectory output ({})", e))?;
+        }
+        Ok(())
+    }
+
+    fn record_snapshot_export(&mut self) -> Result<(), String> {
+        if let Some(export) = self.snapshot_export.as_mut() {
+            let (step, time) = (self.nbody_system.get_step_count(), self.nbody_system.get_clock().elapsed());
+            export.record(step, time, &*self.nbody_system.get_current_state())
+                .map_err(|e| format!("Failed to export snapshot ({})", e))?;
+        }
+        Ok(())
+    }
+
+    fn write_periodic_checkpoint(&self) -> Result<(), String> {
+        match &self.checkpoint {
+            Some(options) if self.nbody_system.get_step_count() % options.interval.max(1) == 0 =>
+                self.save_checkpoint(options.path.as_str()),
+            _ => Ok(())
+        }
+    }
+
+    fn save_checkpoint(&self, file: &str) -> Result<(), String> {
+        self.nbody_system.save_checkpoint(file)
+            .map_err(|e| format!("Failed to save checkpoint \"{}\" ({})", file, e))?;
+        println!("Checkpoint saved to \"{}\" at step {}", file, self.nbody_system.get_step_count());
+        Ok(())
+    }
+
+    // Completes the outputs explicitly, rather than on drop, so that any failure to write them is reported
+    fn finish(mut self) {
+        if let Some(Err(e)) = self.elements_writer.as_mut().map(|x| x.flush()) {
+            println!("Failed to write orbital elements ({})", e);
+        }
+        if let Some(Err(e)) = self.trajectory_writer.as_mut().map(|x| x.flush()) {
+            println!("Failed to write trajectory output ({})", e);
+        }
+        if let Some(Err(e)) = self.snapshot_export.as_mut().map(|x| x.finish()) {
+            println!("Failed to export snapshot ({})", e);
+        }
+
+        if let Some(diagnostics) = self.nbody_system.get_diagnostics() {
+            println!("Diagnostics at step {}: {}", self.nbody_system.get_step_count(), diagnostics.report());
+        }
+    }
+}
+
+fn secs(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 * 1e-9
+}
@@ -0,0 +1,105 @@
+use crate::core::types::Numeric;
+use crate::io::reader::Recording;
+use crate::nbody::history::{HistoryRecord, HistoryStamp};
+
+const MAX_PLAYBACK_SPEED: f64 = 64.0;
+const MIN_PLAYBACK_SPEED: f64 = 1.0 / 64.0;
+
+// Playback of a recorded run.  The position is a fractional frame index advanced by 'speed' frames on
+// each update of the event loop, so that speeds below one hold each frame across several updates
+pub struct Replay {
+    recording: Recording,
+    position: f64,
+    speed: f64,
+    reverse: bool,
+    playing: bool,
+    loaded_frame: Option<usize>
+}
+
+impl Replay {
+    pub fn new(recording: Recording, speed: f64) -> Self {
+        Self {
+            recording,
+            position: 0.0,
+            speed: speed.abs().max(MIN_PLAYBACK_SPEED).min(MAX_PLAYBACK_SPEED),
+            reverse: false,
+            playing: true,
+            loaded_frame: None
+        }
+    }
+
+    pub fn recording(&self) -> &Recording { &self.recording }
+    pub fn frame_count(&self) -> usize { self.recording.frames.len() }
+    pub fn frame_index(&self) -> usize { self.position.round() as usize }
+    pub fn is_playing(&self) -> bool { self.playing }
+
+    pub fn progress(&self) -> f64 {
+        if self.frame_count() > 1 { self.position / (self.frame_count() - 1) as f64 } else { 1.0 }
+    }
+
+    // Advances playback, returning true if a different frame should now be displayed.  The first update
+    // always displays the initial frame
+    pub fn update(&mut self) -> bool {
+        if self.playing && self.loaded_frame.is_some() {
+            let last = self.last_frame() as f64;
+            self.position += if self.reverse { -self.speed } else { self.speed };
+
+            if self.position <= 0.0 || self.position >= last {
+                self.position = self.position.max(0.0).min(last);
+                self.playing = false;
+            }
+        }
+
+        let frame = self.frame_index();
+        let changed = self.loaded_frame != Some(frame);
+        self.loaded_frame = Some(frame);
+        changed
+    }
+
+    pub fn toggle_playing(&mut self) {
+        // Restart from the opposite end if playback had run to completion
+        let at_end = if self.reverse { self.frame_index() == 0 } else { self.frame_index() == self.last_frame() };
+        if !self.playing && at_end {
+            self.position = if self.reverse { self.last_frame() as f64 } else { 0.0 };
+        }
+
+        self.playing = !self.playing;
+    }
+
+    pub fn toggle_reverse(&mut self) { self.reverse = !self.reverse; }
+
+    pub fn faster(&mut self) { self.speed = (self.speed * 2.0).min(MAX_PLAYBACK_SPEED); }
+    pub fn slower(&mut self) { self.speed = (self.speed / 2.0).max(MIN_PLAYBACK_SPEED); }
+
+    // Moves by a number of frames, pausing playback
+    pub fn step_frames(&mut self, frames: isize) {
+        self.playing = false;
+        self.position = (self.frame_index() as isize + frames).max(0).min(self.last_frame() as isize) as f64;
+    }
+
+    // Jumps to a fraction [0 1] of the way through the recording
+    pub fn scrub_to(&mut self, fraction: f64) {
+        self.position = (fraction.max(0.0).min(1.0) * self.last_frame() as f64).round();
+    }
+
+    // Records of up to 'count' frames ending with the current frame, oldest first
+    pub fn history<TNum>(&self, count: usize) -> Vec<HistoryRecord<TNum>>
+        where TNum: Numeric {
+        let end = self.frame_index() + 1;
+        let start = end.saturating_sub(count.max(1));
+
+        self.recording.frames[start..end].iter()
+            .map(|x| HistoryRecord { stamp: HistoryStamp { step: x.step, time: x.time }, state: x.to_state() })
+            .collect()
+    }
+
+    pub fn status_text(&self) -> String {
+        format!("Replay frame {}/{} ({}, {}{}x)", self.frame_index() + 1, self.frame_count(),
+                if self.playing { "playing" } else { "paused" },
+                if self.reverse { "-" } else { "" }, self.speed)
+    }
+
+    fn last_frame(&self) -> usize {
+        self.frame_count().saturating_sub(1)
+    }
+}
@@ -4,22 +4,49 @@ use core::iter::Sum;
 use ::image;
 use piston_window::*;
 use crate::rendering;
-use crate::rendering::{BackBuffer, Renderer};
+use crate::rendering::{BackBuffer, Renderer, RenderOptions};
+use crate::rendering::camera::{Camera, CameraFocus};
+use crate::rendering::style::BodyStyles;
+use crate::rendering::trails::{TrailOptions, TrailColouring};
+use crate::rendering::framing::AutoFrame;
+use crate::rendering::field::FieldQuantity;
 use crate::text;
 use std::cell::{RefCell, Ref, RefMut};
 use crate::util::temporal::get_current_timestamp_secs;
 use crate::nbody::nbody_system::NBodySystem;
-use crate::core::types::Numeric;
+use crate::core::types::{Numeric, Time};
+use crate::core::replay::Replay;
+use crate::core::physics::{PhysicsThread, PhysicsCommand, PhysicsOptions, PhysicsStatus, CheckpointOptions};
+use crate::orbital::osculating::{self, ElementsReference};
+use crate::orbital::series::ElementsSeriesOptions;
+use crate::io::trajectory::TrajectoryOptions;
+use crate::io::export::SnapshotExportOptions;
+use crate::units::UnitSystem;
+use crate::units::quantity::Dimension;
+use crate::frames::{self, ReferenceFrame};
+use crate::math::vec3::Vec3;
+use crate::state::State;
+use chrono::{DateTime, Utc};
 
 const MOUSE_LEFT: usize = 0;
 const MOUSE_RIGHT: usize = 1;
 const MOUSE_BUTTON_COUNT: usize = 2;
 
 const SCROLL_SCALING_FACTOR: f64 = 0.1;
-const PAN_SCALING_FACTOR: f64 = 1.5;
 
 const MAX_OBJECT_SELECT_DISTANCE_SQ: f64 = 2.0 * 2.0;
 
+pub const SIMULATION_TIMESTEP: f64 = 0.008;
+
+const TIMELINE_HEIGHT: f64 = 0.015;
+const REWIND_STEPS: usize = 100;
+
+const SPAWN_MASS_FRACTION: f64 = 1e-3;      // Mass of spawned bodies, relative to the total system mass
+const SPAWN_DRAG_STEPS: f64 = 100.0;        // Spawn velocity covers the dragged distance in this many steps
+const EDIT_MASS_FACTOR: f64 = 1.1;
+const EDIT_SPEED_FACTOR: f64 = 1.05;
+const TRAIL_ARCHIVE_REFRESH: f64 = 0.1;     // Archived trail states are requested again after moving this fraction of the trail length
+
 pub struct Simulation<TNum>
     where TNum: Numeric {
 
@@ -31,17 +58,35 @@ pub struct Simulation<TNum>
     window_size: [f64; 2],
     canvas: BackBuffer,
 
-    zoom_level: f64,
-    view_origin: [f64; 2],
+    camera: Camera,
+    camera_focus: Option<CameraFocus>,
+    auto_frame: AutoFrame,          // Keeps the view fitted to the system until the user pans or zooms
+    show_grid: bool,
+    field: Option<FieldQuantity>,
+    relative_trails: bool,          // Draws trails relative to the camera focus
+    trails: TrailOptions,
     cursor_pos: [f64; 2],
     mouse_down_point: [Option<[f64; 2]>; MOUSE_BUTTON_COUNT],
-
-    nbody_system: NBodySystem<TNum>,
-    renderer: RefCell<Renderer>
+    shift_down: bool,
+
+    nbody_system: NBodySystem<TNum>,           // Copy of the system for display, unless replaying
+    physics: Option<PhysicsThread<TNum>>,       // Runs the system itself, publishing states to the display copy
+    physics_status: Option<PhysicsStatus>,
+    archive_enabled: bool,                      // Trails may be extended beyond the in-memory history from the archive
+    history_requested_at: Option<Time>,         // Clock time of the latest request for archived trail states
+    history_pending: bool,
+    renderer: RefCell<Renderer>,
+
+    selected_entity: Option<usize>,
+    elements_reference: ElementsReference,
+    display_units: UnitSystem,
+    display_frame: ReferenceFrame,
+    replay: Option<Replay>,
+    edit_mode: bool                 // Right mouse button spawns bodies rather than panning
 }
 
 impl <TNum> Simulation<TNum>
-    where TNum: Numeric + Add<Output = TNum> + Sub<Output = TNum> + Mul<Output = TNum> + Div<Output = TNum> + AddAssign + Sum {
+    where TNum: Numeric + Add<Output = TNum> + Sub<Output = TNum> + Mul<Output = TNum> + Div<Output = TNum> + AddAssign + Sum + Send + 'static {
 
     pub fn execute(&mut self) {
         let factory: GfxFactory = self.window().factory.clone();
@@ -49,8 +94,6 @@ impl <TNum> Simulation<TNum>
         let mut texture: G2dTexture = Texture::from_image(&mut texture_context,&self.canvas, &TextureSettings::new()).unwrap();
 
         loop {
-            self.nbody_system.step(TNum::from_f64(0.008));    // Temporarily within render loop
-
             let e_next = self.window_mut().next();
             if e_next == None { break; }
             let e = e_next.unwrap();
@@ -92,13 +135,18 @@ impl <TNum> Simulation<TNum>
                     _ => ()
                 }
                 Event::Loop(event) => match event {
+                    Loop::Update(_) => self.update_replay(),
                     Loop::Render(_) => {
+                        self.update_from_physics();
+                        self.update_camera_focus();
+                        self.update_auto_frame();
+                        self.update_backbuffer();
                         texture.update(&mut texture_context, &self.canvas).unwrap();
-                        let zoom_level = self.zoom_level;
-                        let view_origin = self.view_origin;
+                        let display_frame = self.display_frame;
+                        let units = self.nbody_system.get_units();
+                        let render_options = self.render_options();
                         let render_size = self.draw_sizef;
                         let window_size = self.window_size;
-                        let scaled_size = (self.draw_sizef[0] / self.zoom_level, self.draw_sizef[1] / self.zoom_level);
                         let mut text_manager = self.text_manager.borrow_mut();
                         let glyph_cache = text_manager.glyph_cache();
                         let mut renderer = self.renderer.borrow_mut();
@@ -108,47 +156,121 @@ impl <TNum> Simulation<TNum>
                             let context = piston_window::Context::new_abs(render_size[0], render_size[1])
                                 .scale(render_size[0], render_size[1]);
 
-                            // Render all window content
-                            renderer.perform_rendering(g, &context, scaled_size, zoom_level, view_origin, &self.nbody_system);
+                            // Render all window content, over the field in the backbuffer if shown
+                            texture_context.encoder.flush(device);
+                            let background = if render_options.field.is_some() { Some(&texture) } else { None };
+                            let labels = renderer.perform_rendering(g, &context, &self.camera, &self.nbody_system, background, &render_options);
+                            labels.iter().for_each(|label|
+                                self.render_text(label.text.as_str(), &label.position, label.colour, 12, glyph_cache, &context, g));
 
                             // Render status text
                             self.render_text_lines(vec![
-                                format!("Step {}, Pos[1] = {:?}", self.nbody_system.get_step_count(), self.nbody_system.get_current_state().position(1)).as_str(),
-                                format!("Vel[1] = {:?}", self.nbody_system.get_current_state().velocity(1)).as_str()
+                                format!("{}, {} frame{}{}{}{}{}{}", self.nbody_system.get_clock().format_current(), display_frame.description(),
+                                        self.trails_description(),
+                                        self.field.map(|x| format!(", {} field", x.description())).unwrap_or_default(),
+                                        if self.auto_frame.is_enabled() { ", auto-fit" } else { "" },
+                                        if self.physics_status.as_ref().map(|x| x.reversed).unwrap_or(false) { ", reversed" } else { "" },
+                                        if self.edit_mode { ", editing" } else { "" },
+                                        self.camera_focus.map(|x| format!(", {}", x.description(&*self.nbody_system.get_current_state())))
+                                            .unwrap_or_default()).as_str(),
+                                format!("Step {}{}", self.nbody_system.get_step_count(), self.physics_status.as_ref()
+                                    .map(|x| format!(", dt = {:.5} {}, {}{}", units.convert(x.timestep, Dimension::Time, &self.display_units),
+                                                     self.display_units.time_label(), x.controls,
+                                                     x.archive.as_ref().map(|a| format!(", {}", a)).unwrap_or_default()))
+                                    .unwrap_or_default()).as_str()
                             ],
-                            &[0.01, 0.90], 0.035, [0.0,1.0,0.0,1.0], 14, glyph_cache, &context, g);
+                            &[0.01, 0.87], 0.035, [0.0,1.0,0.0,1.0], 14, glyph_cache, &context, g);
+
+                            // Render conservation diagnostics, if enabled
+                            if let Some(diagnostics) = self.physics_status.as_ref().and_then(|x| x.diagnostics.as_ref()) {
+                                self.render_text(diagnostics.as_str(), &[0.01, 0.98], [0.0,1.0,0.0,1.0], 14, glyph_cache, &context, g);
+                            }
 
-                            // Apply pre-rendered backbuffer target (if not panning the map)
-                            if !self.is_mouse_dragging(MOUSE_RIGHT) {
-                                texture_context.encoder.flush(device);
-                                image(&texture, context.scale(1.0 / texture.get_width() as f64, 1.0 / texture.get_height() as f64).transform, g);
+                            // Render any output failure that halted stepping
+                            if let Some(failure) = self.physics_status.as_ref().and_then(|x| x.failure.as_ref()) {
+                                self.render_text(format!("{}; stepping halted", failure).as_str(), &[0.01, 0.94],
+                                                 rendering::colour::COLOUR_WARNING, 14, glyph_cache, &context, g);
                             }
 
+                            // Render replay status and timeline
+                            if let Some(replay) = &self.replay {
+                                self.render_text(replay.status_text().as_str(), &[0.01, 0.94], [0.0,1.0,0.0,1.0], 14, glyph_cache, &context, g);
+                                rectangle(rendering::colour::COLOUR_TIMELINE, [0.0, 1.0 - TIMELINE_HEIGHT, replay.progress(), TIMELINE_HEIGHT], context.transform, g);
+                            }
+
+                            // Render details of the selected body
+                            let selection_text = self.selected_body_text();
+                            self.render_text_lines(selection_text.iter().map(String::as_str).collect(),
+                            &[0.01, 0.05], 0.035, [0.0,1.0,0.0,1.0], 14, glyph_cache, &context, g);
+
                             // Draw zoom box if relevant
-                            if self.is_mouse_dragging(MOUSE_LEFT) {
+                            if self.shift_down && self.is_mouse_dragging(MOUSE_LEFT) {
                                 let rect = self.get_drag_selection(MOUSE_LEFT, &window_size).unwrap_or_else(|| panic!("No drag entities"));
                                 rectangle(rendering::colour::COLOUR_SELECTION, rect, context.transform, g);
                             }
 
+                            // Draw velocity of the body being spawned, if relevant
+                            if self.edit_mode && self.is_mouse_dragging(MOUSE_RIGHT) {
+                                let start = self.mouse_down_point[MOUSE_RIGHT].unwrap_or_else(|| panic!("No spawn location"));
+                                line_from_to(rendering::colour::COLOUR_SELECTION, 0.002,
+                                             [start[0] / window_size[0], start[1] / window_size[1]],
+                                             [self.cursor_pos[0] / window_size[0], self.cursor_pos[1] / window_size[1]], context.transform, g);
+                            }
+
                             glyph_cache.factory.encoder.flush(device);
                         });
                     },
-                    Loop::AfterRender(_ar) => {
-                        self.update_backbuffer();
-                    },
                     _ => ()
                 },
                 _ => ()
             }
         }
+
+        // Stops the physics thread, completing any output
+        self.physics = None;
     }
 
-    fn key_down(&mut self, _key: &Key) { }
+    fn key_down(&mut self, key: &Key) {
+        match key {
+            Key::LShift | Key::RShift => self.shift_down = true,
+
+            _ => ()
+        }
+    }
 
     fn key_up(&mut self, key: &Key) {
+        if self.replay.is_some() && self.replay_key_up(key) {
+            return;
+        }
+        if self.edit_mode && self.edit_key_up(key) {
+            return;
+        }
+
         match key {
+            Key::LShift | Key::RShift => self.shift_down = false,
             Key::Home => self.reset_view(),
+            Key::A => self.auto_frame.toggle(),
+            Key::G => self.show_grid = !self.show_grid,
+            Key::H => self.field = FieldQuantity::cycle(self.field),
+            Key::P => self.camera.toggle_projection(),
+            Key::C => self.cycle_camera_focus(),
+            Key::T => self.relative_trails = !self.relative_trails,
+            Key::V => self.trails.colouring = self.trails.colouring.next(),
+            Key::L => self.trails.frame = match self.trails.frame { None => Some(self.display_frame), Some(_) => None },
             Key::F12 => rendering::screenshot::display_screenshot(),
+            Key::F5 => self.send_physics(PhysicsCommand::SaveCheckpoint(format!("checkpoint-{}.json", get_current_timestamp_secs()))),
+            Key::U => self.cycle_display_units(),
+            Key::F => self.cycle_display_frame(),
+            Key::B => self.send_physics(PhysicsCommand::ReverseTime),
+            Key::Backspace => self.send_physics(PhysicsCommand::Rewind(REWIND_STEPS)),
+            Key::Space => self.send_physics(PhysicsCommand::TogglePaused),
+            Key::Right => self.send_physics(PhysicsCommand::SingleStep),
+            Key::Up => self.send_physics(PhysicsCommand::Faster),
+            Key::Down => self.send_physics(PhysicsCommand::Slower),
+            Key::Period => self.send_physics(PhysicsCommand::IncreaseTimestep),
+            Key::Comma => self.send_physics(PhysicsCommand::DecreaseTimestep),
+            Key::E if self.replay.is_none() => self.edit_mode = !self.edit_mode,
+            Key::F6 => self.save_system(format!("system-{}.json", get_current_timestamp_secs()).as_str()),
 
             _ => ()
         }
@@ -172,9 +294,173 @@ impl <TNum> Simulation<TNum>
         }
     }
 
+    // Replay controls: Space play/pause, Left/Right step a frame, Up/Down change speed, R reverse,
+    // PageUp/PageDown skip a tenth of the recording.  Returns true if the key was handled
+    fn replay_key_up(&mut self, key: &Key) -> bool {
+        let replay = match self.replay.as_mut() {
+            Some(replay) => replay,
+            None => return false
+        };
+
+        match key {
+            Key::Space => replay.toggle_playing(),
+            Key::Left => replay.step_frames(-1),
+            Key::Right => replay.step_frames(1),
+            Key::Up => replay.faster(),
+            Key::Down => replay.slower(),
+            Key::R => replay.toggle_reverse(),
+            Key::PageUp => replay.scrub_to(replay.progress() - 0.1),
+            Key::PageDown => replay.scrub_to(replay.progress() + 0.1),
+
+            _ => return false
+        }
+
+        true
+    }
+
+    // Editing controls: Delete removes the selected body, +/- change its mass and ]/[ its speed relative to its
+    // primary.  Returns true if the key was handled
+    fn edit_key_up(&mut self, key: &Key) -> bool {
+        match key {
+            Key::Delete => self.delete_selected_entity(),
+            Key::Equals => self.scale_selected_entity(EDIT_MASS_FACTOR, 1.0),
+            Key::Minus => self.scale_selected_entity(1.0 / EDIT_MASS_FACTOR, 1.0),
+            Key::RightBracket => self.scale_selected_entity(1.0, EDIT_SPEED_FACTOR),
+            Key::LeftBracket => self.scale_selected_entity(1.0, 1.0 / EDIT_SPEED_FACTOR),
+
+            _ => return false
+        }
+
+        true
+    }
+
+    // Adds a body at a window location, with velocity in the display frame defined by the drag to a second location.
+    // The new body is selected
+    fn spawn_entity(&mut self, start: &[f64; 2], end: &[f64; 2]) {
+        let screen = |p: &[f64; 2]| [p[0] / self.window_size[0], p[1] / self.window_size[1]];
+        let position = self.camera.unproject(screen(start));
+        let velocity = (&self.camera.unproject(screen(end)) - &position).scale(1.0 / (SPAWN_DRAG_STEPS * self.timestep()));
+
+        let (index, id, mass, (position, velocity)) = {
+            let state = self.nbody_system.get_current_state();
+            let id = (state.entity_count()..).map(|i| format!("body-{}", i))
+                .find(|id| state.index_of(id).is_none())
+                .unwrap_or_else(|| panic!("Cannot determine id for new body"));
+
+            (state.entity_count(), id, frames::system_centre_of_mass(&*state).0 * SPAWN_MASS_FRACTION,
+             frames::to_inertial(&*state, self.display_frame, &position, &velocity))
+        };
+
+        println!("Spawned {}", id);
+        self.send_physics(PhysicsCommand::Edit(Box::new(move |state: &mut State<TNum>| state.add_entity(
+            id, TNum::from_f64(mass), Vec3::from(*position.get_data()), Vec3::from(*velocity.get_data()), Vec3::zero()))));
+
+        self.select_entity(Some(index));
+    }
+
+    fn delete_selected_entity(&mut self) {
+        let removed = match self.selected_entity {
+            Some(ix) if ix < self.nbody_system.get_current_state().entity_count() => ix,
+            _ => return
+        };
+
+        // The display copy may lag the physics thread, so the body is found by its id when the edit is applied
+        let id = self.nbody_system.get_current_state().id(removed).clone();
+        println!("Deleted {}", id);
+        self.send_physics(PhysicsCommand::Edit(Box::new(move |state: &mut State<TNum>| {
+            if let Some(ix) = state.index_of(&id) {
+                state.remove_entity(ix);
+            }
+        })));
+
+        // Bodies after the removed body move down by one, and anything referring to the removed body is reset
+        let reindex = |ix: usize| if ix < removed { Some(ix) } else if ix > removed { Some(ix - 1) } else { None };
+
+        self.selected_entity = None;
+        self.camera_focus = match self.camera_focus {
+            Some(CameraFocus::Entity(ix)) => reindex(ix).map(CameraFocus::Entity),
+            Some(CameraFocus::Subsystem(ix)) => reindex(ix).map(CameraFocus::Subsystem),
+            focus => focus
+        };
+        let reindex_frame = |frame: ReferenceFrame| match frame {
+            ReferenceFrame::Heliocentric(ix) => reindex(ix).map(ReferenceFrame::Heliocentric).unwrap_or(ReferenceFrame::Inertial),
+            ReferenceFrame::Rotating { primary, secondary } => match (reindex(primary), reindex(secondary)) {
+                (Some(primary), Some(secondary)) => ReferenceFrame::Rotating { primary, secondary },
+                _ => ReferenceFrame::Inertial
+            },
+            frame => frame
+        };
+        self.display_frame = reindex_frame(self.display_frame);
+        self.trails.frame = self.trails.frame.map(reindex_frame);
+        if let ElementsReference::Primary(ix) = self.elements_reference {
+            self.elements_reference = ElementsReference::Primary(reindex(ix).unwrap_or(0));
+        }
+    }
+
+    // Scales the mass of the selected body, and its velocity relative to its primary
+    fn scale_selected_entity(&mut self, mass_factor: f64, speed_factor: f64) {
+        let selected = match self.selected_entity {
+            Some(ix) if ix < self.nbody_system.get_current_state().entity_count() => ix,
+            _ => return
+        };
+
+        let (id, reference_velocity) = {
+            let state = self.nbody_system.get_current_state();
+            (state.id(selected).clone(), match self.reference_primary() {
+                primary if primary != selected && primary < state.entity_count() => state.velocity(primary).to_f64(),
+                _ => frames::system_centre_of_mass(&*state).2
+            })
+        };
+
+        self.send_physics(PhysicsCommand::Edit(Box::new(move |state: &mut State<TNum>| {
+            let selected = match state.index_of(&id) {
+                Some(ix) => ix,
+                None => return
+            };
+            let mass = state.mass(selected).into_f64() * mass_factor;
+            let velocity = (&state.velocity(selected).to_f64() - &reference_velocity).scale(speed_factor) + reference_velocity;
+
+            state.masses_mut()[selected] = TNum::from_f64(mass);
+            state.velocities_mut()[selected] = Vec3::from(*velocity.get_data());
+        })));
+    }
+
+    // Writes the current state as a system definition, which may be loaded to start a new run
+    fn save_system(&self, file: &str) {
+        let id = std::path::Path::new(file).file_stem().and_then(|x| x.to_str()).unwrap_or(file).to_string();
+        let mut system = self.nbody_system.to_system(id);
+        self.renderer.borrow().get_styles().apply_to(&mut system);
+
+        system.to_file(file);
+        println!("System saved to \"{}\" at step {}", file, self.nbody_system.get_step_count());
+    }
+
+    // Displays the current replay frame, along with preceding frames as the state history for trails.  Called on each
+    // update of the event loop, which runs at a fixed rate, so that playback speed does not depend on other events
+    fn update_replay(&mut self) {
+        let replay = match self.replay.as_mut() {
+            Some(replay) => replay,
+            None => return
+        };
+
+        if replay.update() {
+            let history = replay.history::<TNum>(self.nbody_system.get_max_state_history_length());
+            self.nbody_system.load_history(history);
+        }
+    }
+
     fn mouse_click(&mut self, button_index: usize, location: &[f64; 2]) {
+        let timeline_click = location[1] >= self.window_size[1] * (1.0 - TIMELINE_HEIGHT);
+
         match button_index {
-            MOUSE_LEFT => (),
+            MOUSE_LEFT if timeline_click => {
+                let fraction = location[0] / self.window_size[0];
+                if let Some(replay) = self.replay.as_mut() { replay.scrub_to(fraction); }
+            },
+            MOUSE_LEFT => {
+                self.select_entity(self.pick_entity(location));
+            },
+            MOUSE_RIGHT if self.edit_mode => self.spawn_entity(location, location),
             MOUSE_RIGHT => (),
             _ => ()
         }
@@ -182,11 +468,18 @@ impl <TNum> Simulation<TNum>
 
     fn mouse_drag_up(&mut self, button_index: usize) {
         match button_index {
-            MOUSE_LEFT => {         // Post-selection drag
+            MOUSE_LEFT if self.shift_down => {      // Post-selection drag
                 let rect = self.get_drag_selection(MOUSE_LEFT, &self.window_size).unwrap_or_else(|| panic!("No drag entities"));
                 self.zoom_to(&rect);
                 self.update_backbuffer();
             }
+            MOUSE_LEFT => {         // Post-rotation
+                self.update_backbuffer();
+            }
+            MOUSE_RIGHT if self.edit_mode => {      // Post-spawn
+                let (start, end) = (self.mouse_down_point[MOUSE_RIGHT].unwrap_or_else(|| panic!("No spawn location")), self.cursor_pos);
+                self.spawn_entity(&start, &end);
+            },
             MOUSE_RIGHT => {        // Post-drag
                 self.update_backbuffer();
             },
@@ -199,13 +492,13 @@ impl <TNum> Simulation<TNum>
     }
 
     fn mouse_move_relative(&mut self, movement: &[f64; 2]) {
-        if self.mouse_is_down(MOUSE_RIGHT) {
-            self.pan_view(
-                self.adjust_pan_for_map_settings([
-                    -(movement[0] / self.draw_sizef[0]),
-                    -(movement[1] / self.draw_sizef[1])
-                ])
-            );
+        let drag = [movement[0] / self.window_size[0], movement[1] / self.window_size[1]];
+
+        if self.mouse_is_down(MOUSE_RIGHT) && !self.edit_mode {
+            self.pan_view([-drag[0], -drag[1]]);
+        }
+        else if self.mouse_is_down(MOUSE_LEFT) && !self.shift_down {
+            self.camera.rotate(drag);
         }
     }
 
@@ -233,11 +526,6 @@ impl <TNum> Simulation<TNum>
         }
     }
 
-    fn get_unzoomed_position(&self, pos: [f64; 2]) -> [f64; 2] {
-        [self.view_origin[0] + (pos[0] / self.zoom_level),
-         self.view_origin[1] + (pos[1] / self.zoom_level)]
-    }
-
     fn window(& self) -> Ref<PistonWindow> {
         self.window.borrow()
     }
@@ -247,8 +535,8 @@ impl <TNum> Simulation<TNum>
     }
 
     fn reset_view(&mut self) {
-        self.view_origin = [0.0, 0.0];
-        self.zoom_level = 1.0;
+        self.camera.reset();
+        self.auto_frame.enable();
     }
 
     fn render_text(&self, text: &str, pos: &[f64; 2], colour: [f32; 4], font_size: u32, glyph_cache: &mut Glyphs, context: &Context, g: &mut G2d) {
@@ -277,53 +565,260 @@ impl <TNum> Simulation<TNum>
         self.window_size = [window_size.width, window_size.height];
 
         self.canvas = image::ImageBuffer::new(self.draw_size[0], self.draw_size[1]);
+        self.camera.set_aspect(self.window_size[0] / self.window_size[1]);
     }
 
-    fn update_backbuffer(&mut self) {
-        Renderer::prepare_backbuffer(&mut self.canvas, &self.draw_size, self.zoom_level, self.view_origin);
+    // Selects a body, or clears the selection.  Clicking the selected body again follows it, and a camera
+    // already following a body moves on to the new selection
+    fn select_entity(&mut self, entity: Option<usize>) {
+        let reselected = entity.is_some() && entity == self.selected_entity;
+        self.selected_entity = entity;
+
+        match (entity, self.camera_focus) {
+            (Some(ix), Some(CameraFocus::Entity(_))) => self.camera_focus = Some(CameraFocus::Entity(ix)),
+            (Some(ix), None) if reselected => self.camera_focus = Some(CameraFocus::Entity(ix)),
+            _ => ()
+        }
     }
 
-    #[allow(unused_parens)]
-    fn perform_zoom(&mut self, scroll: [f64; 2]) {
-        let (_h_scroll, v_scroll) = (scroll[0], scroll[1]);
+    // Returns the lines of the selection panel: state of the selected body, followed by its orbital elements
+    fn selected_body_text(&self) -> Vec<String> {
+        let state = self.nbody_system.get_current_state();
+        let selected = match self.selected_entity {
+            Some(ix) if ix < state.entity_count() => ix,
+            _ => return vec![]
+        };
+
+        let units = self.nbody_system.get_units();
+        let convert = |x: f64, dimension: Dimension| units.convert(x, dimension, &self.display_units);
+        let convert_vec = |v: &Vec3<f64>, dimension: Dimension| {
+            let data = v.get_data();
+            format!("({:.5}, {:.5}, {:.5})", convert(data[0], dimension), convert(data[1], dimension), convert(data[2], dimension))
+        };
+
+        let (position, velocity) = (state.position(selected).to_f64(), state.velocity(selected).to_f64());
+        let mut text = vec![
+            format!("{} [{}]: mass = {:.5e} {}", state.id(selected), selected,
+                    convert(state.mass(selected).into_f64(), Dimension::Mass), self.display_units.mass_label()),
+            format!("Pos = {} {}", convert_vec(&position, Dimension::Length), self.display_units.length_label()),
+            format!("Vel = {} {}, speed = {:.5} {}", convert_vec(&velocity, Dimension::Velocity), self.display_units.velocity_label(),
+                    convert(velocity.length(), Dimension::Velocity), self.display_units.velocity_label())
+        ];
 
-        // Set new zoom level
-        let original_zoom_level = self.zoom_level;
-        self.zoom_level += (v_scroll * SCROLL_SCALING_FACTOR);
+        let primary = self.reference_primary();
+        if primary != selected && primary < state.entity_count() {
+            text.push(format!("Distance to {} = {:.5} {}", state.id(primary),
+                              convert((&position - &state.position(primary).to_f64()).length(), Dimension::Length),
+                              self.display_units.length_label()));
+        }
+
+        let elements = osculating::compute_body_elements(&*state, self.nbody_system.get_gravitational_constant().into_f64(),
+                                                         selected, self.elements_reference);
+        match elements {
+            None => text.push("No orbit".to_string()),
+            Some(el) => {
+                let x = &el.elements;
+                text.extend(vec![
+                    format!("a = {:.5} {}, e = {:.5}, i = {:.3}", convert(x.semi_major_axis, Dimension::Length),
+                            self.display_units.length_label(), x.eccentricity, x.inclination.to_degrees()),
+                    format!("Node = {:.3}, Peri = {:.3}, True = {:.3}, Mean = {:.3}",
+                            x.longitude_of_ascending_node.to_degrees(), x.argument_of_periapsis.to_degrees(),
+                            x.true_anomaly.to_degrees(), el.mean_anomaly.to_degrees()),
+                    format!("Period = {}", el.period.map(|p| format!("{:.5} {}", convert(p, Dimension::Time), self.display_units.time_label()))
+                        .unwrap_or_else(|| "unbound".to_string()))
+                ]);
+            }
+        }
+        text
+    }
 
-        // Limit to acceptable bounds
-        self.zoom_level = self.zoom_level.max(0.1);
+    // Body that the selection is measured against: the primary for orbital elements, or the first body in
+    // Jacobi coordinates
+    fn reference_primary(&self) -> usize {
+        match self.elements_reference {
+            ElementsReference::Primary(primary) => primary,
+            _ => 0
+        }
+    }
 
-        // Determine pan required to maintain consistent zoom target
-        let size = self.window_size;
-        let scale_change = (1.0 / self.zoom_level - 1.0 / original_zoom_level);
-        let zoom_point = [self.cursor_pos[0] / size[0], self.cursor_pos[1] / size[1]];
+    // Cycles on-screen readouts between the native units of the system and each standard unit set
+    fn cycle_display_units(&mut self) {
+        let native = self.nbody_system.get_units();
+        let options = std::iter::once(native)
+            .chain([UnitSystem::Si, UnitSystem::AuMsunYr, UnitSystem::PcMsunMyr].iter().cloned().filter(|&x| x != native))
+            .collect::<Vec<_>>();
 
-        let offset = (-(zoom_point[0] * scale_change), -(zoom_point[1] * scale_change));
-        self.view_origin[0] += offset.0;
-        self.view_origin[1] += offset.1;
+        let current = options.iter().position(|x| *x == self.display_units).unwrap_or(0);
+        self.display_units = options[(current + 1) % options.len()];
     }
 
-    fn zoom_to(&mut self, rect: &[f64; 4]) {
-        let origin = [rect[0], rect[1]];
-        let (width, height) = (rect[2] / self.zoom_level, rect[3] / self.zoom_level);
+    // Cycles the display between inertial, barycentric, primary-relative, Jacobi and rotating frames, where the
+    // rotating frame co-rotates with the primary and selected body
+    fn cycle_display_frame(&mut self) {
+        let secondary = self.selected_entity.filter(|&x| x != 0).unwrap_or(1);
+        self.display_frame = match self.display_frame {
+            ReferenceFrame::Inertial => ReferenceFrame::Barycentric,
+            ReferenceFrame::Barycentric => ReferenceFrame::Heliocentric(0),
+            ReferenceFrame::Heliocentric(_) => ReferenceFrame::Jacobi,
+            ReferenceFrame::Jacobi => ReferenceFrame::Rotating { primary: 0, secondary },
+            ReferenceFrame::Rotating { .. } => ReferenceFrame::Inertial
+        };
+    }
+
+    // Trails locked to a frame other than the display frame, or coloured other than by body, are noted in the status
+    fn trails_description(&self) -> String {
+        let frame = self.trails.frame.filter(|&x| x != self.display_frame)
+            .map(|x| format!(", trails in {} frame", x.description()))
+            .unwrap_or_default();
+        let colouring = Some(self.trails.colouring).filter(|&x| x != TrailColouring::Body)
+            .map(|x| format!(", trails by {}", x.description()))
+            .unwrap_or_default();
 
-        self.view_origin = self.get_unzoomed_position(origin);
-        self.zoom_level = (1.0 / width).min(1.0 / height);
+        format!("{}{}", frame, colouring)
     }
 
-    fn pan_view(&mut self, pan: [f64; 2]) {
-        self.view_origin = [
-            self.view_origin[0] + pan[0],
-            self.view_origin[1] + pan[1]
-        ];
+    // Cycles the camera between following the selected body, its subsystem, the system barycentre, and
+    // free movement
+    fn cycle_camera_focus(&mut self) {
+        let selected = self.selected_entity;
+        self.camera_focus = match (self.camera_focus, selected) {
+            (None, Some(ix)) => Some(CameraFocus::Entity(ix)),
+            (Some(CameraFocus::Entity(ix)), _) => Some(CameraFocus::Subsystem(ix)),
+            (Some(CameraFocus::Subsystem(_)), _) | (None, None) => Some(CameraFocus::Barycentre),
+            (Some(CameraFocus::Barycentre), _) => None
+        };
+    }
+
+    // Keeps the camera centred on its focus, in the display frame
+    fn update_camera_focus(&mut self) {
+        if let Some(focus) = self.camera_focus {
+            let state = frames::transform_state(&*self.nbody_system.get_current_state(), self.display_frame);
+            if let Some(position) = focus.position(&state) {
+                self.camera.set_target(position);
+            }
+        }
+    }
+
+    // Eases the view towards a fit of the current state, unless the user has taken control of the view
+    fn update_auto_frame(&mut self) {
+        let state = frames::transform_state(&*self.nbody_system.get_current_state(), self.display_frame);
+        self.auto_frame.update(&mut self.camera, &state, self.camera_focus.is_some());
+    }
+
+    // Returns the body drawn nearest to a window location, if the location is on or within the selection
+    // distance of its edge.  Where bodies overlap on screen, the nearest to the camera is chosen
+    fn pick_entity(&self, location: &[f64; 2]) -> Option<usize> {
+        let state = frames::transform_state(&*self.nbody_system.get_current_state(), self.display_frame);
+        let (width, height) = (self.window_size[0], self.window_size[1]);
+        let styles = self.renderer.borrow().body_styles(&state);
+
+        state.positions().iter()
+            .enumerate()
+            .filter_map(|(i, pos)| self.camera.project(&pos.to_f64()).map(|p| (i, p)))
+            .map(|(i, p)| {
+                let (dx, dy) = (p.screen[0] * width - location[0], p.screen[1] * height - location[1]);
+                let edge_distance = ((dx * dx + dy * dy).sqrt() - Renderer::body_screen_radius(styles[i].size, &p) * height).max(0.0);
+                (i, edge_distance * edge_distance, p.depth)
+            })
+            .filter(|&(_, distance_sq, _)| distance_sq <= MAX_OBJECT_SELECT_DISTANCE_SQ)
+            .min_by(|a, b| a.2.partial_cmp(&b.2).unwrap_or(std::cmp::Ordering::Equal))
+            .map(|(i, _, _)| i)
+    }
+
+    fn send_physics(&self, command: PhysicsCommand<TNum>) {
+        if let Some(physics) = &self.physics {
+            physics.send(command);
+        }
+    }
+
+    // Brings the display copy of the system up to date with states published by the physics thread
+    fn update_from_physics(&mut self) {
+        let snapshot = match self.physics.as_ref().and_then(PhysicsThread::take_snapshot) {
+            Some(snapshot) => snapshot,
+            None => return
+        };
+
+        if snapshot.reset {
+            // Archived states may have been discarded along with the history
+            self.nbody_system.load_history(snapshot.records);
+            self.nbody_system.clear_archived_history();
+            self.history_requested_at = None;
+            self.history_pending = false;
+        } else {
+            self.nbody_system.append_history(snapshot.records);
+        }
+
+        if let Some(records) = snapshot.history {
+            self.nbody_system.load_archived_history(records);
+            self.history_pending = false;
+        }
+
+        self.physics_status = Some(snapshot.status);
+        self.request_archived_trails();
+    }
+
+    // Requests the archived states covering any part of the trail length beyond the in-memory history, again each
+    // time the trail moves on by a fraction of its length
+    fn request_archived_trails(&mut self) {
+        let length = match self.trails.length {
+            Some(length) if self.archive_enabled && !self.history_pending => length,
+            _ => return
+        };
+
+        let now = self.nbody_system.get_clock().elapsed();
+        if self.history_requested_at.map(|x| (now - x).abs() < TRAIL_ARCHIVE_REFRESH * length).unwrap_or(false) {
+            return;
+        }
+
+        let oldest = match self.nbody_system.get_full_state_stamps().iter().rev().find_map(|x| *x) {
+            Some(stamp) if (now - stamp.time).abs() < length => stamp.time,
+            _ => return
+        };
+
+        // Archived states lie beyond the oldest in-memory state, on the side away from the current time
+        let (start, end) = if oldest <= now { (now - length, oldest) } else { (oldest, now + length) };
+        self.send_physics(PhysicsCommand::QueryHistory(start, end));
+        self.history_requested_at = Some(now);
+        self.history_pending = true;
+    }
+
+    fn timestep(&self) -> f64 {
+        self.physics_status.as_ref().map(|x| x.timestep).unwrap_or(SIMULATION_TIMESTEP)
+    }
+
+    fn update_backbuffer(&mut self) {
+        let options = self.render_options();
+        self.renderer.borrow_mut().prepare_backbuffer(&mut self.canvas, &self.camera, &self.nbody_system, &options);
+    }
+
+    fn render_options(&self) -> RenderOptions {
+        RenderOptions {
+            frame: self.display_frame,
+            trail_focus: if self.relative_trails { self.camera_focus } else { None },
+            selected: self.selected_entity,
+            trails: self.trails,
+            display_units: self.display_units,
+            grid: self.show_grid,
+            field: self.field
+        }
+    }
+
+    fn perform_zoom(&mut self, scroll: [f64; 2]) {
+        let (_h_scroll, v_scroll) = (scroll[0], scroll[1]);
+        let zoom_point = [self.cursor_pos[0] / self.window_size[0], self.cursor_pos[1] / self.window_size[1]];
+
+        self.camera.zoom_at((1.0 + v_scroll * SCROLL_SCALING_FACTOR).max(0.1), zoom_point);
+        self.auto_frame.disable();
+    }
+
+    fn zoom_to(&mut self, rect: &[f64; 4]) {
+        self.camera.zoom_to(rect);
+        self.auto_frame.disable();
     }
 
-    fn adjust_pan_for_map_settings(&self, pan: [f64; 2]) -> [f64; 2] {
-        [
-            (pan[0] * PAN_SCALING_FACTOR) / self.zoom_level,
-            (pan[1] * PAN_SCALING_FACTOR) / self.zoom_level
-        ]
+    fn pan_view(&mut self, pan: [f64; 2]) {
+        self.camera.pan(pan);
+        self.auto_frame.disable();
     }
 
 
@@ -336,6 +831,27 @@ impl <TNum> Simulation<TNum>
         let window_size = [window.size().width, window.size().height];
         let canvas: BackBuffer = image::ImageBuffer::new(draw_size[0], draw_size[1]);
 
+        // Recordings are displayed directly, while a live system is run on the physics thread and displayed
+        // from a copy
+        let archive_enabled = nbody_system.get_history_archive().is_some();
+        let (nbody_system, physics) = match options.replay {
+            Some(_) => (nbody_system, None),
+            None => {
+                let display_copy = nbody_system.display_copy();
+                let physics = PhysicsThread::spawn(nbody_system, PhysicsOptions {
+                    elements_output: options.elements_output,
+                    trajectory_output: options.trajectory_output,
+                    snapshot_export: options.snapshot_export,
+                    stop_at: options.stop_at,
+                    checkpoint: options.checkpoint
+                }, SIMULATION_TIMESTEP);
+
+                (display_copy, Some(physics))
+            }
+        };
+        let display_units = nbody_system.get_units();
+        let selected_entity = if nbody_system.get_current_state().entity_count() > 1 { Some(1) } else { None };
+
         Self {
             window: RefCell::new(window),
             text_manager: RefCell::new(text_manager),
@@ -345,13 +861,31 @@ impl <TNum> Simulation<TNum>
             window_size,
             canvas,
 
-            zoom_level: 1.0,
-            view_origin: [0.0, 0.0],
+            camera: Camera::new(window_size[0] / window_size[1]),
+            camera_focus: None,
+            auto_frame: AutoFrame::new(),
+            show_grid: false,
+            field: None,
+            relative_trails: false,
+            trails: options.trails,
             cursor_pos: [0.0, 0.0],
             mouse_down_point: [None; MOUSE_BUTTON_COUNT],
+            shift_down: false,
 
             nbody_system,
-            renderer: RefCell::new(Renderer::new())
+            physics,
+            physics_status: None,
+            archive_enabled,
+            history_requested_at: None,
+            history_pending: false,
+            renderer: RefCell::new(Renderer::new(options.styles)),
+
+            selected_entity,
+            elements_reference: ElementsReference::Primary(0),
+            display_units,
+            display_frame: ReferenceFrame::Inertial,
+            replay: options.replay,
+            edit_mode: false
         }
     }
 
@@ -388,5 +922,13 @@ impl <TNum> Simulation<TNum>
 
 pub struct BuildOptions {
     pub gl_version: OpenGL,
-    pub use_cache: bool
-}
\ No newline at end of file
+    pub use_cache: bool,
+    pub elements_output: Option<ElementsSeriesOptions>,
+    pub trajectory_output: Option<TrajectoryOptions>,
+    pub snapshot_export: Option<SnapshotExportOptions>,
+    pub stop_at: Option<DateTime<Utc>>,       // Simulation halts once the clock reaches this date
+    pub checkpoint: Option<CheckpointOptions>,
+    pub replay: Option<Replay>,                 // Plays back a recording in place of running the simulation
+    pub trails: TrailOptions,
+    pub styles: BodyStyles                      // Display attributes declared by the system's entities
+}
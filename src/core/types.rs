@@ -16,6 +16,11 @@ pub trait Numeric
     fn sq_root(&self) -> Self;
     fn from_f64(x: f64) -> Self;
     fn into_f64(self) -> f64;
+
+    // Exact binary representation, used to persist values without loss
+    fn type_name() -> &'static str;
+    fn to_raw_bits(self) -> u128;
+    fn from_raw_bits(bits: u128) -> Self;
 }
 
 impl Numeric for f64 {
@@ -24,6 +29,10 @@ impl Numeric for f64 {
     fn sq_root(&self) -> Self { self.sqrt() }
     fn from_f64(x: f64) -> Self { x }
     fn into_f64(self) -> f64 { self }
+
+    fn type_name() -> &'static str { "f64" }
+    fn to_raw_bits(self) -> u128 { self.to_bits() as u128 }
+    fn from_raw_bits(bits: u128) -> Self { f64::from_bits(bits as u64) }
 }
 
 impl Numeric for I16F48 {
@@ -34,6 +43,10 @@ impl Numeric for I16F48 {
     }
     fn from_f64(x: f64) -> Self { FixedI64::from_num::<f64>(x) }
     fn into_f64(self) -> f64 { self.to_num::<f64>() }
+
+    fn type_name() -> &'static str { "I16F48" }
+    fn to_raw_bits(self) -> u128 { self.to_bits() as u64 as u128 }
+    fn from_raw_bits(bits: u128) -> Self { I16F48::from_bits(bits as u64 as i64) }
 }
 
 impl Numeric for I64F64 {
@@ -44,4 +57,8 @@ impl Numeric for I64F64 {
     }
     fn from_f64(x: f64) -> Self { FixedI128::from_num::<f64>(x) }
     fn into_f64(self) -> f64 { self.to_num::<f64>() }
+
+    fn type_name() -> &'static str { "I64F64" }
+    fn to_raw_bits(self) -> u128 { self.to_bits() as u128 }
+    fn from_raw_bits(bits: u128) -> Self { I64F64::from_bits(bits as i128) }
 }
@@ -0,0 +1,37 @@
+use serde::*;
+
+// Optional display attributes of an entity.  Any not given are derived when rendering
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EntityDisplay {
+    // "#rrggbb", "#rrggbbaa" or a named colour
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub colour: Option<String>,
+
+    // Drawn radius as a fraction of the window height, before perspective scaling
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub radius: Option<f64>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub marker: Option<MarkerShape>,
+
+    // As 'colour'; trails take the body colour if not given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trail_colour: Option<String>
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MarkerShape {
+    Circle,
+    Ring,
+    Square,
+    Diamond,
+    Cross
+}
+
+impl Default for MarkerShape {
+    fn default() -> Self { MarkerShape::Circle }
+}
@@ -1,13 +1,99 @@
 use serde::*;
 use crate::core::types::Numeric;
+use super::orbit::Orbit;
+use super::display::EntityDisplay;
+use crate::units::UnitSystem;
+use crate::units::quantity::{self, Dimension};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Entity {
     pub id: String,
     pub mass: f64,
+    #[serde(default)]
     pub position: [f64; 3],
+    #[serde(default)]
     pub velocity: [f64; 3],
-    pub acceleration: [f64; 3]
+    #[serde(default)]
+    pub acceleration: [f64; 3],
+
+    // Entities with an orbit take their position & velocity from it, ignoring any Cartesian values above
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub orbit: Option<Orbit>,
+
+    // Physical radius, used only to scale the body when rendered
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub radius: Option<f64>,
+
+    // Units of the values above, where they differ from those of the owning system
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub units: Option<EntityUnits>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display: Option<EntityDisplay>
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EntityUnits {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mass: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub length: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub velocity: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acceleration: Option<String>
+}
+
+impl Entity {
+    pub fn new_cartesian(id: String, mass: f64, position: [f64; 3], velocity: [f64; 3]) -> Self {
+        Self {
+            id,
+            mass,
+            position,
+            velocity,
+            acceleration: [0.0; 3],
+            orbit: None,
+            radius: None,
+            units: None,
+            display: None
+        }
+    }
+
+    // Creates an entity placed by the given orbital elements, rather than by position and velocity
+    pub fn new_orbiting(id: String, mass: f64, orbit: Orbit) -> Self {
+        Self {
+            orbit: Some(orbit),
+            ..Self::new_cartesian(id, mass, [0.0; 3], [0.0; 3])
+        }
+    }
+
+    // Returns a copy of this entity with all values converted into the given unit system
+    pub fn in_system_units(&self, system: &UnitSystem) -> Entity {
+        let units = match &self.units {
+            None => return self.clone(),
+            Some(units) => units
+        };
+
+        let convert = |x: f64, unit: &Option<String>, dim| quantity::to_system_units(x, unit, dim, system);
+        let convert_vec = |v: [f64; 3], unit: &Option<String>, dim|
+            [convert(v[0], unit, dim), convert(v[1], unit, dim), convert(v[2], unit, dim)];
+
+        Self {
+            id: self.id.clone(),
+            mass: convert(self.mass, &units.mass, Dimension::Mass),
+            position: convert_vec(self.position, &units.length, Dimension::Length),
+            velocity: convert_vec(self.velocity, &units.velocity, Dimension::Velocity),
+            acceleration: convert_vec(self.acceleration, &units.acceleration, Dimension::Acceleration),
+            orbit: self.orbit.as_ref().map(|orbit| {
+                let mut orbit = orbit.clone();
+                orbit.semi_major_axis = convert(orbit.semi_major_axis, &units.length, Dimension::Length);
+                orbit
+            }),
+            radius: self.radius.map(|x| convert(x, &units.length, Dimension::Length)),
+            units: None,
+            display: self.display.clone()
+        }
+    }
 }
 
 impl Clone for Entity {
@@ -17,7 +103,11 @@ impl Clone for Entity {
             mass: self.mass,
             position: self.position,
             velocity: self.velocity,
-            acceleration: self.acceleration
+            acceleration: self.acceleration,
+            orbit: self.orbit.clone(),
+            radius: self.radius,
+            units: self.units.clone(),
+            display: self.display.clone()
         }
     }
 }
\ No newline at end of file
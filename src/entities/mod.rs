@@ -1,3 +1,5 @@
 pub mod entity;
 pub mod entities;
-pub mod system;
\ No newline at end of file
+pub mod orbit;
+pub mod system;
+pub mod display;
\ No newline at end of file
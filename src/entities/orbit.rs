@@ -0,0 +1,81 @@
+use serde::*;
+use crate::orbital::elements::OrbitalElements;
+use crate::orbital::kepler;
+
+// Orbit of an entity around a named primary, or around the barycentre of all previously-declared
+// entities if no primary is given.  Angles are specified in degrees, with exactly one of the mean
+// or true anomaly.  Orbits are elliptical (a > 0, e < 1) or hyperbolic (a < 0, e > 1); parabolic
+// orbits have no finite semi-major axis, and cannot be given
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Orbit {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub primary: Option<String>,
+
+    pub semi_major_axis: f64,
+    pub eccentricity: f64,
+    #[serde(default)]
+    pub inclination: f64,
+    #[serde(default)]
+    pub longitude_of_ascending_node: f64,
+    #[serde(default)]
+    pub argument_of_periapsis: f64,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mean_anomaly: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub true_anomaly: Option<f64>
+}
+
+impl Orbit {
+    pub fn to_elements(&self) -> Result<OrbitalElements, String> {
+        let (a, e) = (self.semi_major_axis, self.eccentricity);
+        if e == 1.0 {
+            return Err("parabolic orbits (e = 1) are not supported; give an eccentricity either side of 1".to_string());
+        }
+        if e.is_nan() || e < 0.0 || !a.is_finite() || (e < 1.0) != (a > 0.0) {
+            return Err(format!("inconsistent elements a = {}, e = {}; bound orbits need a > 0 and e < 1, unbound orbits a < 0 and e > 1", a, e));
+        }
+
+        let true_anomaly = match (self.mean_anomaly, self.true_anomaly) {
+            (None, Some(nu)) => nu.to_radians(),
+            (Some(m), None) => kepler::mean_to_true_anomaly(m.to_radians(), self.eccentricity),
+            _ => return Err("orbit must specify exactly one of mean_anomaly or true_anomaly".to_string())
+        };
+
+        Ok(OrbitalElements {
+            semi_major_axis: self.semi_major_axis,
+            eccentricity: self.eccentricity,
+            inclination: self.inclination.to_radians(),
+            longitude_of_ascending_node: self.longitude_of_ascending_node.to_radians(),
+            argument_of_periapsis: self.argument_of_periapsis.to_radians(),
+            true_anomaly
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn orbit(semi_major_axis: f64, eccentricity: f64) -> Orbit {
+        Orbit {
+            primary: None,
+            semi_major_axis,
+            eccentricity,
+            inclination: 0.0,
+            longitude_of_ascending_node: 0.0,
+            argument_of_periapsis: 0.0,
+            mean_anomaly: None,
+            true_anomaly: Some(30.0)
+        }
+    }
+
+    #[test]
+    fn rejects_parabolic_and_inconsistent_orbits() {
+        assert!(orbit(1.0, 1.0).to_elements().unwrap_err().contains("parabolic"));
+        assert!(orbit(1.0, 1.5).to_elements().is_err());
+        assert!(orbit(-1.0, 0.5).to_elements().is_err());
+        assert!(orbit(1.0, 0.5).to_elements().is_ok());
+        assert!(orbit(-1.0, 1.5).to_elements().is_ok());
+    }
+}
@@ -4,21 +4,75 @@ use crate::core::types::Numeric;
 use serde::Deserialize;
 use crate::state::State;
 use crate::math::vec3::Vec3;
+use crate::orbital::kepler;
+use crate::units::UnitSystem;
+use crate::util::temporal;
+use crate::frames;
+use chrono::{DateTime, Utc};
 use failure::_core::marker::PhantomData;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct System {
     id: String,
 
-    gravitational_constant: f64,
+    #[serde(default)]
+    units: UnitSystem,
+
+    // Derived from the unit system if not specified
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    gravitational_constant: Option<f64>,
     softening_constant: f64,
 
-    entities: Vec<Entity>
+    // Calendar date of the initial state, as RFC 3339 or "JD<julian date>".  Defaults to J2000.0
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    epoch: Option<String>,
+
+    // Seed of the generator that produced the system, if generated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+
+    entities: Vec<Entity>,
+
+    #[serde(skip)]
+    load_options: LoadOptions
+}
+
+// Adjustments applied to the initial state when it is generated
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LoadOptions {
+    pub centre_position: bool,      // Move the centre of mass to the origin
+    pub remove_momentum: bool       // Bring the centre of mass to rest
+}
+
+impl LoadOptions {
+    pub fn barycentric() -> Self {
+        Self { centre_position: true, remove_momentum: true }
+    }
 }
 
 impl System {
-    pub fn get_gravitational_constant(&self) -> f64 { self.gravitational_constant }
+    pub fn new(id: String, units: UnitSystem, gravitational_constant: Option<f64>, softening_constant: f64,
+               epoch: Option<String>, entities: Vec<Entity>) -> Self {
+        Self { id, units, gravitational_constant, softening_constant, epoch, seed: None, entities, load_options: LoadOptions::default() }
+    }
+
+    pub fn get_id(&self) -> &String { &self.id }
+    pub fn get_entities(&self) -> &Vec<Entity> { &self.entities }
+    pub fn get_entities_mut(&mut self) -> &mut Vec<Entity> { &mut self.entities }
+    pub fn add_entities(&mut self, entities: Vec<Entity>) { self.entities.extend(entities); }
+    pub fn get_units(&self) -> UnitSystem { self.units }
+    pub fn get_gravitational_constant(&self) -> f64 {
+        self.gravitational_constant.unwrap_or_else(|| self.units.gravitational_constant())
+    }
     pub fn get_softening_constant(&self) -> f64 { self.softening_constant }
+    pub fn get_seed(&self) -> Option<u64> { self.seed }
+    pub fn set_seed(&mut self, seed: Option<u64>) { self.seed = seed; }
+
+    pub fn get_epoch(&self) -> DateTime<Utc> {
+        self.epoch.as_ref()
+            .map(|x| temporal::parse_epoch(x).unwrap_or_else(|e| panic!("{}", e)))
+            .unwrap_or_else(temporal::j2000_epoch)
+    }
 
     pub fn from_file(file: &str) -> Self {
         let data = std::fs::read_to_string(file)
@@ -28,29 +82,107 @@ impl System {
             .expect(format!("Failed to deserialize data from ({})", file).as_str())
     }
 
+    pub fn from_file_with_options(file: &str, load_options: LoadOptions) -> Self {
+        let mut system = Self::from_file(file);
+        system.set_load_options(load_options);
+        system
+    }
+
+    pub fn set_load_options(&mut self, load_options: LoadOptions) { self.load_options = load_options; }
+
+    pub fn to_file(&self, file: &str) {
+        let data = serde_json::to_string_pretty(self)
+            .expect(format!("Failed to serialize system ({})", self.id).as_str());
+
+        std::fs::write(file, data)
+            .expect(format!("Failed to write file ({})", file).as_str());
+    }
+
     pub fn generate_state<TNum>(&self) -> State<TNum>
         where TNum: Numeric {
 
         let mut state = State::<TNum>::new();
+        let entities = self.entities_in_system_units();
 
-        self.entities.iter().for_each(|x| state.add_entity(
-            x.id.clone(),
-            TNum::from_f64(x.mass),
-            Vec3::from(x.position),
-            Vec3::from(x.velocity),
-            Vec3::from(x.acceleration)));
+        Self::resolve_cartesian_state(&entities, self.get_gravitational_constant()).into_iter()
+            .zip(entities.iter())
+            .for_each(|((pos, vel), x)| state.add_entity(
+                x.id.clone(),
+                TNum::from_f64(x.mass),
+                Vec3::from(*pos.get_data()),
+                Vec3::from(*vel.get_data()),
+                Vec3::from(x.acceleration)));
 
+        frames::shift_to_barycentre(&mut state, self.load_options.centre_position, self.load_options.remove_momentum);
         state
     }
+
+    // Determines the Cartesian position & velocity of each entity in declaration order, converting any
+    // orbital elements relative to their (previously-resolved) primary
+    fn resolve_cartesian_state(entities: &Vec<Entity>, gravitational_constant: f64) -> Vec<(Vec3<f64>, Vec3<f64>)> {
+        let mut resolved: Vec<(Vec3<f64>, Vec3<f64>)> = Vec::with_capacity(entities.len());
+
+        for entity in entities.iter() {
+            let next = match &entity.orbit {
+                None => (Vec3::from(entity.position), Vec3::from(entity.velocity)),
+                Some(orbit) => {
+                    let (primary_mass, primary_pos, primary_vel) = match &orbit.primary {
+                        Some(primary) => {
+                            let ix = entities.iter().take(resolved.len())
+                                .position(|x| &x.id == primary)
+                                .unwrap_or_else(|| panic!("Primary '{}' of entity '{}' must be declared before it", primary, entity.id));
+
+                            (entities[ix].mass, resolved[ix].0.clone(), resolved[ix].1.clone())
+                        },
+                        None => Self::barycentre_of(entities, &resolved)
+                            .unwrap_or_else(|| panic!("Entity '{}' cannot orbit the barycentre of an empty system", entity.id))
+                    };
+
+                    let mu = gravitational_constant * (primary_mass + entity.mass);
+                    let elements = orbit.to_elements()
+                        .unwrap_or_else(|e| panic!("Invalid orbit of entity '{}' ({})", entity.id, e));
+                    let (rel_pos, rel_vel) = kepler::elements_to_state(&elements, mu);
+
+                    (primary_pos + rel_pos, primary_vel + rel_vel)
+                }
+            };
+
+            resolved.push(next);
+        }
+
+        resolved
+    }
+
+    // Returns the total mass, position and velocity of the barycentre of all resolved entities
+    fn barycentre_of(entities: &Vec<Entity>, resolved: &Vec<(Vec3<f64>, Vec3<f64>)>) -> Option<(f64, Vec3<f64>, Vec3<f64>)> {
+        let total_mass: f64 = entities.iter().take(resolved.len()).map(|x| x.mass).sum();
+        if resolved.is_empty() || total_mass <= 0.0 { return None; }
+
+        let (pos, vel) = entities.iter().zip(resolved.iter())
+            .fold((Vec3::zero(), Vec3::zero()), |(pos, vel), (x, (p, v))|
+                (pos + p.scale(x.mass / total_mass), vel + v.scale(x.mass / total_mass)));
+
+        Some((total_mass, pos, vel))
+    }
+
+    fn entities_in_system_units(&self) -> Vec<Entity> {
+        self.entities.iter()
+            .map(|x| x.in_system_units(&self.units))
+            .collect()
+    }
 }
 
 impl Clone for System {
     fn clone(&self) -> Self {
         Self {
             id: self.id.clone(),
+            units: self.units,
             gravitational_constant: self.gravitational_constant.clone(),
             softening_constant: self.softening_constant.clone(),
-            entities: self.entities.clone()
+            epoch: self.epoch.clone(),
+            seed: self.seed,
+            entities: self.entities.clone(),
+            load_options: self.load_options
         }
     }
 
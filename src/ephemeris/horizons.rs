@@ -0,0 +1,290 @@
+use serde::*;
+use failure::{Error, format_err};
+
+pub const DEFAULT_HORIZONS_URL: &str = "https://ssd.jpl.nasa.gov/api/horizons.api";
+
+const START_OF_EPHEMERIS: &str = "$$SOE";
+const END_OF_EPHEMERIS: &str = "$$EOE";
+
+// Obliquity of the ecliptic at J2000.0 (IAU 1976), relating the ICRF and ecliptic frames
+const J2000_OBLIQUITY_DEG: f64 = 84381.448 / 3600.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Frame {
+    Ecliptic,       // Ecliptic and mean equinox of J2000.0
+    Equatorial      // ICRF equator and equinox
+}
+
+impl Default for Frame {
+    fn default() -> Self { Frame::Ecliptic }
+}
+
+impl Frame {
+    // Rotates a vector from this frame into the target frame
+    pub fn transform(&self, v: [f64; 3], target: Frame) -> [f64; 3] {
+        let (sin_e, cos_e) = J2000_OBLIQUITY_DEG.to_radians().sin_cos();
+        match (self, target) {
+            (Frame::Equatorial, Frame::Ecliptic) => [v[0], v[1] * cos_e + v[2] * sin_e, -v[1] * sin_e + v[2] * cos_e],
+            (Frame::Ecliptic, Frame::Equatorial) => [v[0], v[1] * cos_e - v[2] * sin_e, v[1] * sin_e + v[2] * cos_e],
+            _ => v
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VectorRecord {
+    pub julian_date: f64,
+    pub position: [f64; 3],
+    pub velocity: [f64; 3]
+}
+
+// Parsed contents of a Horizons "VECTORS" ephemeris, in either text or CSV layout
+#[derive(Debug, Clone)]
+pub struct VectorTable {
+    pub target: Option<String>,
+    pub center: Option<String>,         // Body or barycentre at the origin of the vectors
+    pub frame: Frame,
+    pub length_unit: String,
+    pub velocity_unit: String,
+    pub records: Vec<VectorRecord>
+}
+
+impl VectorTable {
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let start = text.find(START_OF_EPHEMERIS)
+            .ok_or_else(|| format_err!("No ephemeris data found (missing {})", START_OF_EPHEMERIS))?;
+        let end = text[start..].find(END_OF_EPHEMERIS).map(|x| x + start)
+            .ok_or_else(|| format_err!("Unterminated ephemeris data (missing {})", END_OF_EPHEMERIS))?;
+
+        let header = &text[..start];
+        let body = &text[start + START_OF_EPHEMERIS.len()..end];
+        let (length_unit, velocity_unit) = Self::parse_units(header)?;
+
+        let is_csv = body.lines().any(|x| x.trim().ends_with(','));
+        let records = if is_csv { Self::parse_csv_records(header, body)? } else { Self::parse_text_records(body)? };
+        if records.is_empty() {
+            return Err(format_err!("Ephemeris contains no vector records"));
+        }
+
+        Ok(Self {
+            target: Self::parse_body_name(header, "Target body name"),
+            center: Self::parse_body_name(header, "Center body name"),
+            frame: Self::parse_frame(header),
+            length_unit,
+            velocity_unit,
+            records
+        })
+    }
+
+    pub fn record_at(&self, julian_date: f64) -> Option<&VectorRecord> {
+        self.records.iter().find(|x| (x.julian_date - julian_date).abs() < 1e-8)
+    }
+
+    fn header_value<'a>(header: &'a str, key: &str) -> Option<&'a str> {
+        header.lines()
+            .map(str::trim)
+            .find(|x| x.starts_with(key))
+            .and_then(|x| x.splitn(2, ':').nth(1))
+            .map(str::trim)
+    }
+
+    fn parse_body_name(header: &str, key: &str) -> Option<String> {
+        Self::header_value(header, key)
+            .and_then(|x| x.split(|c| c == '(' || c == '{').next())
+            .map(|x| x.trim().to_string())
+    }
+
+    fn parse_frame(header: &str) -> Frame {
+        let plane = Self::header_value(header, "Coordinate system")
+            .or_else(|| Self::header_value(header, "Reference plane"))
+            .unwrap_or("");
+
+        if plane.contains("Equator") || plane.starts_with("ICRF") { Frame::Equatorial } else { Frame::Ecliptic }
+    }
+
+    fn parse_units(header: &str) -> Result<(String, String), Error> {
+        let units = Self::header_value(header, "Output units").unwrap_or("KM-S");
+        match units.split_whitespace().next().unwrap_or("").to_uppercase().as_str() {
+            "KM-S" => Ok(("km".to_string(), "km/s".to_string())),
+            "KM-D" => Ok(("km".to_string(), "km/day".to_string())),
+            "AU-D" => Ok(("au".to_string(), "au/day".to_string())),
+            other => Err(format_err!("Unsupported output units \"{}\"", other))
+        }
+    }
+
+    // Text layout: a "JD = A.D. date" line followed by "X = .. Y = .." style key/value lines
+    fn parse_text_records(body: &str) -> Result<Vec<VectorRecord>, Error> {
+        let mut records = vec![];
+        let mut current: Option<(f64, Vec<(String, f64)>)> = None;
+
+        for line in body.lines().map(str::trim).filter(|x| !x.is_empty()) {
+            if line.contains("A.D.") || line.contains("B.C.") {
+                if let Some((jd, values)) = current.take() {
+                    records.push(Self::build_record(jd, &values)?);
+                }
+
+                let jd = line.split('=').next().unwrap_or("").trim().parse::<f64>()
+                    .map_err(|_| format_err!("Invalid epoch line \"{}\"", line))?;
+                current = Some((jd, vec![]));
+            }
+            else if let Some((_, values)) = current.as_mut() {
+                let spaced = line.replace("=", " = ");
+                let tokens: Vec<&str> = spaced.split_whitespace().collect();
+                for w in tokens.windows(3).filter(|w| w[1] == "=") {
+                    let value = w[2].parse::<f64>()
+                        .map_err(|_| format_err!("Invalid value for {} (\"{}\")", w[0], w[2]))?;
+                    values.push((w[0].to_string(), value));
+                }
+            }
+        }
+
+        if let Some((jd, values)) = current {
+            records.push(Self::build_record(jd, &values)?);
+        }
+        Ok(records)
+    }
+
+    fn build_record(julian_date: f64, values: &[(String, f64)]) -> Result<VectorRecord, Error> {
+        let get = |key: &str| values.iter().find(|(k, _)| k == key).map(|(_, v)| *v)
+            .ok_or_else(|| format_err!("Missing {} component at JD {}", key, julian_date));
+
+        Ok(VectorRecord {
+            julian_date,
+            position: [get("X")?, get("Y")?, get("Z")?],
+            velocity: [get("VX")?, get("VY")?, get("VZ")?]
+        })
+    }
+
+    // CSV layout: one record per line, with columns named in the header line preceding $$SOE
+    fn parse_csv_records(header: &str, body: &str) -> Result<Vec<VectorRecord>, Error> {
+        let columns: Vec<String> = header.lines()
+            .map(str::trim)
+            .filter(|x| x.starts_with("JDTDB"))
+            .last()
+            .map(|x| x.split(',').map(|c| c.trim().to_uppercase()).collect())
+            .unwrap_or_else(|| ["JDTDB", "CALENDAR DATE (TDB)", "X", "Y", "Z", "VX", "VY", "VZ"]
+                .iter().map(|c| c.to_string()).collect());
+
+        let index_of = |name: &str| columns.iter().position(|c| c == name)
+            .ok_or_else(|| format_err!("Missing CSV column {}", name));
+        let ix = [index_of("X")?, index_of("Y")?, index_of("Z")?, index_of("VX")?, index_of("VY")?, index_of("VZ")?];
+
+        body.lines()
+            .map(str::trim)
+            .filter(|x| !x.is_empty())
+            .map(|line| {
+                let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+                let value = |i: usize| fields.get(i)
+                    .and_then(|x| x.parse::<f64>().ok())
+                    .ok_or_else(|| format_err!("Invalid CSV record \"{}\"", line));
+
+                Ok(VectorRecord {
+                    julian_date: value(0)?,
+                    position: [value(ix[0])?, value(ix[1])?, value(ix[2])?],
+                    velocity: [value(ix[3])?, value(ix[4])?, value(ix[5])?]
+                })
+            })
+            .collect()
+    }
+}
+
+// Parameters for retrieving vector tables from a Horizons-compatible web service
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FetchOptions {
+    #[serde(default = "default_base_url")]
+    pub base_url: String,
+    #[serde(default = "default_center")]
+    pub center: String,
+    pub start_time: String,
+    pub stop_time: String,
+    #[serde(default = "default_step_size")]
+    pub step_size: String
+}
+
+fn default_base_url() -> String { DEFAULT_HORIZONS_URL.to_string() }
+fn default_center() -> String { "500@0".to_string() }
+fn default_step_size() -> String { "1 d".to_string() }
+
+pub fn fetch_vector_table(command: &str, frame: Frame, options: &FetchOptions) -> Result<String, Error> {
+    let quoted = |x: &str| format!("'{}'", x);
+    let ref_plane = match frame { Frame::Ecliptic => "ECLIPTIC", Frame::Equatorial => "FRAME" };
+
+    let text = reqwest::Client::new()
+        .get(options.base_url.as_str())
+        .query(&[
+            ("format", "text".to_string()),
+            ("COMMAND", quoted(command)),
+            ("OBJ_DATA", quoted("NO")),
+            ("MAKE_EPHEM", quoted("YES")),
+            ("EPHEM_TYPE", quoted("VECTORS")),
+            ("CENTER", quoted(&options.center)),
+            ("START_TIME", quoted(&options.start_time)),
+            ("STOP_TIME", quoted(&options.stop_time)),
+            ("STEP_SIZE", quoted(&options.step_size)),
+            ("OUT_UNITS", quoted("AU-D")),
+            ("REF_PLANE", quoted(ref_plane)),
+            ("VEC_TABLE", quoted("2")),
+            ("CSV_FORMAT", quoted("NO"))
+        ])
+        .send()?
+        .error_for_status()?
+        .text()?;
+
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{VectorTable, Frame};
+
+    fn fixture(name: &str) -> String {
+        let path = format!("{}/resources/ephemeris/{}", env!("CARGO_MANIFEST_DIR"), name);
+        std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("Cannot read fixture {} ({})", path, e))
+    }
+
+    #[test]
+    fn parses_text_vector_table() {
+        let table = VectorTable::parse(&fixture("mercury-j2000.txt")).unwrap();
+
+        assert_eq!(table.target.as_deref(), Some("Mercury"));
+        assert_eq!(table.center.as_deref(), Some("Sun"));
+        assert_eq!(table.frame, Frame::Ecliptic);
+        assert_eq!((table.length_unit.as_str(), table.velocity_unit.as_str()), ("au", "au/day"));
+        assert_eq!(table.records.len(), 2);
+
+        let record = table.record_at(2451545.0).unwrap();
+        assert_eq!(record.position, [-1.478672233442572E-01, -4.466929775364947E-01, -2.313937618203309E-02]);
+        assert_eq!(record.velocity, [2.117424563261189E-02, -7.105386404267509E-03, -2.522925180072137E-03]);
+        assert_eq!(table.records[1].julian_date, 2451546.0);
+    }
+
+    #[test]
+    fn parses_csv_vector_table() {
+        let table = VectorTable::parse(&fixture("earth-j2000.csv")).unwrap();
+
+        assert_eq!(table.target.as_deref(), Some("Earth"));
+        assert_eq!(table.frame, Frame::Ecliptic);
+        assert_eq!(table.records.len(), 2);
+
+        let record = table.record_at(2451546.0).unwrap();
+        assert_eq!(record.position, [-1.942715858641990E-01, 9.639119291316950E-01, -3.974153698015700E-06]);
+        assert_eq!(record.velocity, [-1.717825470082130E-02, -3.500541006271850E-03, 1.172891700000000E-07]);
+        assert!(table.record_at(2451547.0).is_none());
+    }
+
+    #[test]
+    fn rejects_tables_without_ephemeris_markers() {
+        let text = fixture("mercury-j2000.txt");
+        assert!(VectorTable::parse(&text.replace("$$SOE", "")).is_err());
+        assert!(VectorTable::parse(&text.replace("$$EOE", "")).is_err());
+        assert!(VectorTable::parse(&text.replace("AU-D", "LY-YR")).is_err());
+    }
+
+    #[test]
+    fn frame_transform_round_trips() {
+        let v = [0.3, -0.7, 1.1];
+        let back = Frame::Ecliptic.transform(Frame::Equatorial.transform(Frame::Ecliptic.transform(v, Frame::Equatorial), Frame::Ecliptic), Frame::Ecliptic);
+        v.iter().zip(back.iter()).for_each(|(a, b)| assert!((a - b).abs() < 1e-12));
+    }
+}
@@ -0,0 +1,230 @@
+use std::path::Path;
+use serde::*;
+use failure::{Error, format_err};
+use crate::entities::entity::Entity;
+use crate::entities::system::System;
+use crate::units::UnitSystem;
+use crate::units::quantity::{self, Dimension};
+use super::horizons::{self, Frame, FetchOptions, VectorTable, VectorRecord};
+
+// Describes a system to be assembled from a set of Horizons vector tables at a common epoch.  Body
+// masses are not part of the vector output, so are supplied here
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HorizonsImport {
+    pub id: String,
+    #[serde(default)]
+    pub units: UnitSystem,
+    pub softening_constant: f64,
+    #[serde(default)]
+    pub frame: Frame,                       // Frame of the resulting system
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub epoch: Option<f64>,                 // Julian date; defaults to the first epoch common to all bodies
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fetch: Option<FetchOptions>,
+
+    pub bodies: Vec<HorizonsBody>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HorizonsBody {
+    pub id: String,
+    pub mass: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mass_unit: Option<String>,
+
+    // Source of the body's vectors: a saved vector table, or a Horizons COMMAND to fetch.  A body with
+    // neither is placed at the origin of the tables, so must be their common centre, which must be a
+    // body (e.g. CENTER "500@10" for the Sun) rather than a barycentre
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>
+}
+
+impl HorizonsImport {
+    pub fn from_file(file: &str) -> Self {
+        let data = std::fs::read_to_string(file)
+            .expect(format!("Failed to read file ({})", file).as_str());
+
+        serde_json::from_str(data.as_str())
+            .expect(format!("Failed to deserialize data from ({})", file).as_str())
+    }
+
+    // Builds the system, resolving any relative table paths against 'base_dir'
+    pub fn build_system(&self, base_dir: &Path) -> Result<System, Error> {
+        let tables = self.bodies.iter()
+            .map(|x| self.load_table(x, base_dir))
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        self.check_centers(&tables)?;
+        let epoch = self.determine_epoch(&tables)?;
+        let entities = self.bodies.iter().zip(tables.iter())
+            .map(|(body, table)| self.build_entity(body, table.as_ref(), epoch))
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        Ok(System::new(self.id.clone(), self.units, None, self.softening_constant, Some(format!("JD{}", epoch)), entities))
+    }
+
+    fn load_table(&self, body: &HorizonsBody, base_dir: &Path) -> Result<Option<VectorTable>, Error> {
+        let text = match (&body.file, &body.command) {
+            (Some(file), _) => std::fs::read_to_string(base_dir.join(file))
+                .map_err(|e| format_err!("Cannot read vector table \"{}\" ({})", file, e))?,
+
+            (None, Some(command)) => {
+                let options = self.fetch.as_ref()
+                    .ok_or_else(|| format_err!("Body \"{}\" requires fetch options to retrieve its vectors", body.id))?;
+                horizons::fetch_vector_table(command, self.frame, options)?
+            },
+
+            (None, None) => return Ok(None)
+        };
+
+        VectorTable::parse(text.as_str())
+            .map(Some)
+            .map_err(|e| format_err!("Invalid vector table for \"{}\" ({})", body.id, e))
+    }
+
+    // Tables must share a centre, and a body without a table is taken to be that centre, so it must be a body and
+    // there can be only one
+    fn check_centers(&self, tables: &[Option<VectorTable>]) -> Result<(), Error> {
+        let describe = |x: &Option<String>| x.clone().unwrap_or_else(|| "unknown".to_string());
+        let mut centers = tables.iter().filter_map(Option::as_ref).map(|x| x.center.clone());
+        let center = centers.next().ok_or_else(|| format_err!("No vector tables provided"))?;
+        if let Some(other) = centers.find(|x| *x != center) {
+            return Err(format_err!("Vector tables have different centres ({} and {})", describe(&center), describe(&other)));
+        }
+
+        let untabulated = self.bodies.iter().zip(tables.iter())
+            .filter(|(_, table)| table.is_none())
+            .map(|(body, _)| body.id.as_str())
+            .collect::<Vec<_>>();
+
+        match untabulated.as_slice() {
+            [] => Ok(()),
+            [_] if center.as_ref().map(|x| !x.to_lowercase().contains("barycenter")).unwrap_or(false) => Ok(()),
+            [id] => Err(format_err!("Body \"{}\" has no vector table, so is placed at the centre of the tables, which must be \
+                                     a body rather than a barycentre (centre is {})", id, describe(&center))),
+            ids => Err(format_err!("Only the centre of the tables may be given without a vector table ({})", ids.join(", ")))
+        }
+    }
+
+    fn determine_epoch(&self, tables: &[Option<VectorTable>]) -> Result<f64, Error> {
+        let first = tables.iter().filter_map(Option::as_ref).next()
+            .ok_or_else(|| format_err!("No vector tables provided"))?;
+
+        let candidates: Vec<f64> = match self.epoch {
+            Some(epoch) => vec![epoch],
+            None => first.records.iter().map(|x| x.julian_date).collect()
+        };
+
+        candidates.into_iter()
+            .find(|&jd| tables.iter().filter_map(Option::as_ref).all(|t| t.record_at(jd).is_some()))
+            .ok_or_else(|| format_err!("No epoch common to all vector tables"))
+    }
+
+    fn build_entity(&self, body: &HorizonsBody, table: Option<&VectorTable>, epoch: f64) -> Result<Entity, Error> {
+        let mass = quantity::to_system_units(body.mass, &body.mass_unit, Dimension::Mass, &self.units);
+        let table = match table {
+            Some(table) => table,
+            None => return Ok(Entity::new_cartesian(body.id.clone(), mass, [0.0; 3], [0.0; 3]))
+        };
+
+        let record: &VectorRecord = table.record_at(epoch)
+            .ok_or_else(|| format_err!("No vectors for \"{}\" at JD {}", body.id, epoch))?;
+
+        let convert = |v: [f64; 3], unit: &String, dim: Dimension| {
+            let v = table.frame.transform(v, self.frame);
+            let unit = Some(unit.clone());
+            [0, 1, 2].iter().fold([0.0; 3], |mut acc, &i| {
+                acc[i] = quantity::to_system_units(v[i], &unit, dim, &self.units);
+                acc
+            })
+        };
+
+        Ok(Entity::new_cartesian(
+            body.id.clone(),
+            mass,
+            convert(record.position, &table.length_unit, Dimension::Length),
+            convert(record.velocity, &table.velocity_unit, Dimension::Velocity)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use super::HorizonsImport;
+
+    const DAYS_PER_YEAR: f64 = 365.25;
+
+    fn fixture_dir() -> String {
+        format!("{}/resources/ephemeris", env!("CARGO_MANIFEST_DIR"))
+    }
+
+    #[test]
+    fn builds_system_from_fixture_tables() {
+        let import = HorizonsImport::from_file(&format!("{}/inner-planets-j2000.json", fixture_dir()));
+        let system = import.build_system(Path::new(&fixture_dir())).unwrap();
+        let entities = system.get_entities();
+
+        assert_eq!(entities.iter().map(|x| x.id.as_str()).collect::<Vec<_>>(), vec!["sol", "mercury", "earth"]);
+        assert_eq!(system.get_epoch(), crate::util::temporal::j2000_epoch());
+
+        // The body without a table is the Horizons centre, at the origin
+        assert_eq!((entities[0].mass, entities[0].position, entities[0].velocity), (1.0, [0.0; 3], [0.0; 3]));
+
+        // Tables in au and au/day are converted to au and au/yr
+        let earth = &entities[2];
+        let expected_position = [-1.771350992727098E-01, 9.672416867665306E-01, -4.085281582511366E-06];
+        earth.position.iter().zip(expected_position.iter()).for_each(|(x, e)| assert!((x - e).abs() < 1e-12));
+        assert!((earth.velocity[0] - -1.720762506872895E-02 * DAYS_PER_YEAR).abs() < 1e-12);
+        assert!((earth.mass - 3.0e-6).abs() < 1e-7);
+        assert!((entities[1].mass - 1.66e-7).abs() < 1e-9);
+    }
+
+    // Copies the fixtures to a temporary directory, with the centre of the Earth table replaced
+    fn fixtures_with_earth_center(name: &str, center: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("horizons-import-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for file in ["mercury-j2000.txt", "earth-j2000.csv"].iter() {
+            let text = std::fs::read_to_string(format!("{}/{}", fixture_dir(), file)).unwrap();
+            let text = if file.starts_with("earth") { text.replace("Center body name: Sun (10)", center) } else { text };
+            std::fs::write(dir.join(file), text).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn rejects_tables_with_different_centres() {
+        let import = HorizonsImport::from_file(&format!("{}/inner-planets-j2000.json", fixture_dir()));
+        let dir = fixtures_with_earth_center("mixed", "Center body name: Solar System Barycenter (0)");
+        let result = import.build_system(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(result.unwrap_err().to_string().contains("different centres"));
+    }
+
+    #[test]
+    fn rejects_untabulated_body_about_barycentre() {
+        let mut import = HorizonsImport::from_file(&format!("{}/inner-planets-j2000.json", fixture_dir()));
+        import.bodies.remove(1);
+        let dir = fixtures_with_earth_center("barycentre", "Center body name: Solar System Barycenter (0)");
+        let result = import.build_system(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(result.unwrap_err().to_string().contains("\"sol\" has no vector table"));
+    }
+
+    #[test]
+    fn rejects_several_untabulated_bodies() {
+        let mut import = HorizonsImport::from_file(&format!("{}/inner-planets-j2000.json", fixture_dir()));
+        import.bodies[1].file = None;
+        assert!(import.build_system(Path::new(&fixture_dir())).is_err());
+    }
+
+    #[test]
+    fn reports_missing_epoch() {
+        let mut import = HorizonsImport::from_file(&format!("{}/inner-planets-j2000.json", fixture_dir()));
+        import.epoch = Some(2451600.0);
+        assert!(import.build_system(Path::new(&fixture_dir())).is_err());
+    }
+}
@@ -0,0 +1,2 @@
+pub mod horizons;
+pub mod import;
@@ -0,0 +1,265 @@
+use crate::core::types::Numeric;
+use crate::math::vec3::Vec3;
+use crate::state::State;
+
+// Frames in which a state can be expressed for output and display.  Bodies are referenced by index
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReferenceFrame {
+    Inertial,                                           // As simulated
+    Barycentric,                                        // Centre-of-mass position and velocity at the origin
+    Heliocentric(usize),                                // Relative to a single body
+    Jacobi,                                             // Each body relative to the barycentre of all preceding bodies
+    Rotating { primary: usize, secondary: usize }       // Co-rotating with the primary-secondary line, about their barycentre
+}
+
+impl ReferenceFrame {
+    pub fn description(&self) -> String {
+        match self {
+            ReferenceFrame::Inertial => "inertial".to_string(),
+            ReferenceFrame::Barycentric => "barycentric".to_string(),
+            ReferenceFrame::Heliocentric(ix) => format!("relative to body {}", ix),
+            ReferenceFrame::Jacobi => "Jacobi".to_string(),
+            ReferenceFrame::Rotating { primary, secondary } => format!("rotating with bodies {}-{}", primary, secondary)
+        }
+    }
+}
+
+// Returns the total mass, position and velocity of the centre of mass of the given bodies
+pub fn centre_of_mass<TNum>(state: &State<TNum>, bodies: impl Iterator<Item = usize>) -> (f64, Vec3<f64>, Vec3<f64>)
+    where TNum: Numeric {
+
+    let (mass, pos, vel) = bodies.fold((0.0, Vec3::zero(), Vec3::zero()), |(m, pos, vel), i| {
+        let mi = state.mass(i).into_f64();
+        (m + mi, pos + state.position(i).to_f64().scale(mi), vel + state.velocity(i).to_f64().scale(mi))
+    });
+
+    if mass > 0.0 { (mass, pos.scale(1.0 / mass), vel.scale(1.0 / mass)) } else { (0.0, pos, vel) }
+}
+
+pub fn system_centre_of_mass<TNum>(state: &State<TNum>) -> (f64, Vec3<f64>, Vec3<f64>)
+    where TNum: Numeric {
+    centre_of_mass(state, 0..state.entity_count())
+}
+
+// Radius of the Hill sphere of a body about its primary, or zero if the body is the primary
+pub fn hill_radius<TNum>(state: &State<TNum>, body: usize, primary: usize) -> f64
+    where TNum: Numeric {
+    let primary_mass = state.mass(primary).into_f64();
+    if body == primary || primary_mass <= 0.0 { return 0.0; }
+
+    let distance = (&state.position(body).to_f64() - &state.position(primary).to_f64()).length();
+    distance * (state.mass(body).into_f64() / (3.0 * primary_mass)).cbrt()
+}
+
+// A body together with all other bodies within its Hill sphere about the primary, e.g. a planet and its moons
+pub fn subsystem<TNum>(state: &State<TNum>, body: usize, primary: usize) -> Vec<usize>
+    where TNum: Numeric {
+    let radius = hill_radius(state, body, primary);
+    let centre = state.position(body).to_f64();
+
+    (0..state.entity_count())
+        .filter(|&i| i == body || (i != primary && (&state.position(i).to_f64() - &centre).length() < radius))
+        .collect()
+}
+
+// Shifts a state in-place so that its centre of mass lies at the origin and/or is at rest
+pub fn shift_to_barycentre<TNum>(state: &mut State<TNum>, centre_position: bool, remove_momentum: bool)
+    where TNum: Numeric {
+
+    let (_, com_pos, com_vel) = system_centre_of_mass(state);
+    if centre_position {
+        state.positions_mut().iter_mut().for_each(|p| *p = to_numeric(&(&p.to_f64() - &com_pos)));
+    }
+    if remove_momentum {
+        state.velocities_mut().iter_mut().for_each(|v| *v = to_numeric(&(&v.to_f64() - &com_vel)));
+    }
+}
+
+// Converts a position and velocity given in the frame back to the inertial frame, for a body that is not
+// (yet) part of the state.  In Jacobi coordinates the body is taken to follow all existing bodies
+pub fn to_inertial<TNum>(state: &State<TNum>, frame: ReferenceFrame, pos: &Vec3<f64>, vel: &Vec3<f64>) -> (Vec3<f64>, Vec3<f64>)
+    where TNum: Numeric {
+    PointTransform::new(state, frame).to_inertial(pos, vel)
+}
+
+// Returns a copy of a state expressed in frame 'from', with its positions and velocities re-expressed in
+// frame 'to', where both frames are those defined by the reference state.  Used to show a past state as seen
+// in a frame at its own time, e.g. co-rotating with a planet, against the current state in another frame.
+// Jacobi coordinates are taken relative to the barycentre, as in to_inertial
+pub fn reexpress_state<TNum>(state: &State<TNum>, reference: &State<TNum>, from: ReferenceFrame, to: ReferenceFrame) -> State<TNum>
+    where TNum: Numeric {
+
+    let (from, to) = (PointTransform::new(reference, from), PointTransform::new(reference, to));
+    let transformed: Vec<(Vec3<f64>, Vec3<f64>)> = state.positions().iter().zip(state.velocities())
+        .map(|(pos, vel)| {
+            let (pos, vel) = from.to_inertial(&pos.to_f64(), &vel.to_f64());
+            to.to_frame(&pos, &vel)
+        })
+        .collect();
+
+    let mut result = state.clone();
+    *result.positions_mut() = transformed.iter().map(|(pos, _)| to_numeric(pos)).collect();
+    *result.velocities_mut() = transformed.iter().map(|(_, vel)| to_numeric(vel)).collect();
+
+    result
+}
+
+// Transform of single points between the inertial frame and a frame defined by a state, in which the
+// frame is treated as a translation, or a co-rotation, common to every point
+enum PointTransform {
+    Translation(Vec3<f64>, Vec3<f64>),
+    Rotation(RotatingFrame)
+}
+
+impl PointTransform {
+    fn new<TNum>(state: &State<TNum>, frame: ReferenceFrame) -> Self
+        where TNum: Numeric {
+
+        let n = state.entity_count();
+        match frame {
+            ReferenceFrame::Barycentric | ReferenceFrame::Jacobi => {
+                let (_, pos, vel) = system_centre_of_mass(state);
+                PointTransform::Translation(pos, vel)
+            },
+
+            ReferenceFrame::Heliocentric(primary) if primary < n =>
+                PointTransform::Translation(state.position(primary).to_f64(), state.velocity(primary).to_f64()),
+
+            ReferenceFrame::Rotating { primary, secondary } if primary < n && secondary < n && primary != secondary =>
+                match RotatingFrame::from_state(state, primary, secondary) {
+                    Some(rotating) => PointTransform::Rotation(rotating),
+                    None => PointTransform::Translation(Vec3::zero(), Vec3::zero())
+                },
+
+            _ => PointTransform::Translation(Vec3::zero(), Vec3::zero())
+        }
+    }
+
+    fn to_inertial(&self, pos: &Vec3<f64>, vel: &Vec3<f64>) -> (Vec3<f64>, Vec3<f64>) {
+        match self {
+            PointTransform::Translation(origin, origin_velocity) => (pos.clone() + origin.clone(), vel.clone() + origin_velocity.clone()),
+            PointTransform::Rotation(rotating) => rotating.inverse_transform(pos, vel)
+        }
+    }
+
+    fn to_frame(&self, pos: &Vec3<f64>, vel: &Vec3<f64>) -> (Vec3<f64>, Vec3<f64>) {
+        match self {
+            PointTransform::Translation(origin, origin_velocity) => (pos - origin, vel - origin_velocity),
+            PointTransform::Rotation(rotating) => rotating.transform(pos, vel)
+        }
+    }
+}
+
+// Returns a copy of the state with all positions and velocities expressed in the given frame
+pub fn transform_state<TNum>(state: &State<TNum>, frame: ReferenceFrame) -> State<TNum>
+    where TNum: Numeric {
+
+    let mut result = state.clone();
+    let n = state.entity_count();
+
+    let transformed: Vec<(Vec3<f64>, Vec3<f64>)> = match frame {
+        ReferenceFrame::Inertial => return result,
+
+        ReferenceFrame::Barycentric => {
+            let (_, pos, vel) = system_centre_of_mass(state);
+            (0..n).map(|i| relative_to(state, i, &pos, &vel)).collect()
+        },
+
+        ReferenceFrame::Heliocentric(primary) if primary < n => {
+            let (pos, vel) = (state.position(primary).to_f64(), state.velocity(primary).to_f64());
+            (0..n).map(|i| relative_to(state, i, &pos, &vel)).collect()
+        },
+
+        ReferenceFrame::Jacobi => (0..n)
+            .map(|i| {
+                let (_, pos, vel) = if i == 0 { system_centre_of_mass(state) } else { centre_of_mass(state, 0..i) };
+                if i == 0 { (pos, vel) } else { relative_to(state, i, &pos, &vel) }
+            })
+            .collect(),
+
+        ReferenceFrame::Rotating { primary, secondary } if primary < n && secondary < n && primary != secondary =>
+            match RotatingFrame::from_state(state, primary, secondary) {
+                Some(rotating) => (0..n).map(|i| rotating.transform(&state.position(i).to_f64(), &state.velocity(i).to_f64())).collect(),
+                None => return result
+            },
+
+        _ => return result
+    };
+
+    *result.positions_mut() = transformed.iter().map(|(pos, _)| to_numeric(pos)).collect();
+    *result.velocities_mut() = transformed.iter().map(|(_, vel)| to_numeric(vel)).collect();
+
+    result
+}
+
+fn relative_to<TNum>(state: &State<TNum>, index: usize, pos: &Vec3<f64>, vel: &Vec3<f64>) -> (Vec3<f64>, Vec3<f64>)
+    where TNum: Numeric {
+    (&state.position(index).to_f64() - pos, &state.velocity(index).to_f64() - vel)
+}
+
+fn to_numeric<TNum>(v: &Vec3<f64>) -> Vec3<TNum>
+    where TNum: Numeric {
+    Vec3::from(*v.get_data())
+}
+
+// Frame co-rotating with the line joining two bodies, with origin at their barycentre, x along the
+// primary-secondary line and z along their orbital angular momentum
+pub struct RotatingFrame {
+    origin: Vec3<f64>,
+    origin_velocity: Vec3<f64>,
+    axes: [Vec3<f64>; 3],
+    angular_velocity: Vec3<f64>
+}
+
+impl RotatingFrame {
+    pub fn from_state<TNum>(state: &State<TNum>, primary: usize, secondary: usize) -> Option<Self>
+        where TNum: Numeric {
+
+        let (_, origin, origin_velocity) = centre_of_mass(state, vec![primary, secondary].into_iter());
+        let r = &state.position(secondary).to_f64() - &state.position(primary).to_f64();
+        let v = &state.velocity(secondary).to_f64() - &state.velocity(primary).to_f64();
+
+        let h = r.cross(&v);
+        let (r_len, h_len) = (r.length(), h.length());
+        if r_len == 0.0 || h_len == 0.0 { return None; }
+
+        let x = r.scale(1.0 / r_len);
+        let z = h.scale(1.0 / h_len);
+        let y = z.cross(&x);
+
+        Some(Self {
+            origin,
+            origin_velocity,
+            angular_velocity: h.scale(1.0 / (r_len * r_len)),
+            axes: [x, y, z]
+        })
+    }
+
+    // Rate of rotation about the frame's z axis
+    pub fn angular_speed(&self) -> f64 {
+        self.angular_velocity.length()
+    }
+
+    pub fn transform(&self, pos: &Vec3<f64>, vel: &Vec3<f64>) -> (Vec3<f64>, Vec3<f64>) {
+        let rel_pos = pos - &self.origin;
+        let rel_vel = &(vel - &self.origin_velocity) - &self.angular_velocity.cross(&rel_pos);
+
+        (self.project(&rel_pos), self.project(&rel_vel))
+    }
+
+    pub fn inverse_transform(&self, pos: &Vec3<f64>, vel: &Vec3<f64>) -> (Vec3<f64>, Vec3<f64>) {
+        let rel_pos = self.unproject(pos);
+        let rel_vel = self.unproject(vel) + self.angular_velocity.cross(&rel_pos);
+
+        (rel_pos + self.origin.clone(), rel_vel + self.origin_velocity.clone())
+    }
+
+    fn project(&self, v: &Vec3<f64>) -> Vec3<f64> {
+        Vec3::new([self.axes[0].dot(v), self.axes[1].dot(v), self.axes[2].dot(v)])
+    }
+
+    fn unproject(&self, v: &Vec3<f64>) -> Vec3<f64> {
+        let data = v.get_data();
+        self.axes[0].scale(data[0]) + self.axes[1].scale(data[1]) + self.axes[2].scale(data[2])
+    }
+}
@@ -0,0 +1,81 @@
+use serde::*;
+use rand::rngs::StdRng;
+use crate::entities::entity::Entity;
+use crate::entities::orbit::Orbit;
+use crate::entities::system::System;
+use super::sampling;
+
+// Belt or ring of (by default massless) test particles orbiting an existing entity.  Surface density
+// follows a power law in radius, Sigma ~ r^-surface_density_index
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DebrisBelt {
+    pub primary: String,
+    pub id_prefix: String,
+    pub n: usize,
+    pub particle_mass: f64,
+
+    pub inner_radius: f64,
+    pub outer_radius: f64,
+    pub surface_density_index: f64,
+    pub eccentricity_sigma: f64,        // Rayleigh-distributed
+    pub max_eccentricity: f64,
+    pub inclination_sigma: f64          // Rayleigh-distributed, degrees
+}
+
+impl Default for DebrisBelt {
+    // Defaults approximate the main asteroid belt
+    fn default() -> Self {
+        Self {
+            primary: "star".to_string(),
+            id_prefix: "belt".to_string(),
+            n: 500,
+            particle_mass: 0.0,
+            inner_radius: 2.1,
+            outer_radius: 3.3,
+            surface_density_index: 1.5,
+            eccentricity_sigma: 0.1,
+            max_eccentricity: 0.4,
+            inclination_sigma: 5.0
+        }
+    }
+}
+
+impl DebrisBelt {
+    // Samples a semi-major axis from the power-law surface density, via the inverse cumulative distribution
+    fn sample_radius(&self, rng: &mut StdRng) -> f64 {
+        let (r0, r1) = (self.inner_radius, self.outer_radius);
+        let u = sampling::uniform(rng, 0.0, 1.0);
+        let p = 2.0 - self.surface_density_index;
+
+        if p.abs() < 1e-9 {
+            r0 * (r1 / r0).powf(u)
+        }
+        else {
+            (r0.powf(p) + u * (r1.powf(p) - r0.powf(p))).powf(1.0 / p)
+        }
+    }
+
+    pub fn generate_entities(&self, rng: &mut StdRng) -> Vec<Entity> {
+        (0..self.n)
+            .map(|i| Entity::new_orbiting(format!("{}-{}", self.id_prefix, i), self.particle_mass, Orbit {
+                primary: Some(self.primary.clone()),
+                semi_major_axis: self.sample_radius(rng),
+                eccentricity: sampling::rayleigh(rng, self.eccentricity_sigma).min(self.max_eccentricity),
+                inclination: sampling::rayleigh(rng, self.inclination_sigma),
+                longitude_of_ascending_node: sampling::uniform(rng, 0.0, 360.0),
+                argument_of_periapsis: sampling::uniform(rng, 0.0, 360.0),
+                mean_anomaly: Some(sampling::uniform(rng, 0.0, 360.0)),
+                true_anomaly: None
+            }))
+            .collect()
+    }
+
+    pub fn add_to(&self, system: &mut System, rng: &mut StdRng) {
+        if !system.get_entities().iter().any(|x| x.id == self.primary) {
+            panic!("Cannot add belt \"{}\" around unknown entity \"{}\"", self.id_prefix, self.primary);
+        }
+
+        system.add_entities(self.generate_entities(rng));
+    }
+}
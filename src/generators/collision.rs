@@ -0,0 +1,78 @@
+use serde::*;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use crate::entities::system::System;
+use super::SystemGenerator;
+use super::disc::DiscGalaxy;
+use super::sampling::{self, Particle};
+
+// Two disc galaxies on a parabolic encounter orbit with the given pericentre distance, each disc
+// tilted by its own inclination relative to the orbital plane.  Both discs must share the same units and
+// softening, which become those of the generated system
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GalaxyCollision {
+    pub primary: DiscGalaxy,
+    pub secondary: DiscGalaxy,
+    pub separation: f64,
+    pub pericentre: f64,
+    pub primary_inclination: f64,       // Degrees
+    pub secondary_inclination: f64      // Degrees
+}
+
+impl Default for GalaxyCollision {
+    fn default() -> Self {
+        Self {
+            primary: DiscGalaxy::default(),
+            secondary: DiscGalaxy::default(),
+            separation: 4.0,
+            pericentre: 0.5,
+            primary_inclination: 0.0,
+            secondary_inclination: 60.0
+        }
+    }
+}
+
+impl GalaxyCollision {
+    pub fn generate_particles(&self, rng: &mut StdRng) -> Vec<Particle> {
+        if self.secondary.units != self.primary.units || self.secondary.softening_constant != self.primary.softening_constant {
+            panic!("Colliding galaxies must share units and softening (primary {:?} with softening {}, secondary {:?} with softening {})",
+                   self.primary.units, self.primary.softening_constant, self.secondary.units, self.secondary.softening_constant);
+        }
+
+        let g = self.primary.units.gravitational_constant();
+        let (m1, m2) = (self.primary.total_mass(), self.secondary.total_mass());
+        let total = m1 + m2;
+
+        // Relative orbit: parabolic speed at the initial separation, with angular momentum set by the pericentre
+        let d = self.separation;
+        let speed = (2.0 * g * total / d).sqrt();
+        let v_perp = (2.0 * g * total * self.pericentre).sqrt() / d;
+        let v_rad = -(speed * speed - v_perp * v_perp).max(0.0).sqrt();
+        let (rel_pos, rel_vel) = ([d, 0.0, 0.0], [v_rad, v_perp, 0.0]);
+
+        let place = |particles: Vec<Particle>, inclination: f64, offset: f64| -> Vec<Particle> {
+            particles.into_iter()
+                .map(|p| Particle::new(
+                    p.mass,
+                    sampling::added(sampling::rotated(p.position, inclination.to_radians(), 0.0), sampling::scaled(rel_pos, offset)),
+                    sampling::added(sampling::rotated(p.velocity, inclination.to_radians(), 0.0), sampling::scaled(rel_vel, offset))))
+                .collect()
+        };
+
+        let mut particles = place(self.primary.generate_particles(rng), self.primary_inclination, -m2 / total);
+        particles.extend(place(self.secondary.generate_particles(rng), self.secondary_inclination, m1 / total));
+
+        sampling::shift_to_centre_of_mass(&mut particles);
+        particles
+    }
+}
+
+impl SystemGenerator for GalaxyCollision {
+    fn generate(&self, seed: u64) -> System {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let entities = super::particles_to_entities("star", self.generate_particles(&mut rng));
+
+        System::new(format!("collision-{}", seed), self.primary.units, None, self.primary.softening_constant, None, entities)
+    }
+}
@@ -0,0 +1,111 @@
+use std::f64::consts::PI;
+use serde::*;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use crate::entities::system::System;
+use crate::units::UnitSystem;
+use super::SystemGenerator;
+use super::hernquist::HernquistBulge;
+use super::sampling::{self, Particle};
+
+// Exponential disc galaxy with an optional live Hernquist bulge and central black hole.  Disc particles
+// follow the rotation curve of the enclosed (spherically-approximated) mass, plus random motions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiscGalaxy {
+    pub n_disc: usize,
+    pub disc_mass: f64,
+    pub scale_length: f64,
+    pub scale_height: f64,
+    pub cutoff_radius: f64,             // In units of the scale length
+    pub velocity_dispersion: f64,       // Fraction of local circular velocity
+
+    pub bulge: Option<HernquistBulge>,
+    pub black_hole_mass: f64,
+
+    pub softening_constant: f64,
+    pub units: UnitSystem
+}
+
+impl Default for DiscGalaxy {
+    fn default() -> Self {
+        let units = UnitSystem::Henon { total_mass_msun: 1e10, virial_radius_pc: 1e4 };
+        Self {
+            n_disc: 2000,
+            disc_mass: 0.8,
+            scale_length: 0.25,
+            scale_height: 0.02,
+            cutoff_radius: 6.0,
+            velocity_dispersion: 0.1,
+            bulge: Some(HernquistBulge { n: 500, total_mass: 0.2, scale_radius: 0.05, units, ..HernquistBulge::default() }),
+            black_hole_mass: 0.0,
+            softening_constant: 1e-4,
+            units
+        }
+    }
+}
+
+impl DiscGalaxy {
+    pub fn total_mass(&self) -> f64 {
+        self.disc_mass + self.black_hole_mass + self.bulge.as_ref().map(|b| b.total_mass).unwrap_or(0.0)
+    }
+
+    fn disc_enclosed_mass(&self, r: f64) -> f64 {
+        let x = r / self.scale_length;
+        self.disc_mass * (1.0 - (1.0 + x) * (-x).exp())
+    }
+
+    pub fn circular_velocity(&self, r: f64) -> f64 {
+        let g = self.units.gravitational_constant();
+        let enclosed = self.disc_enclosed_mass(r) + self.black_hole_mass
+            + self.bulge.as_ref().map(|b| b.enclosed_mass(r)).unwrap_or(0.0);
+
+        (g * enclosed / r.max(1e-12)).sqrt()
+    }
+
+    // Generates particles in the plane of the galaxy, with rotation about +z
+    pub fn generate_particles(&self, rng: &mut StdRng) -> Vec<Particle> {
+        let mass = self.disc_mass / self.n_disc.max(1) as f64;
+        let max_cumulative = self.disc_enclosed_mass(self.cutoff_radius * self.scale_length) / self.disc_mass;
+
+        let mut particles: Vec<Particle> = (0..self.n_disc)
+            .map(|_| {
+                let target = sampling::uniform(rng, 0.0, max_cumulative);
+                let r = sampling::invert_cumulative(target, 0.0, self.cutoff_radius * self.scale_length,
+                                                    |r| self.disc_enclosed_mass(r) / self.disc_mass);
+                let phi = sampling::uniform(rng, 0.0, 2.0 * PI);
+                let z = self.scale_height * (2.0 * sampling::uniform(rng, 1e-9, 1.0 - 1e-9) - 1.0).atanh();   // sech^2 profile
+
+                let v_c = self.circular_velocity(r);
+                let sigma = self.velocity_dispersion * v_c;
+                let (v_r, v_t, v_z) = (sampling::gaussian(rng) * sigma, v_c + sampling::gaussian(rng) * sigma,
+                                       sampling::gaussian(rng) * sigma * 0.5);
+
+                Particle::new(mass,
+                              [r * phi.cos(), r * phi.sin(), z],
+                              [v_r * phi.cos() - v_t * phi.sin(), v_r * phi.sin() + v_t * phi.cos(), v_z])
+            })
+            .collect();
+
+        if let Some(bulge) = &self.bulge {
+            let bulge = HernquistBulge { units: self.units, ..bulge.clone() };
+            particles.extend(bulge.generate_particles(rng));
+        }
+
+        if self.black_hole_mass > 0.0 {
+            particles.insert(0, Particle::new(self.black_hole_mass, [0.0; 3], [0.0; 3]));
+        }
+
+        sampling::shift_to_centre_of_mass(&mut particles);
+        particles
+    }
+}
+
+impl SystemGenerator for DiscGalaxy {
+    fn generate(&self, seed: u64) -> System {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let entities = super::particles_to_entities("star", self.generate_particles(&mut rng));
+
+        System::new(format!("disc-{}-{}", self.n_disc, seed), self.units, None, self.softening_constant, None, entities)
+    }
+}
@@ -0,0 +1,87 @@
+use serde::*;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use crate::entities::system::System;
+use crate::units::UnitSystem;
+use super::SystemGenerator;
+use super::sampling::{self, Particle};
+
+// Hernquist (1990) spherical bulge, with velocities drawn from a local Maxwellian using the isotropic
+// Jeans velocity dispersion
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HernquistBulge {
+    pub n: usize,
+    pub total_mass: f64,
+    pub scale_radius: f64,
+    pub cutoff_radius: f64,         // In units of the scale radius
+    pub softening_constant: f64,
+    pub units: UnitSystem
+}
+
+impl Default for HernquistBulge {
+    fn default() -> Self {
+        Self {
+            n: 1000,
+            total_mass: 1.0,
+            scale_radius: 1.0 / 3.0,
+            cutoff_radius: 20.0,
+            softening_constant: 1e-4,
+            units: UnitSystem::Henon { total_mass_msun: 1000.0, virial_radius_pc: 1.0 }
+        }
+    }
+}
+
+impl HernquistBulge {
+    pub fn enclosed_mass(&self, r: f64) -> f64 {
+        self.total_mass * r * r / ((r + self.scale_radius) * (r + self.scale_radius))
+    }
+
+    // One-dimensional isotropic velocity dispersion (Hernquist 1990, eq. 10)
+    fn velocity_dispersion(&self, r: f64, g: f64) -> f64 {
+        let a = self.scale_radius;
+        let x = r / a;
+        let sigma_sq = g * self.total_mass / (12.0 * a) * (
+            12.0 * x * (1.0 + x).powi(3) * ((1.0 + x) / x).ln()
+                - x / (1.0 + x) * (25.0 + 52.0 * x + 42.0 * x * x + 12.0 * x * x * x));
+
+        sigma_sq.max(0.0).sqrt()
+    }
+
+    pub fn generate_particles(&self, rng: &mut StdRng) -> Vec<Particle> {
+        let g = self.units.gravitational_constant();
+        let a = self.scale_radius;
+        let mass = self.total_mass / self.n as f64;
+
+        let mut particles: Vec<Particle> = (0..self.n)
+            .map(|_| {
+                let r = loop {
+                    let m = sampling::uniform(rng, 0.0, 1.0).sqrt();
+                    let r = a * m / (1.0 - m);
+                    if r <= self.cutoff_radius * a { break r; }
+                };
+
+                let sigma = self.velocity_dispersion(r, g);
+                let v_esc = (2.0 * g * self.total_mass / (r + a)).sqrt();
+                let v = loop {
+                    let v = [sampling::gaussian(rng) * sigma, sampling::gaussian(rng) * sigma, sampling::gaussian(rng) * sigma];
+                    if (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt() < 0.95 * v_esc { break v; }
+                };
+
+                Particle::new(mass, sampling::scaled(sampling::random_direction(rng), r), v)
+            })
+            .collect();
+
+        sampling::shift_to_centre_of_mass(&mut particles);
+        particles
+    }
+}
+
+impl SystemGenerator for HernquistBulge {
+    fn generate(&self, seed: u64) -> System {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let entities = super::particles_to_entities("star", self.generate_particles(&mut rng));
+
+        System::new(format!("hernquist-{}-{}", self.n, seed), self.units, None, self.softening_constant, None, entities)
+    }
+}
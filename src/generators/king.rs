@@ -0,0 +1,135 @@
+use std::f64::consts::PI;
+use serde::*;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use crate::entities::system::System;
+use crate::units::UnitSystem;
+use super::SystemGenerator;
+use super::sampling::{self, Particle};
+
+const KING_INTEGRATION_STEP: f64 = 1e-3;
+
+// King (1966) lowered-isothermal model with central potential depth W0
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KingModel {
+    pub n: usize,
+    pub w0: f64,
+    pub total_mass: f64,
+    pub core_radius: f64,           // King radius r0
+    pub softening_constant: f64,
+    pub units: UnitSystem
+}
+
+impl Default for KingModel {
+    fn default() -> Self {
+        Self {
+            n: 1000,
+            w0: 6.0,
+            total_mass: 1.0,
+            core_radius: 0.25,
+            softening_constant: 1e-4,
+            units: UnitSystem::Henon { total_mass_msun: 1000.0, virial_radius_pc: 1.0 }
+        }
+    }
+}
+
+// Radial profile of the dimensionless model, in units of r0 and rho0
+struct KingProfile {
+    radius: Vec<f64>,
+    potential: Vec<f64>,        // W(r)
+    mass: Vec<f64>              // Enclosed dimensionless mass
+}
+
+impl KingModel {
+    // Dimensionless density for potential depth w
+    fn density(w: f64) -> f64 {
+        if w <= 0.0 { return 0.0; }
+        w.exp() * erf(w.sqrt()) - (4.0 * w / PI).sqrt() * (1.0 + 2.0 * w / 3.0)
+    }
+
+    // Integrates Poisson's equation, r^-2 d/dr (r^2 dW/dr) = -9 rho(W) / rho(W0), out to the tidal radius
+    fn solve_profile(&self) -> KingProfile {
+        let rho0 = Self::density(self.w0);
+        let deriv = |r: f64, w: f64, dw: f64| -> (f64, f64) {
+            let d2w = -9.0 * Self::density(w) / rho0 - if r > 0.0 { 2.0 * dw / r } else { 0.0 };
+            (dw, d2w)
+        };
+
+        let h = KING_INTEGRATION_STEP;
+        let (mut r, mut w, mut dw) = (h, self.w0 - 1.5 * h * h, -3.0 * h);    // Series expansion about r = 0
+        let mut profile = KingProfile { radius: vec![0.0], potential: vec![self.w0], mass: vec![0.0] };
+
+        while w > 0.0 {
+            profile.radius.push(r);
+            profile.potential.push(w);
+            profile.mass.push(-4.0 * PI * r * r * dw / 9.0);
+
+            // Classical fourth-order Runge-Kutta step
+            let (k1w, k1d) = deriv(r, w, dw);
+            let (k2w, k2d) = deriv(r + h / 2.0, w + h * k1w / 2.0, dw + h * k1d / 2.0);
+            let (k3w, k3d) = deriv(r + h / 2.0, w + h * k2w / 2.0, dw + h * k2d / 2.0);
+            let (k4w, k4d) = deriv(r + h, w + h * k3w, dw + h * k3d);
+
+            w += h * (k1w + 2.0 * k2w + 2.0 * k3w + k4w) / 6.0;
+            dw += h * (k1d + 2.0 * k2d + 2.0 * k3d + k4d) / 6.0;
+            r += h;
+        }
+
+        profile
+    }
+
+    pub fn generate_particles(&self, rng: &mut StdRng) -> Vec<Particle> {
+        let profile = self.solve_profile();
+        let dimensionless_mass = *profile.mass.last().unwrap_or(&1.0);
+
+        // Physical scaling: rho0 from the total mass, and sigma from the definition of the King radius
+        let g = self.units.gravitational_constant();
+        let rho0 = self.total_mass / (dimensionless_mass * self.core_radius.powi(3));
+        let sigma = (4.0 * PI * g * rho0 * self.core_radius * self.core_radius / 9.0).sqrt();
+        let mass = self.total_mass / self.n as f64;
+
+        let mut particles: Vec<Particle> = (0..self.n)
+            .map(|_| {
+                let target = sampling::uniform(rng, 0.0, dimensionless_mass);
+                let ix = profile.mass.iter().position(|&m| m >= target).unwrap_or(profile.mass.len() - 1).max(1);
+
+                // Interpolate radius and potential within the enclosing profile interval
+                let (m0, m1) = (profile.mass[ix - 1], profile.mass[ix]);
+                let t = if m1 > m0 { (target - m0) / (m1 - m0) } else { 0.0 };
+                let r = profile.radius[ix - 1] + t * (profile.radius[ix] - profile.radius[ix - 1]);
+                let w = profile.potential[ix - 1] + t * (profile.potential[ix] - profile.potential[ix - 1]);
+
+                // Speed distribution p(s) ~ s^2 (exp(W - s^2/2) - 1) for s = v / sigma below escape
+                let s_max = (2.0 * w.max(0.0)).sqrt();
+                let density = |s: f64| s * s * ((w - s * s / 2.0).exp() - 1.0);
+                let bound = (0..=50).map(|i| density(s_max * i as f64 / 50.0)).fold(0.0, f64::max) * 1.1;
+                let s = if bound > 0.0 { sampling::rejection_sample(rng, 0.0, s_max, bound, density) } else { 0.0 };
+
+                Particle::new(mass,
+                              sampling::scaled(sampling::random_direction(rng), r * self.core_radius),
+                              sampling::scaled(sampling::random_direction(rng), s * sigma))
+            })
+            .collect();
+
+        sampling::shift_to_centre_of_mass(&mut particles);
+        particles
+    }
+}
+
+impl SystemGenerator for KingModel {
+    fn generate(&self, seed: u64) -> System {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let entities = super::particles_to_entities("star", self.generate_particles(&mut rng));
+
+        System::new(format!("king-w{}-{}-{}", self.w0, self.n, seed), self.units, None, self.softening_constant, None, entities)
+    }
+}
+
+// Error function, via the Abramowitz & Stegun 7.1.26 approximation
+fn erf(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.3275911 * x.abs());
+    let poly = t * (0.254829592 + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    let y = 1.0 - poly * (-x * x).exp();
+    if x >= 0.0 { y } else { -y }
+}
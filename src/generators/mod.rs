@@ -0,0 +1,128 @@
+pub mod sampling;
+pub mod plummer;
+pub mod king;
+pub mod hernquist;
+pub mod disc;
+pub mod collision;
+pub mod planetary;
+pub mod belt;
+
+use failure::{Error, format_err};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use crate::core::types::Numeric;
+use crate::entities::entity::Entity;
+use crate::entities::system::System;
+use crate::state::State;
+use self::sampling::Particle;
+
+// Procedural source of initial conditions.  Generation is deterministic for a given seed
+pub trait SystemGenerator {
+    fn generate(&self, seed: u64) -> System;
+
+    fn generate_state<TNum>(&self, seed: u64) -> State<TNum>
+        where TNum: Numeric, Self: Sized {
+        self.generate(seed).generate_state()
+    }
+}
+
+pub const GENERATOR_NAMES: [&str; 6] = ["plummer", "king", "hernquist", "disc", "collision", "planetary"];
+
+// Constructs a named generator from JSON parameters, with unspecified parameters taking their defaults
+pub fn from_name(name: &str, params: serde_json::Value) -> Result<Box<dyn SystemGenerator>, Error> {
+    Ok(match name {
+        "plummer" => Box::new(serde_json::from_value::<plummer::PlummerSphere>(params)?),
+        "king" => Box::new(serde_json::from_value::<king::KingModel>(params)?),
+        "hernquist" => Box::new(serde_json::from_value::<hernquist::HernquistBulge>(params)?),
+        "disc" => Box::new(serde_json::from_value::<disc::DiscGalaxy>(params)?),
+        "collision" => Box::new(serde_json::from_value::<collision::GalaxyCollision>(params)?),
+        "planetary" => Box::new(serde_json::from_value::<planetary::PlanetarySystem>(params)?),
+        _ => return Err(format_err!("Unknown generator \"{}\" (expected one of {:?})", name, GENERATOR_NAMES))
+    })
+}
+
+// Adds a belt, described by JSON parameters, to an existing system.  Generation is deterministic for a given seed
+pub fn add_belt(system: &mut System, seed: u64, params: serde_json::Value) -> Result<(), Error> {
+    let belt = serde_json::from_value::<belt::DebrisBelt>(params)?;
+    belt.add_to(system, &mut StdRng::seed_from_u64(seed));
+    Ok(())
+}
+
+pub fn particles_to_entities(prefix: &str, particles: Vec<Particle>) -> Vec<Entity> {
+    particles.into_iter()
+        .enumerate()
+        .map(|(i, p)| p.into_entity(format!("{}-{}", prefix, i)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // Small instances of each generator, with their expected total mass and whether they are generated
+    // in their centre-of-mass frame
+    fn test_generators() -> Vec<(&'static str, serde_json::Value, f64, bool)> {
+        let disc_mass = disc::DiscGalaxy::default().total_mass();
+        vec![
+            ("plummer", json!({ "n": 64 }), plummer::PlummerSphere::default().total_mass, true),
+            ("king", json!({ "n": 64 }), king::KingModel::default().total_mass, true),
+            ("hernquist", json!({ "n": 64 }), hernquist::HernquistBulge::default().total_mass, true),
+            ("disc", json!({ "n_disc": 64 }), disc_mass, true),
+            ("collision", json!({ "primary": { "n_disc": 32 }, "secondary": { "n_disc": 32 } }), 2.0 * disc_mass, true),
+            ("planetary", json!({ "n_planets": 3 }), planetary::PlanetarySystem::default().star_mass, false)
+        ]
+    }
+
+    #[test]
+    fn generation_is_deterministic_for_a_seed() {
+        for (name, params, _, _) in test_generators() {
+            let generator = from_name(name, params).unwrap();
+            let (first, second) = (generator.generate(42), generator.generate(42));
+
+            assert_eq!(serde_json::to_string(&first).unwrap(), serde_json::to_string(&second).unwrap(), "{}", name);
+            assert_ne!(serde_json::to_string(&first).unwrap(), serde_json::to_string(&generator.generate(43)).unwrap(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn generated_systems_have_expected_mass_and_centre() {
+        for (name, params, expected_mass, centred) in test_generators() {
+            let state = from_name(name, params).unwrap().generate(1).generate_state::<f64>();
+            let total_mass: f64 = (0..state.entity_count()).map(|i| state.mass(i)).sum();
+
+            if centred {
+                assert!((total_mass - expected_mass).abs() < 1e-9 * expected_mass, "{}: mass {} != {}", name, total_mass, expected_mass);
+            } else {
+                // Planetary systems also hold the planet masses
+                assert!(total_mass >= expected_mass && total_mass < 1.01 * expected_mass, "{}: mass {}", name, total_mass);
+            }
+
+            if centred {
+                let extent = (0..state.entity_count()).map(|i| state.position(i).length()).fold(0.0, f64::max);
+                for axis in 0..3 {
+                    let centre: f64 = (0..state.entity_count()).map(|i| state.mass(i) * state.position(i).get_data()[axis]).sum::<f64>() / total_mass;
+                    assert!(centre.abs() < 1e-9 * extent, "{}: centre of mass offset {} on axis {}", name, centre, axis);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn belt_is_added_around_the_chosen_entity() {
+        let mut system = from_name("planetary", json!({ "n_planets": 2 })).unwrap().generate(1);
+        let planet = system.get_entities()[1].id.clone();
+        add_belt(&mut system, 5, json!({ "primary": planet, "n": 20, "inner_radius": 0.01, "outer_radius": 0.02 })).unwrap();
+
+        let belt: Vec<_> = system.get_entities().iter().filter(|x| x.id.starts_with("belt-")).collect();
+        assert_eq!(system.get_entities().len(), 23);
+        assert_eq!(belt.len(), 20);
+        assert!(belt.iter().all(|x| x.orbit.as_ref().and_then(|o| o.primary.as_ref()) == Some(&planet)));
+    }
+
+    #[test]
+    #[should_panic(expected = "must share units and softening")]
+    fn collision_rejects_mismatched_units() {
+        from_name("collision", json!({ "primary": { "n_disc": 8 }, "secondary": { "n_disc": 8, "units": "si" } })).unwrap().generate(1);
+    }
+}
@@ -0,0 +1,105 @@
+use serde::*;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use crate::entities::entity::Entity;
+use crate::entities::orbit::Orbit;
+use crate::entities::system::System;
+use crate::units::UnitSystem;
+use super::SystemGenerator;
+use super::belt::DebrisBelt;
+use super::sampling;
+
+// A star with planets drawn from log-uniform mass and spacing distributions.  Successive planets are
+// separated by a random number of mutual Hill radii, so the system starts dynamically stable
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlanetarySystem {
+    pub star_id: String,
+    pub star_mass: f64,
+
+    pub n_planets: usize,
+    pub min_planet_mass: f64,
+    pub max_planet_mass: f64,
+    pub inner_semi_major_axis: f64,
+    pub min_hill_spacing: f64,          // In mutual Hill radii
+    pub max_hill_spacing: f64,
+    pub eccentricity_sigma: f64,        // Rayleigh-distributed
+    pub inclination_sigma: f64,         // Rayleigh-distributed, degrees
+
+    pub belts: Vec<DebrisBelt>,
+
+    pub softening_constant: f64,
+    pub units: UnitSystem
+}
+
+impl Default for PlanetarySystem {
+    fn default() -> Self {
+        Self {
+            star_id: "star".to_string(),
+            star_mass: 1.0,
+            n_planets: 5,
+            min_planet_mass: 1.5e-7,        // ~Mercury
+            max_planet_mass: 1e-3,          // ~Jupiter
+            inner_semi_major_axis: 0.4,
+            min_hill_spacing: 10.0,
+            max_hill_spacing: 20.0,
+            eccentricity_sigma: 0.02,
+            inclination_sigma: 1.0,
+            belts: vec![],
+            softening_constant: 1e-8,
+            units: UnitSystem::AuMsunYr
+        }
+    }
+}
+
+impl PlanetarySystem {
+    pub fn generate_planets(&self, rng: &mut StdRng) -> Vec<Entity> {
+        let masses: Vec<f64> = (0..self.n_planets)
+            .map(|_| (sampling::uniform(rng, self.min_planet_mass.ln(), self.max_planet_mass.ln())).exp())
+            .collect();
+
+        let mut a = self.inner_semi_major_axis;
+        masses.iter().enumerate()
+            .map(|(i, &mass)| {
+                if i > 0 {
+                    a = self.next_semi_major_axis(a, masses[i - 1], mass,
+                                                  sampling::uniform(rng, self.min_hill_spacing, self.max_hill_spacing));
+                }
+
+                Entity::new_orbiting(format!("planet-{}", i), mass, Orbit {
+                    primary: Some(self.star_id.clone()),
+                    semi_major_axis: a,
+                    eccentricity: sampling::rayleigh(rng, self.eccentricity_sigma).min(0.9),
+                    inclination: sampling::rayleigh(rng, self.inclination_sigma),
+                    longitude_of_ascending_node: sampling::uniform(rng, 0.0, 360.0),
+                    argument_of_periapsis: sampling::uniform(rng, 0.0, 360.0),
+                    mean_anomaly: Some(sampling::uniform(rng, 0.0, 360.0)),
+                    true_anomaly: None
+                })
+            })
+            .collect()
+    }
+
+    // Solves a' - a = spacing * R_H for a', with mutual Hill radius R_H = ((m + m') / 3M)^(1/3) (a + a') / 2
+    fn next_semi_major_axis(&self, a: f64, mass: f64, next_mass: f64, spacing: f64) -> f64 {
+        let k = spacing * ((mass + next_mass) / (3.0 * self.star_mass)).powf(1.0 / 3.0) / 2.0;
+        if k >= 1.0 { panic!("Hill spacing of {} is too large for planet masses {} and {}", spacing, mass, next_mass); }
+
+        a * (1.0 + k) / (1.0 - k)
+    }
+}
+
+impl SystemGenerator for PlanetarySystem {
+    fn generate(&self, seed: u64) -> System {
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        let mut entities = vec![Entity::new_cartesian(self.star_id.clone(), self.star_mass, [0.0; 3], [0.0; 3])];
+        entities.extend(self.generate_planets(&mut rng));
+
+        let mut system = System::new(format!("planetary-{}-{}", self.n_planets, seed), self.units, None,
+                                     self.softening_constant, None, entities);
+
+        self.belts.iter().for_each(|belt| belt.add_to(&mut system, &mut rng));
+        system
+    }
+}
@@ -0,0 +1,71 @@
+use serde::*;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use crate::entities::system::System;
+use crate::units::UnitSystem;
+use super::SystemGenerator;
+use super::sampling::{self, Particle};
+
+// Plummer sphere, sampled following Aarseth, Henon & Wielen (1974)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlummerSphere {
+    pub n: usize,
+    pub total_mass: f64,
+    pub scale_radius: f64,
+    pub cutoff_radius: f64,         // In units of the scale radius
+    pub softening_constant: f64,
+    pub units: UnitSystem
+}
+
+impl Default for PlummerSphere {
+    // Defaults correspond to standard Henon units, with G = M = 1 and E = -1/4
+    fn default() -> Self {
+        Self {
+            n: 1000,
+            total_mass: 1.0,
+            scale_radius: 3.0 * std::f64::consts::PI / 16.0,
+            cutoff_radius: 10.0,
+            softening_constant: 1e-4,
+            units: UnitSystem::Henon { total_mass_msun: 1000.0, virial_radius_pc: 1.0 }
+        }
+    }
+}
+
+impl PlummerSphere {
+    pub fn generate_particles(&self, rng: &mut StdRng) -> Vec<Particle> {
+        let g = self.units.gravitational_constant();
+        let a = self.scale_radius;
+        let mass = self.total_mass / self.n as f64;
+
+        let mut particles: Vec<Particle> = (0..self.n)
+            .map(|_| {
+                let r = loop {
+                    let x = sampling::uniform(rng, 1e-10, 1.0);
+                    let r = a / (x.powf(-2.0 / 3.0) - 1.0).sqrt();
+                    if r <= self.cutoff_radius * a { break r; }
+                };
+
+                // Speed as a fraction q of local escape velocity, with g(q) = q^2 (1 - q^2)^3.5
+                let q = sampling::rejection_sample(rng, 0.0, 1.0, 0.1, |q| q * q * (1.0 - q * q).powf(3.5));
+                let v_esc = (2.0 * g * self.total_mass / (r * r + a * a).sqrt()).sqrt();
+
+                Particle::new(mass,
+                              sampling::scaled(sampling::random_direction(rng), r),
+                              sampling::scaled(sampling::random_direction(rng), q * v_esc))
+            })
+            .collect();
+
+        sampling::shift_to_centre_of_mass(&mut particles);
+        particles
+    }
+}
+
+impl SystemGenerator for PlummerSphere {
+    fn generate(&self, seed: u64) -> System {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let entities = super::particles_to_entities("star", self.generate_particles(&mut rng));
+
+        System::new(format!("plummer-{}-{}", self.n, seed), self.units, None, self.softening_constant, None, entities)
+    }
+}
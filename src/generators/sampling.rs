@@ -0,0 +1,95 @@
+use std::f64::consts::PI;
+use rand::Rng;
+use rand::rngs::StdRng;
+use crate::entities::entity::Entity;
+
+// Intermediate body representation used while generating a system
+#[derive(Debug, Clone)]
+pub struct Particle {
+    pub mass: f64,
+    pub position: [f64; 3],
+    pub velocity: [f64; 3]
+}
+
+impl Particle {
+    pub fn new(mass: f64, position: [f64; 3], velocity: [f64; 3]) -> Self {
+        Self { mass, position, velocity }
+    }
+
+    pub fn into_entity(self, id: String) -> Entity {
+        Entity::new_cartesian(id, self.mass, self.position, self.velocity)
+    }
+}
+
+pub fn uniform(rng: &mut StdRng, min: f64, max: f64) -> f64 {
+    min + (max - min) * rng.gen::<f64>()
+}
+
+// Standard normal deviate, via the Box-Muller transform
+pub fn gaussian(rng: &mut StdRng) -> f64 {
+    let u1 = 1.0 - rng.gen::<f64>();
+    let u2 = rng.gen::<f64>();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+}
+
+pub fn rayleigh(rng: &mut StdRng, sigma: f64) -> f64 {
+    sigma * (-2.0 * (1.0 - rng.gen::<f64>()).ln()).sqrt()
+}
+
+pub fn random_direction(rng: &mut StdRng) -> [f64; 3] {
+    let z = uniform(rng, -1.0, 1.0);
+    let phi = uniform(rng, 0.0, 2.0 * PI);
+    let r = (1.0 - z * z).sqrt();
+    [r * phi.cos(), r * phi.sin(), z]
+}
+
+pub fn scaled(v: [f64; 3], s: f64) -> [f64; 3] {
+    [v[0] * s, v[1] * s, v[2] * s]
+}
+
+pub fn added(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+// Rotation about the x axis followed by the z axis, used to orient discs and orbits
+pub fn rotated(v: [f64; 3], inclination: f64, azimuth: f64) -> [f64; 3] {
+    let (sin_i, cos_i) = inclination.sin_cos();
+    let (sin_a, cos_a) = azimuth.sin_cos();
+    let (x, y, z) = (v[0], v[1] * cos_i - v[2] * sin_i, v[1] * sin_i + v[2] * cos_i);
+    [x * cos_a - y * sin_a, x * sin_a + y * cos_a, z]
+}
+
+// Moves a set of particles into their centre-of-mass frame, removing any net position and momentum offset
+pub fn shift_to_centre_of_mass(particles: &mut [Particle]) {
+    let total_mass: f64 = particles.iter().map(|p| p.mass).sum();
+    if total_mass <= 0.0 { return; }
+
+    let (pos, vel) = particles.iter().fold(([0.0; 3], [0.0; 3]), |(pos, vel), p|
+        (added(pos, scaled(p.position, p.mass / total_mass)), added(vel, scaled(p.velocity, p.mass / total_mass))));
+
+    particles.iter_mut().for_each(|p| {
+        p.position = added(p.position, scaled(pos, -1.0));
+        p.velocity = added(p.velocity, scaled(vel, -1.0));
+    });
+}
+
+// Rejection sampling of x in [min, max] against an unnormalised density bounded above by 'bound'
+pub fn rejection_sample<F>(rng: &mut StdRng, min: f64, max: f64, bound: f64, density: F) -> f64
+    where F: Fn(f64) -> f64 {
+    loop {
+        let x = uniform(rng, min, max);
+        if uniform(rng, 0.0, bound) <= density(x) { return x; }
+    }
+}
+
+// Inverts a monotonic cumulative function on [min, max] by bisection
+pub fn invert_cumulative<F>(target: f64, min: f64, max: f64, cumulative: F) -> f64
+    where F: Fn(f64) -> f64 {
+    let (mut lo, mut hi) = (min, max);
+    for _ in 0..100 {
+        let mid = 0.5 * (lo + hi);
+        if cumulative(mid) < target { lo = mid; } else { hi = mid; }
+    }
+
+    0.5 * (lo + hi)
+}
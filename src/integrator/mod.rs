@@ -4,21 +4,81 @@ use crate::state::State;
 use core::marker::PhantomData;
 use crate::math::vec3::Vec3;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IntegrationScheme {
+    Euler,          // First-order, using the accelerations of the preceding state
+    Leapfrog        // Kick-drift-kick; symplectic and time-reversible, so the system may be run backwards
+}
+
+impl IntegrationScheme {
+    pub fn parse(scheme: &str) -> Result<Self, String> {
+        match scheme.trim().to_lowercase().as_str() {
+            "euler" => Ok(IntegrationScheme::Euler),
+            "leapfrog" => Ok(IntegrationScheme::Leapfrog),
+            other => Err(format!("Unknown integration scheme \"{}\"", other))
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            IntegrationScheme::Euler => "euler",
+            IntegrationScheme::Leapfrog => "leapfrog"
+        }
+    }
+
+    pub fn is_time_reversible(&self) -> bool {
+        *self == IntegrationScheme::Leapfrog
+    }
+}
+
 pub struct Integrator<TNum>
     where TNum: Numeric {
+    scheme: IntegrationScheme,
     _type_marker: PhantomData<TNum>
 }
 
 impl <TNum> Integrator<TNum>
     where TNum: Numeric + Add<Output=TNum> + Mul<Output=TNum> + AddAssign {
 
-    pub fn new() -> Self { Self { _type_marker: PhantomData } }
+    pub fn new() -> Self { Self::with_scheme(IntegrationScheme::Euler) }
+
+    pub fn with_scheme(scheme: IntegrationScheme) -> Self { Self { scheme, _type_marker: PhantomData } }
+
+    pub fn scheme(&self) -> IntegrationScheme { self.scheme }
 
     pub fn integrate(&self, dt: TNum, state: &State<TNum>, result: &mut State<TNum>) {
         self.integrate_acceleration(dt, state, result);
         self.integrate_velocity(dt, state, result);
     }
 
+    // First half of a leapfrog step: half-step velocity kick using the accelerations of 'state', followed by
+    // a full-step position drift.  The caller must then evaluate accelerations at the new positions and apply
+    // 'leapfrog_kick' to complete the step
+    pub fn leapfrog_kick_drift(&self, dt: TNum, state: &State<TNum>, result: &mut State<TNum>) {
+        let half_dt = dt * TNum::from_f64(0.5);
+        result.velocities_mut().iter_mut()
+            .zip(state.velocities().iter().zip(state.accelerations()))
+            .for_each(|(new_vel, (vel, acc))|
+                *new_vel = Vec3::add_vec(vel, &acc.scale(half_dt)));
+
+        let drifted: Vec<Vec3<TNum>> = state.positions().iter()
+            .zip(result.velocities())
+            .map(|(pos, vel)| Vec3::add_vec(pos, &vel.scale(dt)))
+            .collect();
+
+        *result.positions_mut() = drifted;
+    }
+
+    pub fn leapfrog_kick(&self, dt: TNum, result: &mut State<TNum>) {
+        let half_dt = dt * TNum::from_f64(0.5);
+        let kicked: Vec<Vec3<TNum>> = result.velocities().iter()
+            .zip(result.accelerations())
+            .map(|(vel, acc)| Vec3::add_vec(vel, &acc.scale(half_dt)))
+            .collect();
+
+        *result.velocities_mut() = kicked;
+    }
+
     fn integrate_acceleration(&self, dt: TNum, state: &State<TNum>, result: &mut State<TNum>) {
         result.velocities_mut().iter_mut()
             .zip(state.velocities().iter().zip(state.accelerations()))
@@ -0,0 +1,54 @@
+use std::convert::TryFrom;
+use std::io::{Write, Error, ErrorKind};
+use super::format::{SnapshotEncoder, element_values};
+use super::snapshot::{Snapshot, FieldSet, OutputField};
+use super::export::SnapshotMetadata;
+
+pub const BINARY_MAGIC: &[u8; 4] = b"NBTJ";
+pub const BINARY_VERSION: u32 = 2;
+
+// Compact little-endian layout:
+//   header:   magic "NBTJ", version u32, field mask u8, then from version 2 the metadata (u32 length + JSON bytes)
+//   snapshot: step u64, time f64, body count u32, then per body (each only if the field is selected):
+//             id (u16 length + UTF-8 bytes), mass f64, position 3 x f64, velocity 3 x f64, elements 8 x f64
+pub struct BinaryEncoder;
+
+impl SnapshotEncoder for BinaryEncoder {
+    fn write_header(&self, out: &mut dyn Write, fields: &FieldSet, metadata: &SnapshotMetadata) -> std::io::Result<()> {
+        let metadata = metadata.to_json();
+        out.write_all(BINARY_MAGIC)?;
+        out.write_all(&BINARY_VERSION.to_le_bytes())?;
+        out.write_all(&[fields.to_mask()])?;
+        out.write_all(&(metadata.len() as u32).to_le_bytes())?;
+        out.write_all(metadata.as_bytes())
+    }
+
+    fn write_snapshot(&self, out: &mut dyn Write, snapshot: &Snapshot, fields: &FieldSet) -> std::io::Result<()> {
+        out.write_all(&(snapshot.step as u64).to_le_bytes())?;
+        out.write_all(&snapshot.time.to_bits().to_le_bytes())?;
+        out.write_all(&(snapshot.bodies.len() as u32).to_le_bytes())?;
+
+        let write_f64s = |out: &mut dyn Write, values: &[f64]| -> std::io::Result<()> {
+            values.iter().try_for_each(|x| out.write_all(&x.to_bits().to_le_bytes()))
+        };
+
+        for body in snapshot.bodies.iter() {
+            if fields.contains(OutputField::Id) {
+                out.write_all(&id_length(&body.id)?.to_le_bytes())?;
+                out.write_all(body.id.as_bytes())?;
+            }
+            if fields.contains(OutputField::Mass) { write_f64s(out, &[body.mass])?; }
+            if fields.contains(OutputField::Position) { write_f64s(out, &body.position)?; }
+            if fields.contains(OutputField::Velocity) { write_f64s(out, &body.velocity)?; }
+            if fields.contains(OutputField::Elements) { write_f64s(out, &element_values(&body.elements))?; }
+        }
+
+        Ok(())
+    }
+}
+
+// Length prefix of an id in binary layouts, which cannot exceed a u16
+pub fn id_length(id: &str) -> std::io::Result<u16> {
+    u16::try_from(id.len()).map_err(|_| Error::new(ErrorKind::InvalidInput,
+        format!("Id \"{}...\" is longer than {} bytes", id.chars().take(20).collect::<String>(), u16::MAX)))
+}
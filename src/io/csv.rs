@@ -0,0 +1,105 @@
+use std::io::Write;
+use super::format::{SnapshotEncoder, ELEMENT_NAMES, element_values};
+use super::snapshot::{Snapshot, FieldSet, OutputField};
+use super::export::SnapshotMetadata;
+
+pub const METADATA_PREFIX: &str = "# ";
+
+// One row per body per snapshot, after a comment line holding the metadata as JSON and a row of column names.
+// Step is always written, so that rows can be grouped into snapshots
+pub struct CsvEncoder;
+
+impl SnapshotEncoder for CsvEncoder {
+    fn write_header(&self, out: &mut dyn Write, fields: &FieldSet, metadata: &SnapshotMetadata) -> std::io::Result<()> {
+        writeln!(out, "{}{}", METADATA_PREFIX, metadata.to_json())?;
+
+        let mut columns = vec!["step".to_string()];
+        for field in fields.fields() {
+            match field {
+                OutputField::Id => columns.push("id".to_string()),
+                OutputField::Time => columns.push("time".to_string()),
+                OutputField::Mass => columns.push("mass".to_string()),
+                OutputField::Position => columns.extend(vec!["x".to_string(), "y".to_string(), "z".to_string()]),
+                OutputField::Velocity => columns.extend(vec!["vx".to_string(), "vy".to_string(), "vz".to_string()]),
+                OutputField::Elements => columns.extend(ELEMENT_NAMES.iter().map(|x| x.to_string()))
+            }
+        }
+
+        writeln!(out, "{}", columns.join(","))
+    }
+
+    fn write_snapshot(&self, out: &mut dyn Write, snapshot: &Snapshot, fields: &FieldSet) -> std::io::Result<()> {
+        for body in snapshot.bodies.iter() {
+            let mut values = vec![snapshot.step.to_string()];
+            for field in fields.fields() {
+                match field {
+                    OutputField::Id => values.push(quote_field(&body.id)),
+                    OutputField::Time => values.push(snapshot.time.to_string()),
+                    OutputField::Mass => values.push(body.mass.to_string()),
+                    OutputField::Position => values.extend(body.position.iter().map(f64::to_string)),
+                    OutputField::Velocity => values.extend(body.velocity.iter().map(f64::to_string)),
+                    OutputField::Elements => values.extend(element_values(&body.elements).iter()
+                        .map(|x| if x.is_nan() { String::new() } else { x.to_string() }))
+                }
+            }
+
+            writeln!(out, "{}", values.join(","))?;
+        }
+
+        Ok(())
+    }
+}
+
+// Quotes a field holding a separator, quote or line break, doubling any quotes within it
+pub fn quote_field(value: &str) -> String {
+    if value.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// Whether a record is complete, rather than ending within a quoted field that continues on the next line
+pub fn is_complete_record(record: &str) -> bool {
+    record.matches('"').count() % 2 == 0
+}
+
+// Splits a record into its fields, removing the quotes from quoted fields
+pub fn split_fields(record: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = record.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let field = fields.last_mut().expect("No current field");
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => { chars.next(); field.push('"'); }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            c => field.push(c)
+        }
+    }
+
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_fields_are_not_quoted() {
+        assert_eq!(quote_field("earth"), "earth");
+        assert_eq!(split_fields("1,earth,,2.5"), vec!["1", "earth", "", "2.5"]);
+    }
+
+    #[test]
+    fn quoted_fields_round_trip() {
+        let ids = ["alpha, b", "say \"hi\"", "line\nbreak", ""];
+        let record = ids.iter().map(|x| quote_field(x)).collect::<Vec<_>>().join(",");
+
+        assert!(is_complete_record(&record));
+        assert!(!is_complete_record(&record[..3]));
+        assert_eq!(split_fields(&record), ids.to_vec());
+    }
+}
@@ -0,0 +1,227 @@
+use std::fs;
+use std::path::PathBuf;
+use serde::*;
+use crate::core::types::{Numeric, Time};
+use crate::state::State;
+use crate::units::UnitSystem;
+use super::npy::NpyArray;
+use super::npz::NpzWriter;
+use super::snapshot::{Snapshot, BodyRecord};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Npz,                // Single .npz archive holding every snapshot
+    Npy,                // Directory of .npy files, one subdirectory per snapshot
+    #[cfg(feature = "hdf5")]
+    Hdf5                // Single .h5 file with one group per snapshot
+}
+
+impl ExportFormat {
+    pub fn parse(format: &str) -> Result<Self, String> {
+        match format.trim().to_lowercase().as_str() {
+            "npz" => Ok(ExportFormat::Npz),
+            "npy" => Ok(ExportFormat::Npy),
+            #[cfg(feature = "hdf5")]
+            "hdf5" | "h5" => Ok(ExportFormat::Hdf5),
+            #[cfg(not(feature = "hdf5"))]
+            "hdf5" | "h5" => Err("HDF5 export requires building with the \"hdf5\" feature".to_string()),
+            other => Err(format!("Unknown snapshot format \"{}\"", other))
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SnapshotExportOptions {
+    pub path: String,
+    pub format: ExportFormat,
+    pub interval: usize
+}
+
+// System-level attributes stored alongside the snapshot datasets
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotMetadata {
+    pub numeric_type: String,
+    pub units: UnitSystem,
+    pub gravitational_constant: f64,
+    pub softening_constant: f64,
+    pub epoch: String,
+    pub length_unit: String,
+    pub mass_unit: String,
+    pub time_unit: String
+}
+
+impl SnapshotMetadata {
+    pub fn new<TNum>(units: UnitSystem, gravitational_constant: f64, softening_constant: f64, epoch: String) -> Self
+        where TNum: Numeric {
+        Self {
+            numeric_type: TNum::type_name().to_string(),
+            units,
+            gravitational_constant,
+            softening_constant,
+            epoch,
+            length_unit: units.length_label().to_string(),
+            mass_unit: units.mass_label().to_string(),
+            time_unit: units.time_label().to_string()
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Failed to serialize snapshot metadata")
+    }
+}
+
+// Writes per-snapshot datasets of positions, velocities, masses and ids
+pub trait SnapshotExporter {
+    fn write_snapshot(&mut self, snapshot: &Snapshot) -> std::io::Result<()>;
+    fn finish(&mut self) -> std::io::Result<()>;
+}
+
+pub struct SnapshotExport {
+    exporter: Box<dyn SnapshotExporter>,
+    interval: usize
+}
+
+impl SnapshotExport {
+    pub fn create(options: &SnapshotExportOptions, metadata: &SnapshotMetadata) -> std::io::Result<Self> {
+        let exporter: Box<dyn SnapshotExporter> = match options.format {
+            ExportFormat::Npz => Box::new(NpzExporter::create(&options.path, metadata)?),
+            ExportFormat::Npy => Box::new(NpyExporter::create(&options.path, metadata)?),
+            #[cfg(feature = "hdf5")]
+            ExportFormat::Hdf5 => Box::new(super::h5::Hdf5Exporter::create(&options.path, metadata)?)
+        };
+
+        Ok(Self {
+            exporter,
+            interval: options.interval.max(1)
+        })
+    }
+
+    pub fn record<TNum>(&mut self, step: usize, time: Time, state: &State<TNum>) -> std::io::Result<()>
+        where TNum: Numeric {
+        if step % self.interval != 0 { return Ok(()); }
+
+        let snapshot = Snapshot::from_state(step, time, state, &[], None);
+        self.exporter.write_snapshot(&snapshot)
+    }
+
+    pub fn finish(&mut self) -> std::io::Result<()> {
+        self.exporter.finish()
+    }
+}
+
+impl Drop for SnapshotExport {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+pub fn snapshot_group_name(step: usize) -> String {
+    format!("snapshot_{:08}", step)
+}
+
+// Arrays making up a single snapshot, in (name, array) pairs
+pub(super) fn snapshot_arrays(snapshot: &Snapshot) -> Vec<(&'static str, NpyArray)> {
+    let n = snapshot.bodies.len();
+    let flatten = |f: fn(&BodyRecord) -> [f64; 3]| snapshot.bodies.iter().flat_map(|x| f(x).to_vec()).collect::<Vec<f64>>();
+
+    vec![
+        ("positions", NpyArray::from_f64(vec![n, 3], &flatten(|x| x.position))),
+        ("velocities", NpyArray::from_f64(vec![n, 3], &flatten(|x| x.velocity))),
+        ("masses", NpyArray::from_f64(vec![n], &snapshot.bodies.iter().map(|x| x.mass).collect::<Vec<_>>())),
+        ("ids", NpyArray::from_strings(&snapshot.bodies.iter().map(|x| x.id.clone()).collect::<Vec<_>>())),
+        ("step", NpyArray::from_u64(vec![], &[snapshot.step as u64])),
+        ("time", NpyArray::from_f64(vec![], &[snapshot.time]))
+    ]
+}
+
+// Index arrays of every recorded step and time, written once the export is complete
+fn index_arrays(steps: &[u64], times: &[f64]) -> Vec<(&'static str, NpyArray)> {
+    vec![
+        ("steps", NpyArray::from_u64(vec![steps.len()], steps)),
+        ("times", NpyArray::from_f64(vec![times.len()], times))
+    ]
+}
+
+// Arrays are named "<snapshot>/<dataset>", e.g. np.load(f)["snapshot_00000100/positions"]
+pub struct NpzExporter {
+    archive: NpzWriter,
+    steps: Vec<u64>,
+    times: Vec<f64>,
+    finished: bool
+}
+
+impl NpzExporter {
+    pub fn create(path: &str, metadata: &SnapshotMetadata) -> std::io::Result<Self> {
+        let mut archive = NpzWriter::create(path)?;
+        archive.add_array("metadata", &NpyArray::scalar_string(&metadata.to_json()))?;
+
+        Ok(Self { archive, steps: vec![], times: vec![], finished: false })
+    }
+}
+
+impl SnapshotExporter for NpzExporter {
+    fn write_snapshot(&mut self, snapshot: &Snapshot) -> std::io::Result<()> {
+        let group = snapshot_group_name(snapshot.step);
+        for (name, array) in snapshot_arrays(snapshot) {
+            self.archive.add_array(&format!("{}/{}", group, name), &array)?;
+        }
+
+        self.steps.push(snapshot.step as u64);
+        self.times.push(snapshot.time);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        if self.finished { return Ok(()); }
+
+        for (name, array) in index_arrays(&self.steps, &self.times) {
+            self.archive.add_array(name, &array)?;
+        }
+
+        self.finished = true;
+        self.archive.finish()
+    }
+}
+
+// Writes "<dir>/metadata.json", "<dir>/<snapshot>/<dataset>.npy" and the step/time indices on completion
+pub struct NpyExporter {
+    directory: PathBuf,
+    steps: Vec<u64>,
+    times: Vec<f64>
+}
+
+impl NpyExporter {
+    pub fn create(path: &str, metadata: &SnapshotMetadata) -> std::io::Result<Self> {
+        let directory = PathBuf::from(path);
+        fs::create_dir_all(&directory)?;
+        fs::write(directory.join("metadata.json"), metadata.to_json())?;
+
+        Ok(Self { directory, steps: vec![], times: vec![] })
+    }
+
+    fn write_array(directory: &PathBuf, name: &str, array: &NpyArray) -> std::io::Result<()> {
+        fs::write(directory.join(format!("{}.npy", name)), array.to_bytes())
+    }
+}
+
+impl SnapshotExporter for NpyExporter {
+    fn write_snapshot(&mut self, snapshot: &Snapshot) -> std::io::Result<()> {
+        let group = self.directory.join(snapshot_group_name(snapshot.step));
+        fs::create_dir_all(&group)?;
+
+        for (name, array) in snapshot_arrays(snapshot) {
+            Self::write_array(&group, name, &array)?;
+        }
+
+        self.steps.push(snapshot.step as u64);
+        self.times.push(snapshot.time);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        for (name, array) in index_arrays(&self.steps, &self.times) {
+            Self::write_array(&self.directory, name, &array)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,62 @@
+use std::io::Write;
+use serde::*;
+use super::snapshot::{Snapshot, FieldSet};
+use super::export::SnapshotMetadata;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    Csv,
+    Jsonl,
+    Binary
+}
+
+impl OutputFormat {
+    pub fn parse(format: &str) -> Result<Self, String> {
+        match format.trim().to_lowercase().as_str() {
+            "csv" => Ok(OutputFormat::Csv),
+            "jsonl" | "json" => Ok(OutputFormat::Jsonl),
+            "binary" | "bin" => Ok(OutputFormat::Binary),
+            other => Err(format!("Unknown output format \"{}\"", other))
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Csv => "csv",
+            OutputFormat::Jsonl => "jsonl",
+            OutputFormat::Binary => "nbt"
+        }
+    }
+
+    pub fn create_encoder(&self) -> Box<dyn SnapshotEncoder> {
+        match self {
+            OutputFormat::Csv => Box::new(super::csv::CsvEncoder),
+            OutputFormat::Jsonl => Box::new(super::jsonl::JsonLinesEncoder),
+            OutputFormat::Binary => Box::new(super::binary::BinaryEncoder)
+        }
+    }
+}
+
+// Encodes snapshots into a particular trajectory file format.  The header records the system units, G,
+// softening and epoch, so that a recording can be replayed with the same properties
+pub trait SnapshotEncoder {
+    fn write_header(&self, out: &mut dyn Write, fields: &FieldSet, metadata: &SnapshotMetadata) -> std::io::Result<()>;
+    fn write_snapshot(&self, out: &mut dyn Write, snapshot: &Snapshot, fields: &FieldSet) -> std::io::Result<()>;
+}
+
+// Element values written for a body, in a fixed order.  Angles are in degrees
+pub const ELEMENT_NAMES: [&str; 8] = ["semi_major_axis", "eccentricity", "inclination", "longitude_of_ascending_node",
+                                      "argument_of_periapsis", "true_anomaly", "mean_anomaly", "period"];
+
+pub fn element_values(elements: &Option<crate::orbital::osculating::OsculatingElements>) -> [f64; 8] {
+    match elements {
+        None => [std::f64::NAN; 8],
+        Some(el) => {
+            let x = &el.elements;
+            [x.semi_major_axis, x.eccentricity, x.inclination.to_degrees(), x.longitude_of_ascending_node.to_degrees(),
+             x.argument_of_periapsis.to_degrees(), x.true_anomaly.to_degrees(), el.mean_anomaly.to_degrees(),
+             el.period.unwrap_or(std::f64::NAN)]
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use hdf5::types::VarLenUnicode;
+use super::export::{SnapshotExporter, SnapshotMetadata, snapshot_group_name};
+use super::snapshot::Snapshot;
+
+// One group per snapshot holding "positions", "velocities", "masses" and "ids" datasets, with step and
+// time attributes.  System metadata is attached as attributes of the root group
+pub struct Hdf5Exporter {
+    file: hdf5::File
+}
+
+impl Hdf5Exporter {
+    pub fn create(path: &str, metadata: &SnapshotMetadata) -> std::io::Result<Self> {
+        let file = hdf5::File::create(path).map_err(to_io_error)?;
+        let exporter = Self { file };
+        exporter.write_metadata(metadata).map_err(to_io_error)?;
+
+        Ok(exporter)
+    }
+
+    fn write_metadata(&self, metadata: &SnapshotMetadata) -> hdf5::Result<()> {
+        write_string_attr(&self.file, "numeric_type", &metadata.numeric_type)?;
+        write_string_attr(&self.file, "units", &serde_json::to_string(&metadata.units).unwrap_or_default())?;
+        write_string_attr(&self.file, "epoch", &metadata.epoch)?;
+        write_string_attr(&self.file, "length_unit", &metadata.length_unit)?;
+        write_string_attr(&self.file, "mass_unit", &metadata.mass_unit)?;
+        write_string_attr(&self.file, "time_unit", &metadata.time_unit)?;
+
+        self.file.new_attr::<f64>().create("gravitational_constant")?.write_scalar(&metadata.gravitational_constant)?;
+        self.file.new_attr::<f64>().create("softening_constant")?.write_scalar(&metadata.softening_constant)?;
+        Ok(())
+    }
+
+    fn write_group(&self, snapshot: &Snapshot) -> hdf5::Result<()> {
+        let n = snapshot.bodies.len();
+        let group = self.file.create_group(&snapshot_group_name(snapshot.step))?;
+
+        group.new_attr::<u64>().create("step")?.write_scalar(&(snapshot.step as u64))?;
+        group.new_attr::<f64>().create("time")?.write_scalar(&snapshot.time)?;
+
+        let positions: Vec<f64> = snapshot.bodies.iter().flat_map(|x| x.position.to_vec()).collect();
+        let velocities: Vec<f64> = snapshot.bodies.iter().flat_map(|x| x.velocity.to_vec()).collect();
+        let masses: Vec<f64> = snapshot.bodies.iter().map(|x| x.mass).collect();
+        let ids = snapshot.bodies.iter()
+            .map(|x| x.id.parse::<VarLenUnicode>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| hdf5::Error::from(e.to_string().as_str()))?;
+
+        group.new_dataset::<f64>().shape([n, 3]).create("positions")?.write_raw(&positions)?;
+        group.new_dataset::<f64>().shape([n, 3]).create("velocities")?.write_raw(&velocities)?;
+        group.new_dataset::<f64>().shape([n]).create("masses")?.write_raw(&masses)?;
+        group.new_dataset::<VarLenUnicode>().shape([n]).create("ids")?.write_raw(&ids)?;
+        Ok(())
+    }
+}
+
+impl SnapshotExporter for Hdf5Exporter {
+    fn write_snapshot(&mut self, snapshot: &Snapshot) -> std::io::Result<()> {
+        self.write_group(snapshot).map_err(to_io_error)
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        self.file.flush().map_err(to_io_error)
+    }
+}
+
+fn write_string_attr(location: &hdf5::Location, name: &str, value: &str) -> hdf5::Result<()> {
+    let value = value.parse::<VarLenUnicode>()
+        .map_err(|e| hdf5::Error::from(e.to_string().as_str()))?;
+    location.new_attr::<VarLenUnicode>().create(name)?.write_scalar(&value)
+}
+
+fn to_io_error(e: hdf5::Error) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Other, e.to_string())
+}
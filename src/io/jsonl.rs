@@ -0,0 +1,51 @@
+use std::io::Write;
+use serde_json::{json, Map, Value};
+use super::format::{SnapshotEncoder, ELEMENT_NAMES, element_values};
+use super::snapshot::{Snapshot, FieldSet, OutputField};
+use super::export::SnapshotMetadata;
+
+// One JSON object per snapshot per line, holding the step, time and an array of bodies.  The first line holds
+// the metadata instead, as {"metadata": {...}}
+pub struct JsonLinesEncoder;
+
+impl SnapshotEncoder for JsonLinesEncoder {
+    fn write_header(&self, out: &mut dyn Write, _fields: &FieldSet, metadata: &SnapshotMetadata) -> std::io::Result<()> {
+        writeln!(out, "{}", json!({ "metadata": metadata }))
+    }
+
+    fn write_snapshot(&self, out: &mut dyn Write, snapshot: &Snapshot, fields: &FieldSet) -> std::io::Result<()> {
+        let bodies: Vec<Value> = snapshot.bodies.iter()
+            .map(|body| {
+                let mut obj = Map::new();
+                for field in fields.fields() {
+                    match field {
+                        OutputField::Id => { obj.insert("id".to_string(), json!(body.id)); },
+                        OutputField::Mass => { obj.insert("mass".to_string(), json!(body.mass)); },
+                        OutputField::Position => { obj.insert("position".to_string(), json!(body.position)); },
+                        OutputField::Velocity => { obj.insert("velocity".to_string(), json!(body.velocity)); },
+                        OutputField::Elements => {
+                            let elements = body.elements.as_ref().map(|_| {
+                                let values = element_values(&body.elements);
+                                ELEMENT_NAMES.iter().zip(values.iter())
+                                    .map(|(name, x)| (name.to_string(), if x.is_nan() { Value::Null } else { json!(x) }))
+                                    .collect::<Map<String, Value>>()
+                            });
+                            obj.insert("elements".to_string(), elements.map(Value::Object).unwrap_or(Value::Null));
+                        },
+                        OutputField::Time => ()
+                    }
+                }
+                Value::Object(obj)
+            })
+            .collect();
+
+        let mut line = Map::new();
+        line.insert("step".to_string(), json!(snapshot.step));
+        if fields.contains(OutputField::Time) {
+            line.insert("time".to_string(), json!(snapshot.time));
+        }
+        line.insert("bodies".to_string(), Value::Array(bodies));
+
+        writeln!(out, "{}", Value::Object(line))
+    }
+}
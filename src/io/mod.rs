@@ -1,3 +1,12 @@
-pub struct IO {
-
-}
\ No newline at end of file
+pub mod snapshot;
+pub mod format;
+pub mod csv;
+pub mod jsonl;
+pub mod binary;
+pub mod trajectory;
+pub mod npy;
+pub mod npz;
+pub mod export;
+#[cfg(feature = "hdf5")]
+pub mod h5;
+pub mod reader;
@@ -0,0 +1,81 @@
+use std::io::Write;
+
+const NPY_MAGIC: &[u8; 6] = b"\x93NUMPY";
+const NPY_HEADER_ALIGNMENT: usize = 64;
+
+// A little-endian array in the NumPy .npy format, held as its encoded element bytes
+pub struct NpyArray {
+    descr: String,
+    shape: Vec<usize>,
+    data: Vec<u8>
+}
+
+impl NpyArray {
+    pub fn from_f64(shape: Vec<usize>, values: &[f64]) -> Self {
+        assert_eq!(shape.iter().product::<usize>(), values.len(), "Array shape does not match data length");
+        Self {
+            descr: "<f8".to_string(),
+            shape,
+            data: values.iter().flat_map(|x| x.to_le_bytes().to_vec()).collect()
+        }
+    }
+
+    pub fn from_u64(shape: Vec<usize>, values: &[u64]) -> Self {
+        assert_eq!(shape.iter().product::<usize>(), values.len(), "Array shape does not match data length");
+        Self {
+            descr: "<u8".to_string(),
+            shape,
+            data: values.iter().flat_map(|x| x.to_le_bytes().to_vec()).collect()
+        }
+    }
+
+    // Fixed-width UCS-4 strings, sized to the longest value
+    pub fn from_strings(values: &[String]) -> Self {
+        let width = values.iter().map(|x| x.chars().count()).max().unwrap_or(0).max(1);
+        let data = values.iter()
+            .flat_map(|x| x.chars().map(|c| c as u32)
+                .chain(std::iter::repeat(0))
+                .take(width)
+                .flat_map(|c| c.to_le_bytes().to_vec())
+                .collect::<Vec<u8>>())
+            .collect();
+
+        Self { descr: format!("<U{}", width), shape: vec![values.len()], data }
+    }
+
+    // Zero-dimensional string array, used to hold JSON metadata
+    pub fn scalar_string(value: &str) -> Self {
+        let mut array = Self::from_strings(&[value.to_string()]);
+        array.shape = vec![];
+        array
+    }
+
+    fn header(&self) -> String {
+        let shape = match self.shape.len() {
+            1 => format!("({},)", self.shape[0]),
+            _ => format!("({})", self.shape.iter().map(usize::to_string).collect::<Vec<_>>().join(", "))
+        };
+
+        let dict = format!("{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}", self.descr, shape);
+
+        // Magic, version and header length precede the header, which is space-padded and newline-terminated
+        let unpadded = NPY_MAGIC.len() + 2 + 2 + dict.len() + 1;
+        let padding = (NPY_HEADER_ALIGNMENT - unpadded % NPY_HEADER_ALIGNMENT) % NPY_HEADER_ALIGNMENT;
+        format!("{}{}\n", dict, " ".repeat(padding))
+    }
+
+    pub fn write(&self, out: &mut dyn Write) -> std::io::Result<()> {
+        let header = self.header();
+        out.write_all(NPY_MAGIC)?;
+        out.write_all(&[1, 0])?;
+        out.write_all(&(header.len() as u16).to_le_bytes())?;
+        out.write_all(header.as_bytes())?;
+        out.write_all(&self.data)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        self.write(&mut bytes).expect("Failed to encode array");
+        bytes
+    }
+}
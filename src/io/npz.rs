@@ -0,0 +1,225 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use super::npy::NpyArray;
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const END_OF_DIRECTORY_SIGNATURE: u32 = 0x0605_4b50;
+const ZIP64_END_OF_DIRECTORY_SIGNATURE: u32 = 0x0606_4b50;
+const ZIP64_LOCATOR_SIGNATURE: u32 = 0x0706_4b50;
+const ZIP64_EXTRA_FIELD_ID: u16 = 0x0001;
+const ZIP_VERSION: u16 = 20;
+const ZIP64_VERSION: u16 = 45;
+
+// Largest values of the 32-bit size & offset and 16-bit count fields; at or beyond these, the field holds the
+// maximum and the real value is given in a ZIP64 record
+const MAX_FIELD_U32: u64 = 0xFFFF_FFFF;
+const MAX_FIELD_U16: usize = 0xFFFF;
+
+struct NpzEntry {
+    name: String,
+    crc: u32,
+    size: u64,
+    offset: u64
+}
+
+impl NpzEntry {
+    fn size_overflows(&self) -> bool { self.size >= MAX_FIELD_U32 }
+    fn offset_overflows(&self) -> bool { self.offset >= MAX_FIELD_U32 }
+
+    // ZIP64 extra field holding the values that overflow their header fields, in the order the format requires
+    fn zip64_extra(&self, include_offset: bool) -> Vec<u8> {
+        let mut values = vec![];
+        if self.size_overflows() { values.extend_from_slice(&[self.size, self.size]); }
+        if include_offset && self.offset_overflows() { values.push(self.offset); }
+        if values.is_empty() { return vec![]; }
+
+        let mut extra = vec![];
+        extra.extend_from_slice(&ZIP64_EXTRA_FIELD_ID.to_le_bytes());
+        extra.extend_from_slice(&(values.len() as u16 * 8).to_le_bytes());
+        values.iter().for_each(|x| extra.extend_from_slice(&x.to_le_bytes()));
+        extra
+    }
+}
+
+// Streams .npy arrays into an uncompressed zip archive, readable with numpy.load.  The central
+// directory is written when the archive is finished, or when the writer is dropped.  ZIP64 records
+// are added where entries, offsets or the entry count exceed the limits of the original format
+pub struct NpzWriter {
+    writer: BufWriter<File>,
+    entries: Vec<NpzEntry>,
+    offset: u64,
+    finished: bool
+}
+
+impl NpzWriter {
+    pub fn create(path: &str) -> std::io::Result<Self> {
+        Ok(Self {
+            writer: BufWriter::new(File::create(path)?),
+            entries: vec![],
+            offset: 0,
+            finished: false
+        })
+    }
+
+    pub fn add_array(&mut self, name: &str, array: &NpyArray) -> std::io::Result<()> {
+        let data = array.to_bytes();
+        let entry = NpzEntry {
+            name: format!("{}.npy", name),
+            crc: crc32(&data),
+            size: data.len() as u64,
+            offset: self.offset
+        };
+        let extra = entry.zip64_extra(false);
+        let version = if extra.is_empty() { ZIP_VERSION } else { ZIP64_VERSION };
+
+        let w = &mut self.writer;
+        w.write_all(&LOCAL_HEADER_SIGNATURE.to_le_bytes())?;
+        w.write_all(&version.to_le_bytes())?;
+        w.write_all(&[0u8; 8])?;                           // Flags, stored method, modification time & date
+        w.write_all(&entry.crc.to_le_bytes())?;
+        w.write_all(&field_u32(entry.size).to_le_bytes())?; // Compressed size
+        w.write_all(&field_u32(entry.size).to_le_bytes())?; // Uncompressed size
+        w.write_all(&(entry.name.len() as u16).to_le_bytes())?;
+        w.write_all(&(extra.len() as u16).to_le_bytes())?;
+        w.write_all(entry.name.as_bytes())?;
+        w.write_all(&extra)?;
+        w.write_all(&data)?;
+
+        self.offset += 30 + (entry.name.len() + extra.len()) as u64 + entry.size;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    pub fn finish(&mut self) -> std::io::Result<()> {
+        if self.finished { return Ok(()); }
+
+        let directory_offset = self.offset;
+        let mut directory_size = 0u64;
+        let w = &mut self.writer;
+
+        for entry in self.entries.iter() {
+            let extra = entry.zip64_extra(true);
+            let version = if extra.is_empty() { ZIP_VERSION } else { ZIP64_VERSION };
+
+            w.write_all(&CENTRAL_HEADER_SIGNATURE.to_le_bytes())?;
+            w.write_all(&version.to_le_bytes())?;              // Version made by
+            w.write_all(&version.to_le_bytes())?;              // Version needed
+            w.write_all(&[0u8; 8])?;
+            w.write_all(&entry.crc.to_le_bytes())?;
+            w.write_all(&field_u32(entry.size).to_le_bytes())?;
+            w.write_all(&field_u32(entry.size).to_le_bytes())?;
+            w.write_all(&(entry.name.len() as u16).to_le_bytes())?;
+            w.write_all(&(extra.len() as u16).to_le_bytes())?;
+            w.write_all(&[0u8; 10])?;                          // Comment length, disk, attributes
+            w.write_all(&field_u32(entry.offset).to_le_bytes())?;
+            w.write_all(entry.name.as_bytes())?;
+            w.write_all(&extra)?;
+
+            directory_size += 46 + (entry.name.len() + extra.len()) as u64;
+        }
+
+        let count = self.entries.len();
+        if count >= MAX_FIELD_U16 || directory_size >= MAX_FIELD_U32 || directory_offset >= MAX_FIELD_U32 {
+            let zip64_directory_offset = directory_offset + directory_size;
+
+            w.write_all(&ZIP64_END_OF_DIRECTORY_SIGNATURE.to_le_bytes())?;
+            w.write_all(&44u64.to_le_bytes())?;                // Size of the remaining record
+            w.write_all(&ZIP64_VERSION.to_le_bytes())?;        // Version made by
+            w.write_all(&ZIP64_VERSION.to_le_bytes())?;        // Version needed
+            w.write_all(&[0u8; 8])?;                           // Disk numbers
+            w.write_all(&(count as u64).to_le_bytes())?;
+            w.write_all(&(count as u64).to_le_bytes())?;
+            w.write_all(&directory_size.to_le_bytes())?;
+            w.write_all(&directory_offset.to_le_bytes())?;
+
+            w.write_all(&ZIP64_LOCATOR_SIGNATURE.to_le_bytes())?;
+            w.write_all(&0u32.to_le_bytes())?;                 // Disk holding the ZIP64 record
+            w.write_all(&zip64_directory_offset.to_le_bytes())?;
+            w.write_all(&1u32.to_le_bytes())?;                 // Total disks
+        }
+
+        w.write_all(&END_OF_DIRECTORY_SIGNATURE.to_le_bytes())?;
+        w.write_all(&[0u8; 4])?;                               // Disk numbers
+        w.write_all(&(count.min(MAX_FIELD_U16) as u16).to_le_bytes())?;
+        w.write_all(&(count.min(MAX_FIELD_U16) as u16).to_le_bytes())?;
+        w.write_all(&field_u32(directory_size).to_le_bytes())?;
+        w.write_all(&field_u32(directory_offset).to_le_bytes())?;
+        w.write_all(&0u16.to_le_bytes())?;                     // Comment length
+        w.flush()?;
+
+        self.finished = true;
+        Ok(())
+    }
+}
+
+impl Drop for NpzWriter {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+// Value of a 32-bit header field, or the marker that the value is held in a ZIP64 record
+fn field_u32(value: u64) -> u32 {
+    value.min(MAX_FIELD_U32) as u32
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u32, |c, _| if c & 1 != 0 { (c >> 1) ^ 0xEDB8_8320 } else { c >> 1 })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_archive(name: &str, entries: usize) -> Vec<u8> {
+        let path = std::env::temp_dir().join(format!("npz-test-{}-{}.npz", name, std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        {
+            let mut writer = NpzWriter::create(&path).unwrap();
+            let array = NpyArray::from_f64(vec![3], &[1.0, 2.0, 3.0]);
+            (0..entries).for_each(|i| writer.add_array(&format!("a{}", i), &array).unwrap());
+            writer.finish().unwrap();
+        }
+
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        bytes
+    }
+
+    fn u16_at(bytes: &[u8], at: usize) -> u16 { u16::from_le_bytes([bytes[at], bytes[at + 1]]) }
+    fn u32_at(bytes: &[u8], at: usize) -> u32 { u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]) }
+    fn u64_at(bytes: &[u8], at: usize) -> u64 { u32_at(bytes, at) as u64 | (u32_at(bytes, at + 4) as u64) << 32 }
+
+    #[test]
+    fn small_archive_has_no_zip64_records() {
+        let bytes = write_archive("small", 2);
+        let end = bytes.len() - 22;
+
+        assert_eq!(u32_at(&bytes, end), END_OF_DIRECTORY_SIGNATURE);
+        assert_eq!(u16_at(&bytes, end + 10), 2);
+        assert_ne!(u32_at(&bytes, end - 20), ZIP64_LOCATOR_SIGNATURE);
+    }
+
+    #[test]
+    fn entry_count_beyond_limit_adds_zip64_records() {
+        let entries = MAX_FIELD_U16 + 10;
+        let bytes = write_archive("count", entries);
+        let end = bytes.len() - 22;
+        let locator = end - 20;
+
+        assert_eq!(u16_at(&bytes, end + 10), MAX_FIELD_U16 as u16);
+        assert_eq!(u32_at(&bytes, locator), ZIP64_LOCATOR_SIGNATURE);
+
+        let record = u64_at(&bytes, locator + 8) as usize;
+        assert_eq!(u32_at(&bytes, record), ZIP64_END_OF_DIRECTORY_SIGNATURE);
+        assert_eq!(u64_at(&bytes, record + 32) as usize, entries);
+
+        // The directory runs from its recorded offset up to the ZIP64 record
+        let (size, offset) = (u64_at(&bytes, record + 40) as usize, u64_at(&bytes, record + 48) as usize);
+        assert_eq!(offset + size, record);
+        assert_eq!(u32_at(&bytes, offset), CENTRAL_HEADER_SIGNATURE);
+    }
+}
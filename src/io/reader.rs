@@ -0,0 +1,400 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use std::collections::HashMap;
+use std::ops::{Add, Sub, Mul, Div, AddAssign};
+use core::iter::Sum;
+use chrono::{DateTime, Utc};
+use failure::{Error, format_err};
+use fixed::types::{I16F48, I64F64};
+use crate::core::types::Numeric;
+use crate::nbody::checkpoint::Checkpoint;
+use crate::nbody::nbody_system::NBodySystem;
+use crate::units::UnitSystem;
+use crate::util::temporal;
+use super::binary::{BINARY_MAGIC, BINARY_VERSION};
+use super::csv;
+use super::export::SnapshotMetadata;
+use super::format::{OutputFormat, ELEMENT_NAMES};
+use super::snapshot::{Snapshot, BodyRecord, FieldSet, OutputField};
+
+// A sequence of recorded snapshots, with whatever system properties the source recorded
+pub struct Recording {
+    pub frames: Vec<Snapshot>,
+    pub units: UnitSystem,
+    pub gravitational_constant: f64,
+    pub softening_constant: f64,
+    pub epoch: DateTime<Utc>
+}
+
+impl Recording {
+    // Takes the system properties from the metadata, if recorded, or otherwise the defaults
+    fn from_frames(frames: Vec<Snapshot>, metadata: Option<SnapshotMetadata>) -> Result<Self, Error> {
+        let units = metadata.as_ref().map(|x| x.units).unwrap_or_default();
+        let epoch = match metadata.as_ref() {
+            Some(metadata) => DateTime::parse_from_rfc3339(&metadata.epoch)
+                .map_err(|e| format_err!("Invalid epoch \"{}\" in metadata ({})", metadata.epoch, e))?
+                .with_timezone(&Utc),
+            None => temporal::j2000_epoch()
+        };
+
+        Ok(Self {
+            frames,
+            gravitational_constant: metadata.as_ref().map(|x| x.gravitational_constant).unwrap_or_else(|| units.gravitational_constant()),
+            softening_constant: metadata.as_ref().map(|x| x.softening_constant).unwrap_or(0.0),
+            units,
+            epoch
+        })
+    }
+}
+
+// Reads a recording from trajectory files (.csv, .jsonl, .nbt) or a series of checkpoints (.json).  Files are
+// read in the order given; a rotated trajectory may be given by its base path, i.e. without the file index.
+// The system properties are those recorded in the first trajectory file
+pub fn read_recording(paths: &[String]) -> Result<Recording, Error> {
+    let files = paths.iter()
+        .map(|x| expand_rotated_files(x))
+        .collect::<Result<Vec<_>, _>>()?
+        .concat();
+
+    if files.is_empty() {
+        return Err(format_err!("No recording files specified"));
+    }
+
+    if files.iter().all(|x| x.ends_with(".json")) {
+        return read_checkpoint_series(&files);
+    }
+
+    let mut recordings = files.iter()
+        .map(|x| read_trajectory(x))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut recording = recordings.remove(0);
+    recordings.into_iter().for_each(|x| recording.frames.extend(x.frames));
+    Ok(recording)
+}
+
+fn expand_rotated_files(path: &str) -> Result<Vec<String>, Error> {
+    if Path::new(path).exists() {
+        return Ok(vec![path.to_string()]);
+    }
+
+    let extension = [OutputFormat::Csv, OutputFormat::Jsonl, OutputFormat::Binary].iter()
+        .map(OutputFormat::extension)
+        .find(|ext| Path::new(&format!("{}.0000.{}", path, ext)).exists())
+        .ok_or_else(|| format_err!("Recording file \"{}\" does not exist", path))?;
+
+    Ok((0..)
+        .map(|i| format!("{}.{:04}.{}", path, i, extension))
+        .take_while(|x| Path::new(x).exists())
+        .collect())
+}
+
+pub fn read_trajectory(path: &str) -> Result<Recording, Error> {
+    let format = Path::new(path).extension()
+        .and_then(|x| x.to_str())
+        .and_then(|x| match x {
+            "csv" => Some(OutputFormat::Csv),
+            "jsonl" => Some(OutputFormat::Jsonl),
+            "nbt" => Some(OutputFormat::Binary),
+            _ => None
+        })
+        .ok_or_else(|| format_err!("Cannot determine trajectory format of \"{}\"", path))?;
+
+    let file = File::open(path).map_err(|e| format_err!("Failed to open trajectory ({}): {}", path, e))?;
+    match format {
+        OutputFormat::Csv => read_csv(BufReader::new(file)),
+        OutputFormat::Jsonl => read_jsonl(BufReader::new(file)),
+        OutputFormat::Binary => read_binary(BufReader::new(file))
+    }.map_err(|e| format_err!("Failed to read trajectory ({}): {}", path, e))
+}
+
+fn empty_body(index: usize) -> BodyRecord {
+    BodyRecord { id: index.to_string(), mass: 0.0, position: [0.0; 3], velocity: [0.0; 3], elements: None }
+}
+
+fn read_csv(reader: impl BufRead) -> Result<Recording, Error> {
+    let mut lines = reader.lines();
+    let mut header = lines.next().ok_or_else(|| format_err!("Empty file"))??;
+
+    // Files written before metadata was recorded start with the column names
+    let mut metadata = None;
+    if header.starts_with(csv::METADATA_PREFIX) {
+        metadata = Some(serde_json::from_str(&header[csv::METADATA_PREFIX.len()..])?);
+        header = lines.next().ok_or_else(|| format_err!("No column names"))??;
+    }
+    let columns: HashMap<String, usize> = csv::split_fields(&header).into_iter().enumerate().map(|(i, x)| (x, i)).collect();
+
+    let column = |name: &str| columns.get(name).cloned();
+    let step_column = column("step").ok_or_else(|| format_err!("No step column"))?;
+    let position_columns = [column("x"), column("y"), column("z")];
+    if position_columns.iter().any(Option::is_none) {
+        return Err(format_err!("No position columns"));
+    }
+    let velocity_columns = [column("vx"), column("vy"), column("vz")];
+
+    let mut frames: Vec<Snapshot> = vec![];
+    while let Some(line) = lines.next() {
+        let mut line = line?;
+        if line.trim().is_empty() { continue; }
+
+        // Quoted fields may continue over several lines
+        while !csv::is_complete_record(&line) {
+            let next = lines.next().ok_or_else(|| format_err!("Unterminated quoted field in row \"{}\"", line))??;
+            line = format!("{}\n{}", line, next);
+        }
+
+        let values = csv::split_fields(&line);
+        let value = |ix: Option<usize>| -> Result<f64, Error> {
+            match ix {
+                None => Ok(0.0),
+                Some(ix) => values.get(ix)
+                    .ok_or_else(|| format_err!("Missing value in row \"{}\"", line))?
+                    .parse::<f64>()
+                    .map_err(|_| format_err!("Invalid value in row \"{}\"", line))
+            }
+        };
+
+        let step = value(Some(step_column))? as usize;
+        if frames.last().map(|x| x.step != step).unwrap_or(true) {
+            frames.push(Snapshot { step, time: value(column("time"))?, bodies: vec![] });
+        }
+
+        let frame = frames.last_mut().expect("No current frame");
+        let mut body = empty_body(frame.bodies.len());
+        if let Some(ix) = column("id") { body.id = values.get(ix).cloned().unwrap_or_default(); }
+        body.mass = value(column("mass"))?;
+        for axis in 0..3 {
+            body.position[axis] = value(position_columns[axis])?;
+            body.velocity[axis] = value(velocity_columns[axis])?;
+        }
+
+        frame.bodies.push(body);
+    }
+
+    Recording::from_frames(frames, metadata)
+}
+
+fn read_jsonl(reader: impl BufRead) -> Result<Recording, Error> {
+    let vector = |x: Option<&serde_json::Value>| -> [f64; 3] {
+        let mut v = [0.0; 3];
+        if let Some(values) = x.and_then(|x| x.as_array()) {
+            values.iter().take(3).enumerate().for_each(|(i, x)| v[i] = x.as_f64().unwrap_or(0.0));
+        }
+        v
+    };
+
+    let mut metadata = None;
+    let mut frames = vec![];
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() { continue; }
+
+        let value: serde_json::Value = serde_json::from_str(&line)?;
+        if let Some(x) = value.get("metadata") {
+            metadata = Some(serde_json::from_value(x.clone())?);
+            continue;
+        }
+
+        let bodies = value["bodies"].as_array()
+            .ok_or_else(|| format_err!("Snapshot has no bodies"))?
+            .iter()
+            .enumerate()
+            .map(|(i, x)| BodyRecord {
+                id: x["id"].as_str().map(str::to_string).unwrap_or_else(|| i.to_string()),
+                mass: x["mass"].as_f64().unwrap_or(0.0),
+                position: vector(x.get("position")),
+                velocity: vector(x.get("velocity")),
+                elements: None
+            })
+            .collect();
+
+        frames.push(Snapshot {
+            step: value["step"].as_u64().unwrap_or(0) as usize,
+            time: value["time"].as_f64().unwrap_or(0.0),
+            bodies
+        });
+    }
+
+    Recording::from_frames(frames, metadata)
+}
+
+fn read_binary(mut reader: impl Read) -> Result<Recording, Error> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != BINARY_MAGIC {
+        return Err(format_err!("Not a binary trajectory file"));
+    }
+
+    let version = read_u32(&mut reader)?;
+    if version > BINARY_VERSION {
+        return Err(format_err!("Trajectory version {} is newer than supported version {}", version, BINARY_VERSION));
+    }
+
+    let mut mask = [0u8; 1];
+    reader.read_exact(&mut mask)?;
+    let fields = FieldSet::from_mask(mask[0]);
+
+    let mut metadata = None;
+    if version >= 2 {
+        let mut json = vec![0u8; read_u32(&mut reader)? as usize];
+        reader.read_exact(&mut json)?;
+        metadata = Some(serde_json::from_slice(&json)?);
+    }
+
+    let mut frames = vec![];
+    loop {
+        let mut step = [0u8; 8];
+        match reader.read_exact(&mut step) {
+            Err(ref e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            result => result?
+        }
+
+        let time = read_f64(&mut reader)?;
+        let count = read_u32(&mut reader)? as usize;
+        let mut bodies = Vec::with_capacity(count);
+
+        for i in 0..count {
+            let mut body = empty_body(i);
+            if fields.contains(OutputField::Id) {
+                let mut length = [0u8; 2];
+                reader.read_exact(&mut length)?;
+                let mut id = vec![0u8; u16::from_le_bytes(length) as usize];
+                reader.read_exact(&mut id)?;
+                body.id = String::from_utf8(id)?;
+            }
+            if fields.contains(OutputField::Mass) { body.mass = read_f64(&mut reader)?; }
+            if fields.contains(OutputField::Position) { for x in body.position.iter_mut() { *x = read_f64(&mut reader)?; } }
+            if fields.contains(OutputField::Velocity) { for x in body.velocity.iter_mut() { *x = read_f64(&mut reader)?; } }
+            if fields.contains(OutputField::Elements) { for _ in ELEMENT_NAMES.iter() { read_f64(&mut reader)?; } }
+
+            bodies.push(body);
+        }
+
+        frames.push(Snapshot { step: u64::from_le_bytes(step) as usize, time, bodies });
+    }
+
+    Recording::from_frames(frames, metadata)
+}
+
+fn read_u32(reader: &mut impl Read) -> std::io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_f64(reader: &mut impl Read) -> std::io::Result<f64> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(f64::from_bits(u64::from_le_bytes(bytes)))
+}
+
+fn read_checkpoint_series(files: &[String]) -> Result<Recording, Error> {
+    let mut recording = Recording::from_frames(vec![], None)?;
+
+    for file in files {
+        let checkpoint = Checkpoint::from_file(file)?;
+        match checkpoint.numeric_type.as_str() {
+            "I16F48" => add_checkpoint_frame::<I16F48>(&checkpoint, &mut recording)?,
+            "I64F64" => add_checkpoint_frame::<I64F64>(&checkpoint, &mut recording)?,
+            _ => add_checkpoint_frame::<f64>(&checkpoint, &mut recording)?
+        };
+    }
+
+    recording.frames.sort_by_key(|x| x.step);
+    Ok(recording)
+}
+
+// Appends the current state of a checkpoint, taking the system properties from the checkpoint itself
+fn add_checkpoint_frame<TNum>(checkpoint: &Checkpoint, recording: &mut Recording) -> Result<(), Error>
+    where TNum: Numeric + Add<Output = TNum> + Sub<Output = TNum> + Mul<Output = TNum> + Div<Output = TNum> + AddAssign + Sum {
+
+    let nbody = NBodySystem::<TNum>::from_checkpoint(checkpoint)?;
+    recording.frames.push(Snapshot::from_state(nbody.get_step_count(), nbody.get_clock().elapsed(),
+                                               &*nbody.get_current_state(), &[], None));
+
+    recording.units = nbody.get_units();
+    recording.gravitational_constant = nbody.get_gravitational_constant().into_f64();
+    recording.softening_constant = nbody.get_softening_constant().into_f64();
+    recording.epoch = nbody.get_clock().epoch();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::csv::CsvEncoder;
+    use super::super::jsonl::JsonLinesEncoder;
+    use super::super::binary::BinaryEncoder;
+    use super::super::format::SnapshotEncoder;
+
+    fn test_metadata() -> SnapshotMetadata {
+        SnapshotMetadata::new::<f64>(UnitSystem::PcMsunMyr, 0.0045, 0.01, "2020-01-01T00:00:00+00:00".to_string())
+    }
+
+    fn encode(encoder: &dyn SnapshotEncoder, snapshot: &Snapshot, fields: &FieldSet) -> Vec<u8> {
+        let mut data = vec![];
+        encoder.write_header(&mut data, fields, &test_metadata()).unwrap();
+        encoder.write_snapshot(&mut data, snapshot, fields).unwrap();
+        data
+    }
+
+    fn test_snapshot(ids: &[&str]) -> Snapshot {
+        Snapshot {
+            step: 3,
+            time: 0.5,
+            bodies: ids.iter().enumerate()
+                .map(|(i, id)| BodyRecord { id: id.to_string(), mass: i as f64, position: [1.0, 2.0, 3.0], velocity: [0.0; 3], elements: None })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn csv_ids_with_separators_and_quotes_round_trip() {
+        let ids = ["alpha, b", "say \"hi\"", "line\nbreak"];
+        let fields = FieldSet::new(vec![OutputField::Id, OutputField::Time, OutputField::Mass, OutputField::Position]);
+        let data = encode(&CsvEncoder, &test_snapshot(&ids), &fields);
+
+        let frames = read_csv(&data[..]).unwrap().frames;
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].bodies.iter().map(|x| x.id.as_str()).collect::<Vec<_>>(), ids.to_vec());
+        assert_eq!(frames[0].bodies[2].mass, 2.0);
+        assert_eq!(frames[0].bodies[2].position, [1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn metadata_round_trips_in_every_format() {
+        let snapshot = test_snapshot(&["star", "planet"]);
+        let fields = FieldSet::all();
+        let recordings = vec![
+            read_csv(&encode(&CsvEncoder, &snapshot, &fields)[..]).unwrap(),
+            read_jsonl(&encode(&JsonLinesEncoder, &snapshot, &fields)[..]).unwrap(),
+            read_binary(&encode(&BinaryEncoder, &snapshot, &fields)[..]).unwrap()
+        ];
+
+        for recording in recordings {
+            assert_eq!(recording.frames.len(), 1);
+            assert_eq!(recording.frames[0].bodies[1].id, "planet");
+            assert_eq!(recording.units, UnitSystem::PcMsunMyr);
+            assert_eq!(recording.gravitational_constant, 0.0045);
+            assert_eq!(recording.softening_constant, 0.01);
+            assert_eq!(recording.epoch.to_rfc3339(), "2020-01-01T00:00:00+00:00");
+        }
+    }
+
+    #[test]
+    fn csv_without_metadata_uses_defaults() {
+        let recording = read_csv(&b"step,id,x,y,z\n1,star,0,0,0\n"[..]).unwrap();
+        assert_eq!(recording.frames[0].bodies[0].id, "star");
+        assert_eq!(recording.units, UnitSystem::default());
+        assert_eq!(recording.gravitational_constant, UnitSystem::default().gravitational_constant());
+    }
+
+    #[test]
+    fn binary_rejects_ids_beyond_length_prefix() {
+        let id = "x".repeat(u16::MAX as usize + 1);
+        let mut data = vec![];
+        let error = BinaryEncoder.write_snapshot(&mut data, &test_snapshot(&[&id]), &FieldSet::all()).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+}
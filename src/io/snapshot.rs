@@ -0,0 +1,113 @@
+use serde::*;
+use crate::core::types::{Numeric, Time};
+use crate::state::State;
+use crate::math::vec3::Vec3;
+use crate::orbital::osculating::{self, ElementsReference, OsculatingElements};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputField {
+    Id,
+    Time,
+    Mass,
+    Position,
+    Velocity,
+    Elements
+}
+
+impl OutputField {
+    pub const ALL: [OutputField; 6] = [OutputField::Id, OutputField::Time, OutputField::Mass,
+                                       OutputField::Position, OutputField::Velocity, OutputField::Elements];
+
+    pub fn parse(field: &str) -> Result<Self, String> {
+        match field.trim().to_lowercase().as_str() {
+            "id" => Ok(OutputField::Id),
+            "time" => Ok(OutputField::Time),
+            "mass" => Ok(OutputField::Mass),
+            "position" => Ok(OutputField::Position),
+            "velocity" => Ok(OutputField::Velocity),
+            "elements" => Ok(OutputField::Elements),
+            other => Err(format!("Unknown output field \"{}\"", other))
+        }
+    }
+
+    pub fn mask_bit(&self) -> u8 {
+        1 << (*self as u8)
+    }
+}
+
+// Set of fields written for each body
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldSet {
+    fields: Vec<OutputField>
+}
+
+impl FieldSet {
+    pub fn new(fields: Vec<OutputField>) -> Self {
+        Self { fields: OutputField::ALL.iter().cloned().filter(|x| fields.contains(x)).collect() }
+    }
+
+    pub fn all() -> Self { Self::new(OutputField::ALL.to_vec()) }
+
+    pub fn contains(&self, field: OutputField) -> bool { self.fields.contains(&field) }
+    pub fn fields(&self) -> &Vec<OutputField> { &self.fields }
+
+    pub fn to_mask(&self) -> u8 {
+        self.fields.iter().fold(0, |mask, x| mask | x.mask_bit())
+    }
+
+    pub fn from_mask(mask: u8) -> Self {
+        Self::new(OutputField::ALL.iter().cloned().filter(|x| mask & x.mask_bit() != 0).collect())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BodyRecord {
+    pub id: String,
+    pub mass: f64,
+    pub position: [f64; 3],
+    pub velocity: [f64; 3],
+    pub elements: Option<OsculatingElements>
+}
+
+// Selected bodies of a single state, converted for output
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub step: usize,
+    pub time: Time,
+    pub bodies: Vec<BodyRecord>
+}
+
+impl Snapshot {
+    // Elements are only computed when a gravitational constant and reference are given
+    pub fn from_state<TNum>(step: usize, time: Time, state: &State<TNum>, filter: &[String],
+                            elements: Option<(f64, ElementsReference)>) -> Self
+        where TNum: Numeric {
+
+        let bodies = (0..state.entity_count())
+            .filter(|&i| filter.is_empty() || filter.contains(state.id(i)))
+            .map(|i| BodyRecord {
+                id: state.id(i).clone(),
+                mass: state.mass(i).into_f64(),
+                position: *state.position(i).to_f64().get_data(),
+                velocity: *state.velocity(i).to_f64().get_data(),
+                elements: elements.and_then(|(gravitational_constant, reference)|
+                    osculating::compute_body_elements(state, gravitational_constant, i, reference))
+            })
+            .collect();
+
+        Self { step, time, bodies }
+    }
+
+    // Reconstructs a state from the recorded bodies; fields absent from the recording are zero
+    pub fn to_state<TNum>(&self) -> State<TNum>
+        where TNum: Numeric {
+        let mut state = State::new();
+        let vector = |x: &[f64; 3]| Vec3::new([TNum::from_f64(x[0]), TNum::from_f64(x[1]), TNum::from_f64(x[2])]);
+
+        self.bodies.iter().for_each(|body| state.add_entity(
+            body.id.clone(), TNum::from_f64(body.mass), vector(&body.position), vector(&body.velocity), Vec3::zero()));
+
+        state
+    }
+}
@@ -0,0 +1,115 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use crate::core::types::{Numeric, Time};
+use crate::state::State;
+use crate::orbital::osculating::ElementsReference;
+use super::format::{OutputFormat, SnapshotEncoder};
+use super::snapshot::{Snapshot, FieldSet, OutputField};
+use super::export::SnapshotMetadata;
+
+#[derive(Debug, Clone)]
+pub struct TrajectoryOptions {
+    pub path: String,                   // Base path; the format extension is appended
+    pub format: OutputFormat,
+    pub interval: usize,                // Write every k-th step
+    pub entities: Vec<String>,          // Ids of bodies to write; all bodies if empty
+    pub fields: FieldSet,
+    pub rotate_after: Option<usize>,    // Start a new file after this many snapshots
+    pub elements_reference: ElementsReference
+}
+
+impl TrajectoryOptions {
+    pub fn new(path: String, format: OutputFormat) -> Self {
+        Self {
+            path,
+            format,
+            interval: 1,
+            entities: vec![],
+            fields: FieldSet::all(),
+            rotate_after: None,
+            elements_reference: ElementsReference::Primary(0)
+        }
+    }
+}
+
+// Streams snapshots of every k-th state to trajectory files, rotating to a new numbered file once the
+// configured number of snapshots has been written.  Each file starts with the metadata of the system
+pub struct TrajectoryWriter {
+    options: TrajectoryOptions,
+    encoder: Box<dyn SnapshotEncoder>,
+    metadata: SnapshotMetadata,
+
+    writer: Option<BufWriter<File>>,
+    file_index: usize,
+    snapshots_in_file: usize
+}
+
+impl TrajectoryWriter {
+    pub fn create(options: &TrajectoryOptions, metadata: &SnapshotMetadata) -> std::io::Result<Self> {
+        let mut writer = Self {
+            encoder: options.format.create_encoder(),
+            options: options.clone(),
+            metadata: metadata.clone(),
+            writer: None,
+            file_index: 0,
+            snapshots_in_file: 0
+        };
+
+        writer.open_next_file()?;
+        Ok(writer)
+    }
+
+    pub fn current_file_path(&self) -> String {
+        match self.options.rotate_after {
+            None => format!("{}.{}", self.options.path, self.options.format.extension()),
+            Some(_) => format!("{}.{:04}.{}", self.options.path, self.file_index, self.options.format.extension())
+        }
+    }
+
+    fn open_next_file(&mut self) -> std::io::Result<()> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush()?;
+            self.file_index += 1;
+        }
+
+        let mut writer = BufWriter::new(File::create(self.current_file_path())?);
+        self.encoder.write_header(&mut writer, &self.options.fields, &self.metadata)?;
+
+        self.writer = Some(writer);
+        self.snapshots_in_file = 0;
+        Ok(())
+    }
+
+    pub fn record<TNum>(&mut self, step: usize, time: Time, state: &State<TNum>) -> std::io::Result<()>
+        where TNum: Numeric {
+        if step % self.options.interval.max(1) != 0 { return Ok(()); }
+
+        if self.options.rotate_after.map(|n| self.snapshots_in_file >= n.max(1)).unwrap_or(false) {
+            self.open_next_file()?;
+        }
+
+        let elements = if self.options.fields.contains(OutputField::Elements) {
+            Some((self.metadata.gravitational_constant, self.options.elements_reference))
+        } else { None };
+
+        let snapshot = Snapshot::from_state(step, time, state, &self.options.entities, elements);
+
+        let writer = self.writer.as_mut().expect("Trajectory writer has no open file");
+        self.encoder.write_snapshot(writer, &snapshot, &self.options.fields)?;
+        self.snapshots_in_file += 1;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        match self.writer.as_mut() {
+            Some(writer) => writer.flush(),
+            None => Ok(())
+        }
+    }
+}
+
+impl Drop for TrajectoryWriter {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
@@ -4,29 +4,187 @@ mod nbody;
 mod integrator;
 mod rendering;
 mod entities;
+mod ephemeris;
+mod frames;
+mod generators;
 mod io;
+mod orbital;
 mod state;
 mod text;
 mod util;
+mod units;
+mod cli;
 
 use ::core::cell::RefCell;
 use fixed::types::{I16F48, I64F64};
 use crate::core::simulation;
+use crate::core::replay::Replay;
+use crate::core::physics;
+use crate::rendering::style::BodyStyles;
+use crate::rendering::trails::TrailOptions;
 use shader_version::OpenGL;
-use crate::entities::system::System;
+use crate::entities::system::{System, LoadOptions};
+use crate::ephemeris::import::HorizonsImport;
 use std::ops::DerefMut;
+use crate::nbody::nbody_system::NBodySystem;
 
 fn main() {
-    let sys = System::from_file("resources/systems/test-system.json");
-    let mut nbody = nbody::nbody_system::NBodySystem::<f64>::new(&sys, 400);
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let command = cli::parse(&args).unwrap_or_else(|e| {
+        eprintln!("{}\n\n{}", e, cli::USAGE);
+        std::process::exit(1)
+    });
+
+    match command {
+        cli::Command::Run(options) => run(&options),
+        cli::Command::Generate { model, seed, system, output, params } => generate(&model, seed, system, output, params),
+        cli::Command::Replay { files, speed, trails } => replay(&files, speed, trails)
+    }
+}
+
+fn run(options: &cli::RunOptions) {
+    let load_options = if options.barycentric { LoadOptions::barycentric() } else { LoadOptions::default() };
+    let (mut nbody, styles) = match &options.restore {
+        Some(checkpoint) => {
+            let nbody = NBodySystem::<f64>::load_checkpoint(checkpoint)
+                .unwrap_or_else(|e| panic!("Cannot restore checkpoint ({})", e));
+            let styles = BodyStyles::from_checkpoint(nbody.get_displays());
+            (nbody, styles)
+        },
+        None => {
+            let system = match &options.horizons {
+                Some(import) => import_horizons(import, load_options),
+                None => System::from_file_with_options(&options.system, load_options)
+            };
+            (NBodySystem::<f64>::new(&system, options.history_length), BodyStyles::from_system(&system))
+        }
+    };
+
+    if let Some(scheme) = options.integrator {
+        nbody.set_integration_scheme(scheme);
+    }
+
+    if let Some(archive) = &options.archive {
+        if archive.spill_path.is_none() {
+            println!("History archive holds the latest {} archived states; older states are discarded without --archive-spill",
+                     archive.memory_capacity);
+        }
+        nbody.enable_history_archive(archive.clone())
+            .unwrap_or_else(|e| panic!("Cannot enable history archive ({})", e));
+    }
+
+    if let Some(interval) = options.diagnostics_interval {
+        nbody.enable_diagnostics(interval, options.abort_threshold);
+    }
+
+    if options.headless {
+        return run_headless(nbody, options);
+    }
+
+    let mut simulation = simulation::Simulation::create(
+        simulation::BuildOptions {
+            gl_version: OpenGL::V4_5,
+            use_cache: false,
+            elements_output: options.elements_output.clone(),
+            trajectory_output: options.output.clone(),
+            snapshot_export: options.snapshot.clone(),
+            stop_at: options.until,
+            checkpoint: options.checkpoint.as_ref().map(|path| physics::CheckpointOptions {
+                path: path.clone(),
+                interval: options.checkpoint_interval.unwrap_or(10000)
+            }),
+            replay: None,
+            styles,
+            trails: options.trails
+        },
+        nbody
+    );
+
+    simulation.execute();
+}
+
+// Steps the system to the --until date without a window, then writes the final state to the --checkpoint file, if given
+fn run_headless(mut nbody: NBodySystem<f64>, options: &cli::RunOptions) {
+    let until = options.until.unwrap_or_else(|| panic!("Headless runs require a date to run until"));
+    let steps = nbody.run_until(&until, simulation::SIMULATION_TIMESTEP)
+        .unwrap_or_else(|e| panic!("Cannot run until {} ({})", until.to_rfc3339(), e));
+
+    println!("Ran {} steps to {}{}", steps, nbody.get_clock().format_current(), if nbody.is_aborted() { " (ABORTED)" } else { "" });
+    if let Some(diagnostics) = nbody.get_diagnostics() {
+        println!("Diagnostics at step {}: {}", nbody.get_step_count(), diagnostics.report());
+    }
+
+    if let Some(path) = &options.checkpoint {
+        nbody.save_checkpoint(path).unwrap_or_else(|e| panic!("Failed to save checkpoint ({})", e));
+        println!("Checkpoint saved to \"{}\" at step {}", path, nbody.get_step_count());
+    }
+}
+
+// Builds a system from the vector tables of a Horizons import description, which are resolved relative to it
+fn import_horizons(file: &str, load_options: LoadOptions) -> System {
+    let import = HorizonsImport::from_file(file);
+    let base_dir = std::path::Path::new(file).parent().unwrap_or_else(|| std::path::Path::new("."));
+
+    let mut system = import.build_system(base_dir)
+        .unwrap_or_else(|e| panic!("Cannot import Horizons system ({})", e));
+    system.set_load_options(load_options);
+    system
+}
+
+fn replay(files: &[String], speed: f64, trails: TrailOptions) {
+    let recording = io::reader::read_recording(files)
+        .unwrap_or_else(|e| panic!("Cannot read recording ({})", e));
+    let first_state = recording.frames.first()
+        .unwrap_or_else(|| panic!("Recording contains no frames"))
+        .to_state();
+    let styles = BodyStyles::from_ids(first_state.ids());
+
+    let mut nbody = NBodySystem::<f64>::new_from_params(
+        recording.gravitational_constant, recording.softening_constant, first_state, cli::DEFAULT_HISTORY_LENGTH);
+    nbody.set_units_and_epoch(recording.units, recording.epoch);
 
     let mut simulation = simulation::Simulation::create(
         simulation::BuildOptions {
             gl_version: OpenGL::V4_5,
-            use_cache: false
+            use_cache: false,
+            elements_output: None,
+            trajectory_output: None,
+            snapshot_export: None,
+            stop_at: None,
+            checkpoint: None,
+            replay: Some(Replay::new(recording, speed)),
+            styles,
+            trails
         },
         nbody
     );
 
     simulation.execute();
 }
+
+// Generates a system, or with a system file adds a belt to that system, keeping its own seed
+fn generate(model: &str, seed: u64, system: Option<String>, output: Option<String>, params: serde_json::Value) {
+    let system = match system {
+        Some(file) => {
+            let mut system = System::from_file(&file);
+            generators::add_belt(&mut system, seed, params)
+                .unwrap_or_else(|e| panic!("Cannot create belt ({})", e));
+            system
+        },
+        None => {
+            let generator = generators::from_name(model, params)
+                .unwrap_or_else(|e| panic!("Cannot create generator ({})", e));
+            let mut system = generator.generate(seed);
+            system.set_seed(Some(seed));
+            system
+        }
+    };
+
+    match output {
+        Some(file) => {
+            system.to_file(&file);
+            println!("Generated {} entities to \"{}\"", system.get_entities().len(), file);
+        },
+        None => println!("{}", serde_json::to_string_pretty(&system).expect("Failed to serialize system"))
+    }
+}
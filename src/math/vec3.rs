@@ -2,7 +2,7 @@ use std::ops::{Add, Sub, Mul, AddAssign};
 use core::iter::Sum;
 use crate::core::types::Numeric;
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct Vec3<T> {
     data: [T; 3]
 }
@@ -45,6 +45,11 @@ impl <T> Vec3<T> {
         &self.data
     }
 
+    pub fn to_f64(&self) -> Vec3<f64>
+        where T: Numeric {
+        Vec3::new([self.data[0].into_f64(), self.data[1].into_f64(), self.data[2].into_f64()])
+    }
+
     pub fn x(&self) -> T where T: Copy { self.data[0] }
     pub fn y(&self) -> T where T: Copy { self.data[1] }
     pub fn z(&self) -> T where T: Copy { self.data[2] }
@@ -57,6 +62,25 @@ impl <T> Vec3<T> {
         where T: Copy + Mul<Output = T> + Sum {
         self.data.iter().map(|&x| x * x).sum()
     }
+
+    pub fn length(&self) -> T
+        where T: Numeric + Mul<Output = T> + Sum {
+        self.length_sq().sq_root()
+    }
+
+    pub fn dot(&self, other: &Self) -> T
+        where T: Numeric + Add<Output = T> + Mul<Output = T> {
+        self.data[0] * other.data[0] + self.data[1] * other.data[1] + self.data[2] * other.data[2]
+    }
+
+    pub fn cross(&self, other: &Self) -> Self
+        where T: Numeric + Sub<Output = T> + Mul<Output = T> {
+        Self::new([
+            self.data[1] * other.data[2] - self.data[2] * other.data[1],
+            self.data[2] * other.data[0] - self.data[0] * other.data[2],
+            self.data[0] * other.data[1] - self.data[1] * other.data[0]
+        ])
+    }
 }
 
 impl <T> Clone for Vec3<T>
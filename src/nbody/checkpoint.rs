@@ -0,0 +1,245 @@
+use serde::*;
+use failure::{Error, format_err};
+use crate::core::types::Numeric;
+use crate::math::vec3::Vec3;
+use crate::state::State;
+use crate::units::UnitSystem;
+use crate::entities::display::EntityDisplay;
+
+pub const CHECKPOINT_FORMAT: &str = "nbody-checkpoint";
+pub const CHECKPOINT_VERSION: u32 = 1;
+
+// Serialised form of a complete NBodySystem.  Numeric values are stored as the hex encoding of their
+// raw bits, so that restoring a checkpoint reproduces the original system exactly for any numeric type
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub format: String,
+    pub version: u32,
+    pub numeric_type: String,
+
+    pub gravitational_constant: String,
+    pub softening_constant: String,
+    pub units: UnitSystem,
+
+    pub epoch: String,
+    pub elapsed_time: String,
+    pub step_count: usize,
+
+    pub state_cycles: usize,
+    pub current_state: usize,
+    pub states: Vec<StateCheckpoint>,
+
+    pub integrator: IntegratorCheckpoint,
+    #[serde(default)]
+    pub rng_seed: Option<u64>,
+    #[serde(default)]
+    pub displays: Vec<DisplayCheckpoint>
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StateCheckpoint {
+    pub id: Vec<String>,
+    pub mass: Vec<String>,
+    pub position: Vec<[String; 3]>,
+    pub velocity: Vec<[String; 3]>,
+    pub acceleration: Vec<[String; 3]>
+}
+
+// Declared display attributes and physical radius (in system units) of an entity of the loaded system, so that
+// a restored run is drawn as the original.  Every entity is listed, in load order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DisplayCheckpoint {
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display: Option<EntityDisplay>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub radius: Option<f64>
+}
+
+// Integrators carry no state between steps; this records which scheme produced the checkpoint
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IntegratorCheckpoint {
+    pub scheme: String
+}
+
+impl Checkpoint {
+    pub fn from_file(file: &str) -> Result<Self, Error> {
+        let data = std::fs::read_to_string(file)
+            .map_err(|e| format_err!("Failed to read checkpoint ({}): {}", file, e))?;
+        let checkpoint: Checkpoint = serde_json::from_str(data.as_str())
+            .map_err(|e| format_err!("Failed to deserialize checkpoint ({}): {}", file, e))?;
+
+        if checkpoint.format != CHECKPOINT_FORMAT {
+            return Err(format_err!("File ({}) is not a checkpoint", file));
+        }
+        if checkpoint.version > CHECKPOINT_VERSION {
+            return Err(format_err!("Checkpoint version {} is newer than supported version {}", checkpoint.version, CHECKPOINT_VERSION));
+        }
+
+        Ok(checkpoint)
+    }
+
+    // Writes to a temporary file which then replaces the target, so that an existing checkpoint survives a
+    // failure part-way through the write
+    pub fn to_file(&self, file: &str) -> Result<(), Error> {
+        let data = serde_json::to_string(self)?;
+        let temporary = format!("{}.tmp", file);
+
+        std::fs::write(&temporary, data)
+            .map_err(|e| format_err!("Failed to write checkpoint ({}): {}", temporary, e))?;
+        std::fs::rename(&temporary, file)
+            .map_err(|e| format_err!("Failed to replace checkpoint ({}): {}", file, e))
+    }
+
+    pub fn verify_numeric_type<TNum>(&self) -> Result<(), Error>
+        where TNum: Numeric {
+        if self.numeric_type != TNum::type_name() {
+            return Err(format_err!("Checkpoint holds {} values, cannot restore as {}", self.numeric_type, TNum::type_name()));
+        }
+
+        Ok(())
+    }
+}
+
+impl StateCheckpoint {
+    pub fn from_state<TNum>(state: &State<TNum>) -> Self
+        where TNum: Numeric {
+        Self {
+            id: state.ids().clone(),
+            mass: state.masses().iter().map(|&x| encode(x)).collect(),
+            position: state.positions().iter().map(encode_vec).collect(),
+            velocity: state.velocities().iter().map(encode_vec).collect(),
+            acceleration: state.accelerations().iter().map(encode_vec).collect()
+        }
+    }
+
+    pub fn to_state<TNum>(&self) -> Result<State<TNum>, Error>
+        where TNum: Numeric {
+        let decode_all = |values: &Vec<[String; 3]>| values.iter().map(decode_vec).collect::<Result<Vec<_>, Error>>();
+
+        Ok(State::from_parts(
+            self.id.clone(),
+            self.mass.iter().map(|x| decode(x)).collect::<Result<Vec<_>, Error>>()?,
+            decode_all(&self.position)?,
+            decode_all(&self.velocity)?,
+            decode_all(&self.acceleration)?))
+    }
+}
+
+pub fn encode<TNum>(x: TNum) -> String
+    where TNum: Numeric {
+    format!("{:032x}", x.to_raw_bits())
+}
+
+pub fn decode<TNum>(x: &str) -> Result<TNum, Error>
+    where TNum: Numeric {
+    u128::from_str_radix(x, 16)
+        .map(TNum::from_raw_bits)
+        .map_err(|_| format_err!("Invalid encoded value \"{}\"", x))
+}
+
+fn encode_vec<TNum>(v: &Vec3<TNum>) -> [String; 3]
+    where TNum: Numeric {
+    [encode(v.x()), encode(v.y()), encode(v.z())]
+}
+
+fn decode_vec<TNum>(v: &[String; 3]) -> Result<Vec3<TNum>, Error>
+    where TNum: Numeric {
+    Ok(Vec3::new([decode(&v[0])?, decode(&v[1])?, decode(&v[2])?]))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::{Add, Sub, Mul, Div, AddAssign};
+    use core::iter::Sum;
+    use fixed::types::{I16F48, I64F64};
+    use crate::core::types::Numeric;
+    use crate::integrator::IntegrationScheme;
+    use crate::math::vec3::Vec3;
+    use crate::nbody::nbody_system::NBodySystem;
+    use crate::state::State;
+    use super::Checkpoint;
+
+    const STATE_CYCLES: usize = 5;          // Short enough that the history wraps before and after the checkpoint
+    const STEPS_BEFORE: usize = 40;
+    const STEPS_AFTER: usize = 60;
+    const TIMESTEP: f64 = 0.01;
+
+    fn test_system<TNum>(scheme: IntegrationScheme) -> NBodySystem<TNum>
+        where TNum: Numeric + Add<Output = TNum> + Sub<Output = TNum> + Mul<Output = TNum> + Div<Output = TNum> + AddAssign + Sum {
+        let mut state = State::new();
+        state.add_entity("star".to_string(), TNum::from_f64(1.0), Vec3::from([0.0, 0.0, 0.0]), Vec3::from([0.0, 0.0, 0.0]), Vec3::zero());
+        state.add_entity("planet".to_string(), TNum::from_f64(1e-3), Vec3::from([1.0, 0.0, 0.0]), Vec3::from([0.0, 1.0, 0.0]), Vec3::zero());
+        state.add_entity("comet".to_string(), TNum::from_f64(1e-6), Vec3::from([0.0, -2.0, 0.1]), Vec3::from([0.6, 0.0, 0.05]), Vec3::zero());
+
+        let mut system = NBodySystem::new_from_params(TNum::from_f64(1.0), TNum::from_f64(1e-4), state, STATE_CYCLES);
+        system.set_integration_scheme(scheme);
+        system
+    }
+
+    fn run_steps<TNum>(system: &mut NBodySystem<TNum>, steps: usize)
+        where TNum: Numeric + Add<Output = TNum> + Sub<Output = TNum> + Mul<Output = TNum> + Div<Output = TNum> + AddAssign + Sum {
+        (0..steps).for_each(|_| system.step(TNum::from_f64(TIMESTEP)));
+    }
+
+    // Steps a system, checkpoints it through its serialised form, and continues the restored system.  Its
+    // checkpoint, holding the raw bits of every value in the history, must match that of an uninterrupted run
+    fn assert_restart_matches_uninterrupted<TNum>(scheme: IntegrationScheme)
+        where TNum: Numeric + Add<Output = TNum> + Sub<Output = TNum> + Mul<Output = TNum> + Div<Output = TNum> + AddAssign + Sum {
+        let mut uninterrupted = test_system::<TNum>(scheme);
+        run_steps(&mut uninterrupted, STEPS_BEFORE + STEPS_AFTER);
+
+        let mut interrupted = test_system::<TNum>(scheme);
+        run_steps(&mut interrupted, STEPS_BEFORE);
+        let data = serde_json::to_string(&interrupted.to_checkpoint()).unwrap();
+        let checkpoint: Checkpoint = serde_json::from_str(&data).unwrap();
+
+        let mut restored = NBodySystem::<TNum>::from_checkpoint(&checkpoint).unwrap();
+        run_steps(&mut restored, STEPS_AFTER);
+
+        assert_eq!(restored.get_step_count(), STEPS_BEFORE + STEPS_AFTER);
+        assert_eq!(serde_json::to_string(&restored.to_checkpoint()).unwrap(),
+                   serde_json::to_string(&uninterrupted.to_checkpoint()).unwrap(),
+                   "Restored {} system diverged from uninterrupted run", TNum::type_name());
+    }
+
+    #[test]
+    fn restart_matches_uninterrupted_f64() {
+        assert_restart_matches_uninterrupted::<f64>(IntegrationScheme::Euler);
+        assert_restart_matches_uninterrupted::<f64>(IntegrationScheme::Leapfrog);
+    }
+
+    #[test]
+    fn restart_matches_uninterrupted_i16f48() {
+        assert_restart_matches_uninterrupted::<I16F48>(IntegrationScheme::Euler);
+        assert_restart_matches_uninterrupted::<I16F48>(IntegrationScheme::Leapfrog);
+    }
+
+    #[test]
+    fn restart_matches_uninterrupted_i64f64() {
+        assert_restart_matches_uninterrupted::<I64F64>(IntegrationScheme::Euler);
+        assert_restart_matches_uninterrupted::<I64F64>(IntegrationScheme::Leapfrog);
+    }
+
+    #[test]
+    fn checkpoint_file_replaces_previous_checkpoint() {
+        let file = std::env::temp_dir().join(format!("checkpoint-test-{}.json", std::process::id()));
+        let file = file.to_str().unwrap();
+
+        let mut system = test_system::<f64>(IntegrationScheme::Euler);
+        system.save_checkpoint(file).unwrap();
+        run_steps(&mut system, STEPS_BEFORE);
+        system.save_checkpoint(file).unwrap();
+
+        let restored = NBodySystem::<f64>::load_checkpoint(file).unwrap();
+        assert_eq!(restored.get_step_count(), STEPS_BEFORE);
+        assert!(!std::path::Path::new(&format!("{}.tmp", file)).exists());
+        std::fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn checkpoint_restore_rejects_other_numeric_type() {
+        let checkpoint = test_system::<f64>(IntegrationScheme::Euler).to_checkpoint();
+        assert!(NBodySystem::<I64F64>::from_checkpoint(&checkpoint).is_err());
+    }
+}
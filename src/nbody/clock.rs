@@ -0,0 +1,49 @@
+use chrono::{DateTime, Utc, Duration};
+use crate::core::types::Time;
+use crate::units::UnitSystem;
+use crate::util::temporal;
+
+// Tracks simulated time from a calendar start epoch.  Elapsed time is held in the time unit of the
+// owning system
+#[derive(Debug, Clone)]
+pub struct SimulationClock {
+    epoch: DateTime<Utc>,
+    elapsed: Time,
+    time_unit_secs: f64
+}
+
+impl SimulationClock {
+    pub fn new(epoch: DateTime<Utc>, units: &UnitSystem) -> Self {
+        Self { epoch, elapsed: 0.0, time_unit_secs: units.time_si() }
+    }
+
+    pub fn advance(&mut self, dt: Time) {
+        self.elapsed += dt;
+    }
+
+    pub fn set_elapsed(&mut self, elapsed: Time) {
+        self.elapsed = elapsed;
+    }
+
+    pub fn epoch(&self) -> DateTime<Utc> { self.epoch }
+    pub fn elapsed(&self) -> Time { self.elapsed }
+
+    pub fn current_datetime(&self) -> DateTime<Utc> {
+        let secs = self.elapsed * self.time_unit_secs;
+        self.epoch + Duration::milliseconds((secs * 1e3) as i64)
+    }
+
+    pub fn current_julian_date(&self) -> f64 {
+        temporal::julian_date_from_datetime(&self.epoch) + self.elapsed * self.time_unit_secs / 86400.0
+    }
+
+    // Simulated time remaining until the given date, in system time units (negative if already passed)
+    pub fn time_until(&self, target: &DateTime<Utc>) -> Time {
+        let target_secs = (temporal::julian_date_from_datetime(target) - temporal::julian_date_from_datetime(&self.epoch)) * 86400.0;
+        target_secs / self.time_unit_secs - self.elapsed
+    }
+
+    pub fn format_current(&self) -> String {
+        format!("{} (JD {:.4})", self.current_datetime().format("%Y-%m-%d %H:%M:%S UTC"), self.current_julian_date())
+    }
+}
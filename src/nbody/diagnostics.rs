@@ -0,0 +1,145 @@
+use crate::core::types::{Numeric, Time};
+use crate::math::vec3::Vec3;
+use crate::state::State;
+use crate::frames;
+
+// Conserved quantities of a single state
+#[derive(Debug, Clone)]
+pub struct Diagnostics {
+    pub kinetic_energy: f64,
+    pub potential_energy: f64,
+    pub linear_momentum: Vec3<f64>,
+    pub angular_momentum: Vec3<f64>,
+    pub centre_of_mass: Vec3<f64>,
+    pub momentum_scale: f64         // Sum of |m.v|, used to normalise momentum errors
+}
+
+impl Diagnostics {
+    pub fn from_state<TNum>(state: &State<TNum>, gravitational_constant: f64, softening_constant: f64) -> Self
+        where TNum: Numeric {
+
+        let n = state.entity_count();
+        let mass = |i: usize| state.mass(i).into_f64();
+        let (pos, vel): (Vec<Vec3<f64>>, Vec<Vec3<f64>>) = (0..n)
+            .map(|i| (state.position(i).to_f64(), state.velocity(i).to_f64()))
+            .unzip();
+
+        let kinetic_energy = (0..n).map(|i| 0.5 * mass(i) * vel[i].length_sq()).sum();
+        let potential_energy = (0..n)
+            .flat_map(|i| (0..i).map(move |j| (i, j)))
+            .map(|(i, j)| -gravitational_constant * mass(i) * mass(j) * Self::softened_inverse_distance(&pos[i], &pos[j], softening_constant))
+            .sum();
+
+        let linear_momentum = (0..n).fold(Vec3::zero(), |p, i| p + vel[i].scale(mass(i)));
+        let angular_momentum = (0..n).fold(Vec3::zero(), |l, i| l + pos[i].cross(&vel[i]).scale(mass(i)));
+        let momentum_scale = (0..n).map(|i| mass(i) * vel[i].length()).sum();
+        let (_, centre_of_mass, _) = frames::system_centre_of_mass(state);
+
+        Self { kinetic_energy, potential_energy, linear_momentum, angular_momentum, centre_of_mass, momentum_scale }
+    }
+
+    // Potential kernel matching the softened force law F = G.m1.m2 / (r.sqrt(r^2 + eps)), which integrates
+    // to asinh(sqrt(eps) / r) / sqrt(eps) and reduces to 1/r as eps -> 0
+    fn softened_inverse_distance(p0: &Vec3<f64>, p1: &Vec3<f64>, softening_constant: f64) -> f64 {
+        let r = (p1 - p0).length();
+        if softening_constant > 0.0 {
+            let s = softening_constant.sqrt();
+            (s / r).asinh() / s
+        } else {
+            1.0 / r
+        }
+    }
+
+    pub fn total_energy(&self) -> f64 {
+        self.kinetic_energy + self.potential_energy
+    }
+}
+
+// Errors of a state's conserved quantities relative to the initial state
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RelativeErrors {
+    pub energy: f64,
+    pub linear_momentum: f64,
+    pub angular_momentum: f64,
+    pub centre_of_mass_drift: f64       // Absolute distance from the initial centre of mass
+}
+
+impl RelativeErrors {
+    pub fn between(initial: &Diagnostics, current: &Diagnostics) -> Self {
+        let relative = |delta: f64, scale: f64| if scale != 0.0 { delta / scale.abs() } else { delta };
+
+        Self {
+            energy: relative(current.total_energy() - initial.total_energy(), initial.total_energy()).abs(),
+            linear_momentum: relative((&current.linear_momentum - &initial.linear_momentum).length(), initial.momentum_scale),
+            angular_momentum: relative((&current.angular_momentum - &initial.angular_momentum).length(), initial.angular_momentum.length()),
+            centre_of_mass_drift: (&current.centre_of_mass - &initial.centre_of_mass).length()
+        }
+    }
+
+    pub fn max_conservation_error(&self) -> f64 {
+        self.energy.max(self.linear_momentum).max(self.angular_momentum)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DiagnosticsRecord {
+    pub step: usize,
+    pub time: Time,
+    pub diagnostics: Diagnostics,
+    pub errors: RelativeErrors
+}
+
+// Records diagnostics every 'interval' steps, flagging the run as failed if any relative conservation
+// error exceeds the abort threshold.  Only the baseline and latest records are kept, so that long runs
+// do not accumulate them
+pub struct DiagnosticsTracker {
+    interval: usize,
+    abort_threshold: Option<f64>,
+    baseline: Option<DiagnosticsRecord>,
+    latest: Option<DiagnosticsRecord>,
+    aborted: bool
+}
+
+impl DiagnosticsTracker {
+    pub fn new(interval: usize, abort_threshold: Option<f64>) -> Self {
+        Self { interval: interval.max(1), abort_threshold, baseline: None, latest: None, aborted: false }
+    }
+
+    pub fn should_record(&self, step: usize) -> bool {
+        self.baseline.is_none() || step % self.interval == 0
+    }
+
+    pub fn record<TNum>(&mut self, step: usize, time: Time, state: &State<TNum>, gravitational_constant: f64, softening_constant: f64)
+        where TNum: Numeric {
+
+        let diagnostics = Diagnostics::from_state(state, gravitational_constant, softening_constant);
+        let baseline = self.baseline.get_or_insert_with(|| DiagnosticsRecord {
+            step, time, diagnostics: diagnostics.clone(), errors: RelativeErrors::default()
+        });
+        let errors = RelativeErrors::between(&baseline.diagnostics, &diagnostics);
+
+        if let Some(threshold) = self.abort_threshold {
+            if errors.max_conservation_error() > threshold { self.aborted = true; }
+        }
+
+        self.latest = Some(DiagnosticsRecord { step, time, diagnostics, errors });
+    }
+
+    // Measures subsequent errors against the next recorded state, e.g. after the system has been edited
+    pub fn rebase(&mut self) {
+        self.baseline = None;
+    }
+
+    pub fn is_aborted(&self) -> bool { self.aborted }
+    pub fn baseline(&self) -> Option<&DiagnosticsRecord> { self.baseline.as_ref() }
+    pub fn latest(&self) -> Option<&DiagnosticsRecord> { self.latest.as_ref() }
+
+    pub fn report(&self) -> String {
+        match self.latest() {
+            None => "No diagnostics recorded".to_string(),
+            Some(x) => format!("dE/E = {:.3e}, dP = {:.3e}, dL/L = {:.3e}, COM drift = {:.3e}{}",
+                               x.errors.energy, x.errors.linear_momentum, x.errors.angular_momentum,
+                               x.errors.centre_of_mass_drift, if self.aborted { " (ABORTED)" } else { "" })
+        }
+    }
+}
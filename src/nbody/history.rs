@@ -0,0 +1,319 @@
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use crate::core::types::{Numeric, Time};
+use crate::math::vec3::Vec3;
+use crate::state::State;
+use crate::io::binary::id_length;
+
+// Step and simulation time at which a state was produced
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HistoryStamp {
+    pub step: usize,
+    pub time: Time
+}
+
+#[derive(Debug, Clone)]
+pub struct HistoryRecord<TNum>
+    where TNum: Numeric {
+    pub stamp: HistoryStamp,
+    pub state: State<TNum>
+}
+
+#[derive(Debug, Clone)]
+pub struct ArchiveOptions {
+    pub interval: usize,                // Archive every k-th state evicted from the in-memory history
+    pub memory_capacity: usize,         // Archived states held in memory before spilling to disk
+    pub spill_path: Option<String>      // Disk-backed store for older states; discarded if not set
+}
+
+impl Default for ArchiveOptions {
+    fn default() -> Self {
+        Self { interval: 10, memory_capacity: 2000, spill_path: None }
+    }
+}
+
+// Older tiers of the state history.  States evicted from the full-resolution in-memory history are
+// down-sampled into a bounded in-memory buffer, and from there spilled to an append-only file holding the
+// raw bits of each value, so that archived states are recovered exactly
+pub struct HistoryArchive<TNum>
+    where TNum: Numeric {
+    options: ArchiveOptions,
+    memory: VecDeque<HistoryRecord<TNum>>,
+    spill: Option<SpillStore>
+}
+
+struct SpillStore {
+    path: String,
+    file: File,
+    index: Vec<(HistoryStamp, u64)>,    // Stamp and file offset of each spilled state
+    length: u64
+}
+
+impl<TNum> HistoryArchive<TNum>
+    where TNum: Numeric {
+
+    pub fn new(options: ArchiveOptions) -> std::io::Result<Self> {
+        let spill = match &options.spill_path {
+            None => None,
+            Some(path) => Some(SpillStore {
+                path: path.clone(),
+                file: OpenOptions::new().create(true).write(true).truncate(true).open(path)?,
+                index: vec![],
+                length: 0
+            })
+        };
+
+        Ok(Self { options, memory: VecDeque::new(), spill })
+    }
+
+    // Holds the given states, oldest first, in memory only; such as those received to display a system running elsewhere
+    pub fn from_records(records: Vec<HistoryRecord<TNum>>) -> Self {
+        let options = ArchiveOptions { interval: 1, memory_capacity: records.len(), spill_path: None };
+        Self { options, memory: records.into(), spill: None }
+    }
+
+    // Offers a state leaving the in-memory history, which is archived if it falls on the sampling interval
+    pub fn offer(&mut self, stamp: HistoryStamp, state: &State<TNum>) -> std::io::Result<()> {
+        if stamp.step % self.options.interval.max(1) != 0 { return Ok(()); }
+        if self.latest_stamp().map(|x| x.step >= stamp.step).unwrap_or(false) { return Ok(()); }
+
+        self.memory.push_back(HistoryRecord { stamp, state: state.clone() });
+
+        while self.memory.len() > self.options.memory_capacity {
+            let oldest = self.memory.pop_front().expect("Archive memory is empty");
+            if let Some(spill) = self.spill.as_mut() {
+                spill.append(&oldest)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // All archived states with a time in the range [start end], oldest first
+    pub fn range(&self, start: Time, end: Time) -> std::io::Result<Vec<HistoryRecord<TNum>>> {
+        let in_range = |stamp: &HistoryStamp| stamp.time >= start && stamp.time <= end;
+
+        let mut records = match &self.spill {
+            Some(spill) => spill.read(|stamp| in_range(stamp))?,
+            None => vec![]
+        };
+
+        records.extend(self.memory.iter().filter(|x| in_range(&x.stamp)).cloned());
+        Ok(records)
+    }
+
+    // Discards all archived states after the given step
+    pub fn truncate_after(&mut self, step: usize) -> std::io::Result<()> {
+        self.memory.retain(|x| x.stamp.step <= step);
+        if let Some(spill) = self.spill.as_mut() {
+            spill.truncate_after(step)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn memory_count(&self) -> usize { self.memory.len() }
+    pub fn spilled_count(&self) -> usize { self.spill.as_ref().map(|x| x.index.len()).unwrap_or(0) }
+
+    fn latest_stamp(&self) -> Option<HistoryStamp> {
+        self.memory.back().map(|x| x.stamp)
+            .or_else(|| self.spill.as_ref().and_then(|x| x.index.last()).map(|x| x.0))
+    }
+}
+
+impl SpillStore {
+    fn append<TNum>(&mut self, record: &HistoryRecord<TNum>) -> std::io::Result<()>
+        where TNum: Numeric {
+        let data = encode_record(record)?;
+        self.file.write_all(&data)?;
+
+        self.index.push((record.stamp, self.length));
+        self.length += data.len() as u64;
+        Ok(())
+    }
+
+    fn read<TNum, F>(&self, include: F) -> std::io::Result<Vec<HistoryRecord<TNum>>>
+        where TNum: Numeric,
+              F: Fn(&HistoryStamp) -> bool {
+
+        let mut reader = BufReader::new(File::open(&self.path)?);
+        self.index.iter()
+            .filter(|(stamp, _)| include(stamp))
+            .map(|&(_, offset)| {
+                reader.seek(SeekFrom::Start(offset))?;
+                decode_record(&mut reader)
+            })
+            .collect()
+    }
+
+    fn truncate_after(&mut self, step: usize) -> std::io::Result<()> {
+        let retained = self.index.iter().position(|(stamp, _)| stamp.step > step).unwrap_or(self.index.len());
+        if retained < self.index.len() {
+            self.length = self.index[retained].1;
+            self.index.truncate(retained);
+
+            self.file.set_len(self.length)?;
+            self.file.seek(SeekFrom::Start(self.length))?;
+        }
+
+        Ok(())
+    }
+}
+
+// Record layout: step u64, time f64, entity count u32, then per entity the id (u16 length + UTF-8 bytes)
+// followed by the raw bits (u128) of mass, position, velocity and acceleration.  All little-endian
+fn encode_record<TNum>(record: &HistoryRecord<TNum>) -> std::io::Result<Vec<u8>>
+    where TNum: Numeric {
+    let state = &record.state;
+    let mut data = vec![];
+    data.extend_from_slice(&(record.stamp.step as u64).to_le_bytes());
+    data.extend_from_slice(&record.stamp.time.to_bits().to_le_bytes());
+    data.extend_from_slice(&(state.entity_count() as u32).to_le_bytes());
+
+    for i in 0..state.entity_count() {
+        let id = state.id(i);
+        data.extend_from_slice(&id_length(id)?.to_le_bytes());
+        data.extend_from_slice(id.as_bytes());
+
+        let vectors = [state.position(i), state.velocity(i), state.acceleration(i)];
+        std::iter::once(state.mass(i))
+            .chain(vectors.iter().flat_map(|v| v.get_data().iter().cloned()))
+            .for_each(|x| data.extend_from_slice(&x.to_raw_bits().to_le_bytes()));
+    }
+
+    Ok(data)
+}
+
+fn decode_record<TNum>(reader: &mut impl Read) -> std::io::Result<HistoryRecord<TNum>>
+    where TNum: Numeric {
+    let step = read_u64(reader)? as usize;
+    let time = f64::from_bits(read_u64(reader)?);
+
+    let mut count = [0u8; 4];
+    reader.read_exact(&mut count)?;
+
+    let mut state = State::new();
+    for _ in 0..u32::from_le_bytes(count) {
+        let mut length = [0u8; 2];
+        reader.read_exact(&mut length)?;
+        let mut id = vec![0u8; u16::from_le_bytes(length) as usize];
+        reader.read_exact(&mut id)?;
+        let id = String::from_utf8(id)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+
+        let x = (0..10)
+            .map(|_| read_u128(reader).map(TNum::from_raw_bits))
+            .collect::<std::io::Result<Vec<TNum>>>()?;
+
+        state.add_entity(id, x[0], Vec3::new([x[1], x[2], x[3]]), Vec3::new([x[4], x[5], x[6]]), Vec3::new([x[7], x[8], x[9]]));
+    }
+
+    Ok(HistoryRecord { stamp: HistoryStamp { step, time }, state })
+}
+
+fn read_u64(reader: &mut impl Read) -> std::io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_u128(reader: &mut impl Read) -> std::io::Result<u128> {
+    let mut bytes = [0u8; 16];
+    reader.read_exact(&mut bytes)?;
+    Ok(u128::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fixed::types::I64F64;
+
+    fn test_record<TNum>(step: usize) -> HistoryRecord<TNum>
+        where TNum: Numeric {
+        let x = TNum::from_f64(step as f64 / 3.0);
+        let mut state = State::new();
+        state.add_entity("star".to_string(), TNum::from_f64(1.0), Vec3::new([x, x, x]), Vec3::zero(), Vec3::zero());
+        state.add_entity("planet, b".to_string(), TNum::from_f64(1e-3), Vec3::new([x, TNum::from_f64(1.0), x]),
+                         Vec3::new([TNum::from_f64(0.1), x, TNum::from_f64(-0.2)]), Vec3::new([x, x, TNum::from_f64(1e-9)]));
+        HistoryRecord { stamp: HistoryStamp { step, time: step as f64 * 0.5 }, state }
+    }
+
+    fn assert_same<TNum>(a: &HistoryRecord<TNum>, b: &HistoryRecord<TNum>)
+        where TNum: Numeric {
+        assert_eq!(a.stamp, b.stamp);
+        assert_eq!(a.state.ids(), b.state.ids());
+
+        let bits = |v: &Vec3<TNum>| v.get_data().iter().map(|x| x.to_raw_bits()).collect::<Vec<_>>();
+        for i in 0..a.state.entity_count() {
+            assert_eq!(a.state.mass(i).to_raw_bits(), b.state.mass(i).to_raw_bits());
+            assert_eq!(bits(a.state.position(i)), bits(b.state.position(i)));
+            assert_eq!(bits(a.state.velocity(i)), bits(b.state.velocity(i)));
+            assert_eq!(bits(a.state.acceleration(i)), bits(b.state.acceleration(i)));
+        }
+    }
+
+    fn spill_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("history-test-{}-{}.bin", name, std::process::id()));
+        path.to_str().unwrap().to_string()
+    }
+
+    fn archive_steps<TNum>(archive: &HistoryArchive<TNum>) -> Vec<usize>
+        where TNum: Numeric {
+        archive.range(std::f64::MIN, std::f64::MAX).unwrap().iter().map(|x| x.stamp.step).collect()
+    }
+
+    #[test]
+    fn records_round_trip_exactly() {
+        let record = test_record::<I64F64>(7);
+        let data = encode_record(&record).unwrap();
+        let decoded = decode_record::<I64F64>(&mut &data[..]).unwrap();
+        assert_same(&record, &decoded);
+
+        let record = test_record::<f64>(7);
+        assert_same(&record, &decode_record::<f64>(&mut &encode_record(&record).unwrap()[..]).unwrap());
+    }
+
+    #[test]
+    fn range_reads_spilled_and_memory_states() {
+        let path = spill_path("range");
+        let mut archive = HistoryArchive::<f64>::new(ArchiveOptions { interval: 2, memory_capacity: 3, spill_path: Some(path.clone()) }).unwrap();
+        (0..20).for_each(|step| { let record = test_record(step); archive.offer(record.stamp, &record.state).unwrap(); });
+
+        assert_eq!((archive.memory_count(), archive.spilled_count()), (3, 7));
+        assert_eq!(archive_steps(&archive), (0..20).step_by(2).collect::<Vec<_>>());
+
+        // Steps 4 to 14 lie at times 2.0 to 7.0, spanning the spilled and in-memory states
+        let records = archive.range(2.0, 7.0).unwrap();
+        assert_eq!(records.iter().map(|x| x.stamp.step).collect::<Vec<_>>(), vec![4, 6, 8, 10, 12, 14]);
+        records.iter().for_each(|x| assert_same(x, &test_record(x.stamp.step)));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn appends_continue_after_truncation() {
+        let path = spill_path("truncate");
+        let mut archive = HistoryArchive::<f64>::new(ArchiveOptions { interval: 1, memory_capacity: 2, spill_path: Some(path.clone()) }).unwrap();
+        (0..10).for_each(|step| { let record = test_record(step); archive.offer(record.stamp, &record.state).unwrap(); });
+
+        archive.truncate_after(4).unwrap();
+        assert_eq!(archive_steps(&archive), vec![0, 1, 2, 3, 4]);
+        assert_eq!((archive.memory_count(), archive.spilled_count()), (0, 5));
+
+        // A branched run archives new states at the steps that were discarded
+        (5..9).for_each(|step| {
+            let mut record = test_record(step);
+            record.stamp.time += 100.0;
+            archive.offer(record.stamp, &record.state).unwrap();
+        });
+        assert_eq!(archive_steps(&archive), (0..9).collect::<Vec<_>>());
+
+        let records = archive.range(std::f64::MIN, std::f64::MAX).unwrap();
+        assert_eq!(records[4].stamp.time, 2.0);
+        assert_eq!(records[5].stamp.time, 102.5);
+        assert_same(&records[6], &{ let mut x = test_record(6); x.stamp.time += 100.0; x });
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1 +1,5 @@
-pub mod nbody_system;
\ No newline at end of file
+pub mod nbody_system;
+pub mod clock;
+pub mod diagnostics;
+pub mod checkpoint;
+pub mod history;
\ No newline at end of file
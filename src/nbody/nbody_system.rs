@@ -7,8 +7,17 @@ use crate::core::types::Numeric;
 use crate::math::vec3::Vec3;
 use crate::state::State;
 use crate::core::types::*;
-use crate::integrator::Integrator;
+use crate::integrator::{Integrator, IntegrationScheme};
 use crate::entities::system::System;
+use crate::entities::entity::Entity;
+use crate::units::UnitSystem;
+use crate::util::temporal;
+use super::clock::SimulationClock;
+use super::diagnostics::DiagnosticsTracker;
+use super::history::{HistoryArchive, HistoryStamp, HistoryRecord, ArchiveOptions};
+use super::checkpoint::{self, Checkpoint, StateCheckpoint, IntegratorCheckpoint, DisplayCheckpoint, CHECKPOINT_FORMAT, CHECKPOINT_VERSION};
+use failure::Error;
+use chrono::{DateTime, Utc};
 use failure::_core::cell::Ref;
 
 pub struct NBodySystem<TNum>
@@ -16,25 +25,42 @@ pub struct NBodySystem<TNum>
 
     gravitational_constant: TNum,   // Gravitational constant G
     softening_constant: TNum,       // Compensates for Newtonian mechanics treating objects as point masses
+    units: UnitSystem,
 
     state_cycles: usize,
     current_state: usize,
     states: Vec<RefCell<State<TNum>>>,
+    stamps: Vec<Option<HistoryStamp>>,         // Step and time of each in-memory state, where known
+    archive: Option<HistoryArchive<TNum>>,     // Down-sampled and disk-backed tiers of older history
+    archive_disabled: bool,                    // Set once the archive is disabled by a failure to write it
 
     step_count: usize,
-    integrator: Integrator<TNum>
+    clock: SimulationClock,
+    diagnostics: Option<DiagnosticsTracker>,
+    integrator: Integrator<TNum>,
+    rng_seed: Option<u64>,          // Seed of any random process used to build or drive the system
+    displays: Vec<DisplayCheckpoint>    // Declared display attributes of the loaded entities, carried into checkpoints
 }
 
 impl<TNum> NBodySystem<TNum>
     where TNum: Numeric + Add<Output = TNum> + Sub<Output = TNum> + Mul<Output = TNum> + Div<Output = TNum> + AddAssign + Sum {
 
     pub fn new(system: &System, state_cycles: usize) -> Self {
-        Self::new_from_params(
+        let mut nbody = Self::new_from_params(
             TNum::from_f64(system.get_gravitational_constant()),
             TNum::from_f64(system.get_softening_constant()),
             system.generate_state(),
             state_cycles
-        )
+        );
+
+        nbody.units = system.get_units();
+        nbody.clock = SimulationClock::new(system.get_epoch(), &nbody.units);
+        nbody.rng_seed = system.get_seed();
+        nbody.displays = system.get_entities().iter()
+            .map(|x| x.in_system_units(&nbody.units))
+            .map(|x| DisplayCheckpoint { id: x.id, display: x.display, radius: x.radius })
+            .collect();
+        nbody
     }
 
     pub fn new_from_params(gravitational_constant: TNum, softening_constant: TNum,
@@ -42,16 +68,171 @@ impl<TNum> NBodySystem<TNum>
         Self {
             gravitational_constant,
             softening_constant,
+            units: UnitSystem::default(),
 
             state_cycles,
             current_state: 0,
             states: NBodySystem::initialise_states(&initial_state, state_cycles),
+            stamps: NBodySystem::<TNum>::initialise_stamps(0, 0, 0.0, state_cycles),
+            archive: None,
+            archive_disabled: false,
 
             step_count: 0,
-            integrator: Integrator::new()
+            clock: SimulationClock::new(temporal::j2000_epoch(), &UnitSystem::default()),
+            diagnostics: None,
+            integrator: Integrator::new(),
+            rng_seed: None,
+            displays: vec![]
+        }
+    }
+
+    pub fn to_checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            format: CHECKPOINT_FORMAT.to_string(),
+            version: CHECKPOINT_VERSION,
+            numeric_type: TNum::type_name().to_string(),
+
+            gravitational_constant: checkpoint::encode(self.gravitational_constant),
+            softening_constant: checkpoint::encode(self.softening_constant),
+            units: self.units,
+
+            epoch: self.clock.epoch().to_rfc3339(),
+            elapsed_time: checkpoint::encode(self.clock.elapsed()),
+            step_count: self.step_count,
+
+            state_cycles: self.state_cycles,
+            current_state: self.current_state,
+            states: self.states.iter().map(|x| StateCheckpoint::from_state(&*x.borrow())).collect(),
+
+            integrator: IntegratorCheckpoint { scheme: self.integrator.scheme().name().to_string() },
+            rng_seed: self.rng_seed,
+            displays: self.displays.clone()
+        }
+    }
+
+    // Restores a system exactly as it was when checkpointed.  Diagnostics are not restored, and should be
+    // re-enabled if required
+    pub fn from_checkpoint(checkpoint: &Checkpoint) -> Result<Self, Error> {
+        checkpoint.verify_numeric_type::<TNum>()?;
+        if checkpoint.states.len() != checkpoint.state_cycles || checkpoint.current_state >= checkpoint.state_cycles {
+            return Err(failure::format_err!("Checkpoint state history is inconsistent"));
+        }
+
+        let epoch = temporal::parse_epoch(&checkpoint.epoch).map_err(|e| failure::format_err!("{}", e))?;
+        let mut clock = SimulationClock::new(epoch, &checkpoint.units);
+        clock.set_elapsed(checkpoint::decode(&checkpoint.elapsed_time)?);
+
+        Ok(Self {
+            gravitational_constant: checkpoint::decode(&checkpoint.gravitational_constant)?,
+            softening_constant: checkpoint::decode(&checkpoint.softening_constant)?,
+            units: checkpoint.units,
+
+            state_cycles: checkpoint.state_cycles,
+            current_state: checkpoint.current_state,
+            states: checkpoint.states.iter()
+                .map(|x| x.to_state().map(RefCell::new))
+                .collect::<Result<Vec<_>, Error>>()?,
+            stamps: NBodySystem::<TNum>::initialise_stamps(checkpoint.current_state, checkpoint.step_count,
+                                                           checkpoint::decode(&checkpoint.elapsed_time)?, checkpoint.state_cycles),
+            archive: None,
+            archive_disabled: false,
+
+            step_count: checkpoint.step_count,
+            clock,
+            diagnostics: None,
+            integrator: Integrator::with_scheme(IntegrationScheme::parse(&checkpoint.integrator.scheme)
+                .map_err(|e| failure::format_err!("{}", e))?),
+            rng_seed: checkpoint.rng_seed,
+            displays: checkpoint.displays.clone()
+        })
+    }
+
+    pub fn save_checkpoint(&self, file: &str) -> Result<(), Error> {
+        self.to_checkpoint().to_file(file)
+    }
+
+    pub fn load_checkpoint(file: &str) -> Result<Self, Error> {
+        Self::from_checkpoint(&Checkpoint::from_file(file)?)
+    }
+
+    // Sets the unit system and epoch of the clock, retaining the elapsed time
+    pub fn set_units_and_epoch(&mut self, units: UnitSystem, epoch: DateTime<Utc>) {
+        let elapsed = self.clock.elapsed();
+        self.units = units;
+        self.clock = SimulationClock::new(epoch, &units);
+        self.clock.set_elapsed(elapsed);
+    }
+
+    // Replaces the state history with the given records, oldest first, as if the system had been stepped
+    // through them.  Used to display recorded runs without integrating
+    pub fn load_history(&mut self, history: Vec<HistoryRecord<TNum>>) {
+        let (first, last) = match (history.first(), history.last()) {
+            (Some(first), Some(last)) => (first.state.clone(), last.stamp),
+            _ => return
+        };
+
+        let padding = self.state_cycles.saturating_sub(history.len());
+        let skip = history.len().saturating_sub(self.state_cycles);
+
+        self.stamps = std::iter::repeat(None).take(padding)
+            .chain(history.iter().skip(skip).map(|x| Some(x.stamp)))
+            .collect();
+        self.states = std::iter::repeat(first).take(padding)
+            .chain(history.into_iter().skip(skip).map(|x| x.state))
+            .map(RefCell::new)
+            .collect();
+
+        self.current_state = self.state_cycles - 1;
+        self.step_count = last.step;
+        self.clock.set_elapsed(last.time);
+    }
+
+    // Appends records, oldest first, to the in-memory history as if the system had been stepped through them
+    pub fn append_history(&mut self, history: Vec<HistoryRecord<TNum>>) {
+        for record in history {
+            self.advance_states();
+            *self.states[self.current_state].borrow_mut() = record.state;
+            self.stamps[self.current_state] = Some(record.stamp);
+
+            self.step_count = record.stamp.step;
+            self.clock.set_elapsed(record.stamp.time);
         }
     }
 
+    // Returns a copy of the system and its in-memory history, without any archive or diagnostics, which can
+    // be kept up to date with load_history and append_history to display a system running elsewhere
+    pub fn display_copy(&self) -> Self {
+        Self {
+            gravitational_constant: self.gravitational_constant,
+            softening_constant: self.softening_constant,
+            units: self.units,
+
+            state_cycles: self.state_cycles,
+            current_state: self.current_state,
+            states: self.states.iter().map(|x| RefCell::new(x.borrow().clone())).collect(),
+            stamps: self.stamps.clone(),
+            archive: None,
+            archive_disabled: false,
+
+            step_count: self.step_count,
+            clock: self.clock.clone(),
+            diagnostics: None,
+            integrator: Integrator::with_scheme(self.integrator.scheme()),
+            rng_seed: self.rng_seed,
+            displays: self.displays.clone()
+        }
+    }
+
+    pub fn get_rng_seed(&self) -> Option<u64> { self.rng_seed }
+    pub fn get_displays(&self) -> &[DisplayCheckpoint] { &self.displays }
+
+    // Only the stamp of the current state is known when a history is created or restored
+    fn initialise_stamps(current_state: usize, step: usize, time: Time, state_cycles: usize) -> Vec<Option<HistoryStamp>> {
+        (0..state_cycles)
+            .map(|i| if i == current_state { Some(HistoryStamp { step, time }) } else { None })
+            .collect()
+    }
+
     fn initialise_states(initial_state: &State<TNum>, state_cycles: usize) -> Vec<RefCell<State<TNum>>> {
         (0..state_cycles)
             .map(|_| initial_state.clone())
@@ -60,6 +241,7 @@ impl<TNum> NBodySystem<TNum>
     }
 
     pub fn step(&mut self, dt: TNum) {
+        self.archive_evicted_state();
         {
             let state = self.states[self.current_state_index()].borrow();
             let mut next = self.states[self.next_state_index()].borrow_mut();
@@ -68,12 +250,130 @@ impl<TNum> NBodySystem<TNum>
         }
 
         self.advance_states();
-        self.complete_step();
+        self.complete_step(dt);
+    }
+
+    // Steps the system until the simulation clock reaches the given date, in either direction, returning the number
+    // of steps taken.  Fails if the timestep does not move the clock towards the date
+    pub fn run_until(&mut self, target: &DateTime<Utc>, dt: TNum) -> Result<usize, Error> {
+        let step = dt.into_f64();
+        let remaining = self.clock.time_until(target);
+        if remaining != 0.0 && step * remaining <= 0.0 {
+            return Err(failure::format_err!("Timestep {} does not move the clock towards {}", step, target.to_rfc3339()));
+        }
+
+        let start = self.step_count;
+        while self.clock.time_until(target) * step > 0.0 && !self.is_aborted() {
+            self.step(dt);
+        }
+
+        Ok(self.step_count - start)
     }
 
     fn step_for_states(&self, dt: TNum, state: &State<TNum>, result: &mut State<TNum>) {
-        self.calculate_acceleration_systems(dt, state, result);
-        self.integrator.integrate(dt, state, result);
+        match self.integrator.scheme() {
+            IntegrationScheme::Euler => {
+                self.calculate_acceleration_systems(dt, state, result);
+                self.integrator.integrate(dt, state, result);
+            },
+            IntegrationScheme::Leapfrog => {
+                self.integrator.leapfrog_kick_drift(dt, state, result);
+                let accelerations = self.compute_accelerations(result);
+                *result.accelerations_mut() = accelerations;
+                self.integrator.leapfrog_kick(dt, result);
+            }
+        }
+    }
+
+    // Leapfrog requires the accelerations of the current state to be consistent with its positions, so these
+    // are re-evaluated when switching scheme
+    pub fn set_integration_scheme(&mut self, scheme: IntegrationScheme) {
+        self.integrator = Integrator::with_scheme(scheme);
+        if scheme == IntegrationScheme::Leapfrog {
+            let accelerations = self.compute_accelerations(&*self.get_current_state());
+            *self.states[self.current_state].borrow_mut().accelerations_mut() = accelerations;
+        }
+    }
+
+    pub fn get_integration_scheme(&self) -> IntegrationScheme { self.integrator.scheme() }
+
+    // The system may be run backwards, by stepping with a negative dt, only if the integrator is time-reversible
+    pub fn is_time_reversible(&self) -> bool { self.integrator.scheme().is_time_reversible() }
+
+    // Returns the system to an earlier state still held in the in-memory history, setting the step count and
+    // clock to those of that state.  All later states are discarded, so that subsequent steps branch a new run
+    pub fn rewind_to_step(&mut self, step: usize) -> Result<(), Error> {
+        let index = self.stamps.iter()
+            .position(|x| x.map(|x| x.step == step).unwrap_or(false))
+            .ok_or_else(|| failure::format_err!("State at step {} is no longer held in the history", step))?;
+        let stamp = self.stamps[index].expect("No stamp for rewound state");
+
+        // Later states are overwritten with the rewound state, as when the history is first created
+        let rewound = self.states[index].borrow().clone();
+        let mut slot = index;
+        while slot != self.current_state {
+            slot = self.determine_successor_state(slot);
+            *self.states[slot].borrow_mut() = rewound.clone();
+            self.stamps[slot] = None;
+        }
+
+        if let Some(archive) = self.archive.as_mut() {
+            archive.truncate_after(step)
+                .map_err(|e| failure::format_err!("Failed to truncate history archive ({})", e))?;
+        }
+
+        self.current_state = index;
+        self.step_count = stamp.step;
+        self.clock.set_elapsed(stamp.time);
+        Ok(())
+    }
+
+    // Rewinds by up to 'steps', limited to the earliest state held in memory.  Returns the number of steps rewound
+    pub fn rewind(&mut self, steps: usize) -> Result<usize, Error> {
+        let earliest = self.stamps.iter().filter_map(|x| x.map(|x| x.step)).min().unwrap_or(self.step_count);
+        let target = self.step_count.saturating_sub(steps).max(earliest);
+
+        let rewound = self.step_count - target;
+        self.rewind_to_step(target)?;
+        Ok(rewound)
+    }
+
+    // Applies an edit to the current state, e.g. adding, removing or modifying bodies.  If the number of bodies
+    // changes, the in-memory history is replaced by the edited state, as when the history is first created
+    pub fn edit_current_state<F>(&mut self, edit: F)
+        where F: FnOnce(&mut State<TNum>) {
+
+        let mut state = self.get_current_state().clone();
+        edit(&mut state);
+        *state.accelerations_mut() = self.compute_accelerations(&state);
+
+        if state.entity_count() != self.get_current_state().entity_count() {
+            let current = self.current_state;
+            for i in (0..self.state_cycles).filter(|&i| i != current) {
+                *self.states[i].borrow_mut() = state.clone();
+                self.stamps[i] = None;
+            }
+        }
+        *self.states[self.current_state].borrow_mut() = state;
+
+        if let Some(diagnostics) = self.diagnostics.as_mut() {
+            diagnostics.rebase();
+        }
+        self.record_diagnostics();
+    }
+
+    // Returns the current state as a system definition, with its epoch at the current simulation time
+    pub fn to_system(&self, id: String) -> System {
+        let state = self.get_current_state();
+        let entities = (0..state.entity_count())
+            .map(|i| Entity::new_cartesian(state.id(i).clone(), state.mass(i).into_f64(),
+                                           *state.position(i).to_f64().get_data(), *state.velocity(i).to_f64().get_data()))
+            .collect();
+
+        let mut system = System::new(id, self.units, Some(self.gravitational_constant.into_f64()), self.softening_constant.into_f64(),
+                                     Some(self.clock.current_datetime().to_rfc3339()), entities);
+        system.set_seed(self.rng_seed);
+        system
     }
 
    pub fn current_state_index(&self) -> usize {
@@ -97,22 +397,92 @@ impl<TNum> NBodySystem<TNum>
         self.current_state = self.next_state_index();
     }
 
-    fn complete_step(&mut self) {
+    fn complete_step(&mut self, dt: TNum) {
         self.step_count += 1;
+        self.clock.advance(dt.into_f64());
+        self.stamps[self.current_state] = Some(HistoryStamp { step: self.step_count, time: self.clock.elapsed() });
+        self.record_diagnostics();
+    }
+
+    // Enables tracking of conserved quantities every 'interval' steps, optionally flagging the run as aborted
+    // once any relative conservation error exceeds 'abort_threshold'
+    pub fn enable_diagnostics(&mut self, interval: usize, abort_threshold: Option<f64>) {
+        self.diagnostics = Some(DiagnosticsTracker::new(interval, abort_threshold));
+        self.record_diagnostics();
+    }
+
+    fn record_diagnostics(&mut self) {
+        let (step, time) = (self.step_count, self.clock.elapsed());
+        let (g, softening) = (self.gravitational_constant.into_f64(), self.softening_constant.into_f64());
+        let state = self.states[self.current_state].borrow();
+
+        if let Some(diagnostics) = self.diagnostics.as_mut() {
+            if diagnostics.should_record(step) {
+                diagnostics.record(step, time, &*state, g, softening);
+            }
+        }
+    }
+
+    // Retains older states beyond the in-memory history, down-sampled and optionally spilled to disk
+    pub fn enable_history_archive(&mut self, options: ArchiveOptions) -> Result<(), Error> {
+        self.archive = Some(HistoryArchive::new(options)
+            .map_err(|e| failure::format_err!("Failed to create history archive ({})", e))?);
+        Ok(())
+    }
+
+    pub fn get_history_archive(&self) -> Option<&HistoryArchive<TNum>> { self.archive.as_ref() }
+    pub fn is_archive_disabled(&self) -> bool { self.archive_disabled }
+
+    // Replaces the archive of a display copy with archived states, oldest first, received from the system it displays
+    pub fn load_archived_history(&mut self, records: Vec<HistoryRecord<TNum>>) {
+        self.archive = Some(HistoryArchive::from_records(records));
+    }
+
+    pub fn clear_archived_history(&mut self) {
+        self.archive = None;
+    }
+
+    // Passes the state about to be overwritten in the in-memory history to the archive.  The archive is disabled
+    // if it cannot be written, rather than halting the run
+    fn archive_evicted_state(&mut self) {
+        let next = self.next_state_index();
+        if let (Some(archive), Some(stamp)) = (self.archive.as_mut(), self.stamps[next]) {
+            if let Err(e) = archive.offer(stamp, &*self.states[next].borrow()) {
+                println!("Failed to archive state history ({}); the history archive is disabled", e);
+                self.archive = None;
+                self.archive_disabled = true;
+            }
+        }
+        self.stamps[next] = None;
+    }
+
+    pub fn get_diagnostics(&self) -> Option<&DiagnosticsTracker> { self.diagnostics.as_ref() }
+
+    pub fn is_aborted(&self) -> bool {
+        self.diagnostics.as_ref().map(DiagnosticsTracker::is_aborted).unwrap_or(false)
     }
 
     pub fn get_step_count(&self) -> usize {
         self.step_count
     }
 
+    pub fn get_clock(&self) -> &SimulationClock { &self.clock }
+
     pub fn get_max_state_history_length(&self) -> usize { self.state_cycles }
 
+    pub fn get_gravitational_constant(&self) -> TNum { self.gravitational_constant }
+    pub fn get_softening_constant(&self) -> TNum { self.softening_constant }
+    pub fn get_units(&self) -> UnitSystem { self.units }
+
     fn calculate_acceleration_systems(&self, dt: TNum, state: &State<TNum>, result: &mut State<TNum>) {
-        result.accelerations_mut().iter_mut()
-            .enumerate()
-            .for_each(|(i, acc)| {
+        *result.accelerations_mut() = self.compute_accelerations(state);
+    }
+
+    fn compute_accelerations(&self, state: &State<TNum>) -> Vectors<TNum> {
+        (0..state.entity_count())
+            .map(|i| {
                 let pos_i = state.position(i);
-                *acc = state.masses().iter().zip(state.positions())
+                state.masses().iter().zip(state.positions())
                     .enumerate()
                     .filter(|(j, _)| i != *j)
                     .map(|(_, (mass_j, pos_j))| {
@@ -124,8 +494,9 @@ impl<TNum> NBodySystem<TNum>
 
                         d_pos.scale(force)
                     })
-                    .fold(Vec3::zero(), |sum, x| sum + x);
-            });
+                    .fold(Vec3::zero(), |sum, x| sum + x)
+            })
+            .collect()
     }
 
     pub fn get_current_state(&self) -> Ref<'_, State<TNum>> {
@@ -146,4 +517,90 @@ impl<TNum> NBodySystem<TNum>
     pub fn get_full_state_history(&self) -> Vec<Ref<'_, State<TNum>>> {
         self.get_state_history(self.state_cycles)
     }
-}
\ No newline at end of file
+
+    // Step and time of each state returned by get_full_state_history, where known
+    pub fn get_full_state_stamps(&self) -> Vec<Option<HistoryStamp>> {
+        self.stamps.iter()
+            .rev()
+            .cycle()
+            .skip(self.state_cycles - self.current_state_index() - 1)
+            .take(self.state_cycles)
+            .cloned()
+            .collect()
+    }
+
+    // Returns each in-memory state with a known step and time, oldest first
+    pub fn get_history_records(&self) -> Vec<HistoryRecord<TNum>> {
+        let mut records: Vec<HistoryRecord<TNum>> = self.states.iter().zip(self.stamps.iter())
+            .filter_map(|(state, stamp)| stamp.map(|stamp| HistoryRecord { stamp, state: state.borrow().clone() }))
+            .collect();
+
+        records.sort_by_key(|x| x.stamp.step);
+        records
+    }
+
+    // Returns all retained states with a simulation time in the range [start end], oldest first, drawing on
+    // the in-memory history and each tier of the archive.  Archived states are down-sampled
+    pub fn get_state_history_range(&self, start: Time, end: Time) -> Result<Vec<HistoryRecord<TNum>>, Error> {
+        let mut records = match &self.archive {
+            Some(archive) => archive.range(start, end)
+                .map_err(|e| failure::format_err!("Failed to read history archive ({})", e))?,
+            None => vec![]
+        };
+
+        let earliest_recent = self.stamps.iter().filter_map(|x| x.map(|x| x.step)).min();
+        records.retain(|x| earliest_recent.map(|step| x.stamp.step < step).unwrap_or(true));
+
+        let mut recent: Vec<HistoryRecord<TNum>> = self.states.iter().zip(self.stamps.iter())
+            .filter_map(|(state, stamp)| stamp.filter(|x| x.time >= start && x.time <= end)
+                .map(|stamp| HistoryRecord { stamp, state: state.borrow().clone() }))
+            .collect();
+        recent.sort_by_key(|x| x.stamp.step);
+
+        records.extend(recent);
+        Ok(records)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    const TIMESTEP: f64 = 1e-4;
+
+    // A star and planet, with the clock in the default units, in which a day is about 27.4 steps
+    fn test_system() -> NBodySystem<f64> {
+        let mut state = State::new();
+        state.add_entity("star".to_string(), 1.0, Vec3::from([0.0, 0.0, 0.0]), Vec3::from([0.0, 0.0, 0.0]), Vec3::zero());
+        state.add_entity("planet".to_string(), 1e-3, Vec3::from([1.0, 0.0, 0.0]), Vec3::from([0.0, 2.0 * std::f64::consts::PI, 0.0]), Vec3::zero());
+        NBodySystem::new_from_params(39.478, 0.0, state, 4)
+    }
+
+    #[test]
+    fn run_until_steps_to_target_in_either_direction() {
+        let mut system = test_system();
+        let target = system.get_clock().epoch() + Duration::days(1);
+        let expected = (system.get_clock().time_until(&target) / TIMESTEP).ceil() as usize;
+
+        assert_eq!(system.run_until(&target, TIMESTEP).unwrap(), expected);
+        assert_eq!(system.get_step_count(), expected);
+        assert!(system.get_clock().time_until(&target) <= 0.0);
+        assert!(system.get_clock().time_until(&target) > -TIMESTEP);
+
+        let earlier = system.get_clock().epoch();
+        assert!(system.run_until(&earlier, -TIMESTEP).unwrap() >= expected);
+        assert!(system.get_clock().time_until(&earlier) >= 0.0);
+    }
+
+    #[test]
+    fn run_until_rejects_timestep_away_from_target() {
+        let mut system = test_system();
+        let target = system.get_clock().epoch() + Duration::days(1);
+
+        assert!(system.run_until(&target, -TIMESTEP).is_err());
+        assert!(system.run_until(&target, 0.0).is_err());
+        assert_eq!(system.get_step_count(), 0);
+        assert_eq!(system.get_clock().elapsed(), 0.0);
+    }
+}
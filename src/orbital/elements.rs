@@ -0,0 +1,21 @@
+// Classical Keplerian elements.  Angles are held in radians; distances and velocities are in the
+// units of the owning system
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OrbitalElements {
+    pub semi_major_axis: f64,
+    pub eccentricity: f64,
+    pub inclination: f64,
+    pub longitude_of_ascending_node: f64,
+    pub argument_of_periapsis: f64,
+    pub true_anomaly: f64
+}
+
+impl OrbitalElements {
+    pub fn semi_latus_rectum(&self) -> f64 {
+        self.semi_major_axis * (1.0 - self.eccentricity * self.eccentricity)
+    }
+
+    pub fn is_bound(&self) -> bool {
+        self.eccentricity < 1.0
+    }
+}
@@ -0,0 +1,178 @@
+use std::f64::consts::PI;
+use crate::math::vec3::Vec3;
+use super::elements::OrbitalElements;
+
+const KEPLER_TOLERANCE: f64 = 1e-14;
+const KEPLER_MAX_ITERATIONS: usize = 100;
+
+// Solves Kepler's equation M = E - e.sin(E) for the eccentric anomaly of a bound orbit
+pub fn eccentric_anomaly(mean_anomaly: f64, eccentricity: f64) -> f64 {
+    let m = normalise_angle(mean_anomaly);
+    let mut e_anom = if eccentricity < 0.8 { m } else { PI };
+
+    for _ in 0..KEPLER_MAX_ITERATIONS {
+        let delta = (e_anom - eccentricity * e_anom.sin() - m) / (1.0 - eccentricity * e_anom.cos());
+        e_anom -= delta;
+        if delta.abs() < KEPLER_TOLERANCE { break; }
+    }
+
+    e_anom
+}
+
+// Solves the hyperbolic equivalent M = e.sinh(H) - H for the hyperbolic anomaly of an unbound orbit
+pub fn hyperbolic_anomaly(mean_anomaly: f64, eccentricity: f64) -> f64 {
+    let mut h_anom = (mean_anomaly / eccentricity).asinh();
+
+    for _ in 0..KEPLER_MAX_ITERATIONS {
+        let delta = (eccentricity * h_anom.sinh() - h_anom - mean_anomaly) / (eccentricity * h_anom.cosh() - 1.0);
+        h_anom -= delta;
+        if delta.abs() < KEPLER_TOLERANCE { break; }
+    }
+
+    h_anom
+}
+
+pub fn mean_to_true_anomaly(mean_anomaly: f64, eccentricity: f64) -> f64 {
+    if eccentricity < 1.0 {
+        let e_anom = eccentric_anomaly(mean_anomaly, eccentricity);
+        2.0 * ((1.0 + eccentricity).sqrt() * (e_anom / 2.0).sin())
+            .atan2((1.0 - eccentricity).sqrt() * (e_anom / 2.0).cos())
+    }
+    else if eccentricity > 1.0 {
+        let h_anom = hyperbolic_anomaly(mean_anomaly, eccentricity);
+        2.0 * (((eccentricity + 1.0) / (eccentricity - 1.0)).sqrt() * (h_anom / 2.0).tanh()).atan()
+    }
+    else {
+        panic!("Mean anomaly is undefined for parabolic orbits; specify a true anomaly instead")
+    }
+}
+
+pub fn true_to_mean_anomaly(true_anomaly: f64, eccentricity: f64) -> f64 {
+    if eccentricity < 1.0 {
+        let e_anom = 2.0 * ((1.0 - eccentricity).sqrt() * (true_anomaly / 2.0).sin())
+            .atan2((1.0 + eccentricity).sqrt() * (true_anomaly / 2.0).cos());
+        normalise_angle(e_anom - eccentricity * e_anom.sin())
+    }
+    else if eccentricity > 1.0 {
+        let h_anom = 2.0 * (((eccentricity - 1.0) / (eccentricity + 1.0)).sqrt() * (true_anomaly / 2.0).tan()).atanh();
+        eccentricity * h_anom.sinh() - h_anom
+    }
+    else {
+        let d = (true_anomaly / 2.0).tan();
+        d + d * d * d / 3.0
+    }
+}
+
+// Converts orbital elements into a (position, velocity) pair relative to the primary, for the given
+// standard gravitational parameter mu = G(M + m)
+pub fn elements_to_state(elements: &OrbitalElements, mu: f64) -> (Vec3<f64>, Vec3<f64>) {
+    let p = elements.semi_latus_rectum();
+    if !(p > 0.0) {
+        panic!("Inconsistent orbital elements (a = {}, e = {})", elements.semi_major_axis, elements.eccentricity);
+    }
+
+    let (e, nu) = (elements.eccentricity, elements.true_anomaly);
+    let r = p / (1.0 + e * nu.cos());
+    let v_scale = (mu / p).sqrt();
+
+    // Position and velocity in the perifocal frame
+    let r_pf = [r * nu.cos(), r * nu.sin()];
+    let v_pf = [-v_scale * nu.sin(), v_scale * (e + nu.cos())];
+
+    let rotate = |v: [f64; 2]| rotate_from_perifocal(v, elements);
+    (rotate(r_pf), rotate(v_pf))
+}
+
+fn rotate_from_perifocal(v: [f64; 2], elements: &OrbitalElements) -> Vec3<f64> {
+    let (sin_o, cos_o) = elements.longitude_of_ascending_node.sin_cos();
+    let (sin_w, cos_w) = elements.argument_of_periapsis.sin_cos();
+    let (sin_i, cos_i) = elements.inclination.sin_cos();
+
+    let p = [cos_o * cos_w - sin_o * sin_w * cos_i, sin_o * cos_w + cos_o * sin_w * cos_i, sin_w * sin_i];
+    let q = [-cos_o * sin_w - sin_o * cos_w * cos_i, -sin_o * sin_w + cos_o * cos_w * cos_i, cos_w * sin_i];
+
+    Vec3::new([
+        p[0] * v[0] + q[0] * v[1],
+        p[1] * v[0] + q[1] * v[1],
+        p[2] * v[0] + q[2] * v[1]
+    ])
+}
+
+pub fn normalise_angle(angle: f64) -> f64 {
+    let a = angle % (2.0 * PI);
+    if a < 0.0 { a + 2.0 * PI } else { a }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orbital::osculating::OsculatingElements;
+
+    const TOLERANCE: f64 = 1e-10;
+
+    fn angle_difference(a: f64, b: f64) -> f64 {
+        let d = normalise_angle(a - b);
+        d.min(2.0 * PI - d)
+    }
+
+    #[test]
+    fn eccentric_anomaly_solves_keplers_equation() {
+        for &e in [0.0, 0.1, 0.5, 0.85, 0.99].iter() {
+            for &m in [0.1, 1.0, 2.5, PI, 4.0, 6.0].iter() {
+                let e_anom = eccentric_anomaly(m, e);
+                assert!(angle_difference(e_anom - e * e_anom.sin(), m) < TOLERANCE, "e = {}, M = {}", e, m);
+            }
+        }
+    }
+
+    #[test]
+    fn hyperbolic_anomaly_solves_keplers_equation() {
+        for &e in [1.01, 1.5, 3.0].iter() {
+            for &m in [-5.0, -0.5, 0.0, 0.3, 2.0, 20.0].iter() {
+                let h_anom = hyperbolic_anomaly(m, e);
+                assert!((e * h_anom.sinh() - h_anom - m).abs() < TOLERANCE, "e = {}, M = {}", e, m);
+            }
+        }
+    }
+
+    #[test]
+    fn mean_and_true_anomalies_round_trip() {
+        for &e in [0.0, 0.3, 0.9].iter() {
+            for &nu in [0.0, 0.5, 2.0, 3.5, 6.0].iter() {
+                assert!(angle_difference(mean_to_true_anomaly(true_to_mean_anomaly(nu, e), e), nu) < TOLERANCE, "e = {}, nu = {}", e, nu);
+            }
+        }
+
+        // Unbound orbits only reach true anomalies within the asymptotes
+        for &e in [1.2, 2.0].iter() {
+            for &nu in [-1.5, -0.2, 0.0, 0.7, 1.5].iter() {
+                assert!((mean_to_true_anomaly(true_to_mean_anomaly(nu, e), e) - nu).abs() < TOLERANCE, "e = {}, nu = {}", e, nu);
+            }
+        }
+    }
+
+    #[test]
+    fn elements_round_trip_through_state() {
+        let mu = 4.0 * PI * PI;
+        let orbits = [
+            OrbitalElements { semi_major_axis: 1.5, eccentricity: 0.3, inclination: 0.4, longitude_of_ascending_node: 1.2,
+                              argument_of_periapsis: 2.1, true_anomaly: 0.8 },
+            OrbitalElements { semi_major_axis: 0.7, eccentricity: 0.05, inclination: 2.5, longitude_of_ascending_node: 4.0,
+                              argument_of_periapsis: 0.3, true_anomaly: 5.0 },
+            OrbitalElements { semi_major_axis: -2.0, eccentricity: 1.4, inclination: 0.9, longitude_of_ascending_node: 0.6,
+                              argument_of_periapsis: 3.0, true_anomaly: 1.0 }
+        ];
+
+        for elements in orbits.iter() {
+            let (position, velocity) = elements_to_state(elements, mu);
+            let x = OsculatingElements::from_relative_state(&position, &velocity, mu).elements;
+
+            assert!((x.semi_major_axis - elements.semi_major_axis).abs() < TOLERANCE * elements.semi_major_axis.abs(), "{:?}", x);
+            assert!((x.eccentricity - elements.eccentricity).abs() < TOLERANCE, "{:?}", x);
+            assert!(angle_difference(x.inclination, elements.inclination) < TOLERANCE, "{:?}", x);
+            assert!(angle_difference(x.longitude_of_ascending_node, elements.longitude_of_ascending_node) < TOLERANCE, "{:?}", x);
+            assert!(angle_difference(x.argument_of_periapsis, elements.argument_of_periapsis) < TOLERANCE, "{:?}", x);
+            assert!(angle_difference(x.true_anomaly, elements.true_anomaly) < TOLERANCE, "{:?}", x);
+        }
+    }
+}
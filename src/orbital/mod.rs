@@ -0,0 +1,4 @@
+pub mod elements;
+pub mod kepler;
+pub mod osculating;
+pub mod series;
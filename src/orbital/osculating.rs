@@ -0,0 +1,190 @@
+use std::f64::consts::PI;
+use crate::core::types::Numeric;
+use crate::math::vec3::Vec3;
+use crate::state::State;
+use super::elements::OrbitalElements;
+use super::kepler;
+
+const ELEMENT_EPSILON: f64 = 1e-11;
+
+// Reference used when determining the osculating orbit of each body
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ElementsReference {
+    Primary(usize),     // Relative to a single primary body, by index
+    Jacobi              // Relative to the barycentre of all preceding bodies
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OsculatingElements {
+    pub elements: OrbitalElements,
+    pub mean_anomaly: f64,
+    pub period: Option<f64>     // Only defined for bound orbits
+}
+
+impl OsculatingElements {
+    pub fn from_relative_state(rel_pos: &Vec3<f64>, rel_vel: &Vec3<f64>, mu: f64) -> Self {
+        let r = rel_pos.length();
+        let v_sq = rel_vel.length_sq();
+        let r_dot_v = rel_pos.dot(rel_vel);
+
+        let h = rel_pos.cross(rel_vel);
+        let h_len = h.length();
+        let n = Vec3::new([-h.y(), h.x(), 0.0]);
+        let n_len = n.length();
+
+        let e_vec = rel_pos.scale((v_sq - mu / r) / mu) + rel_vel.scale(-r_dot_v / mu);
+        let e = e_vec.length();
+
+        let energy = v_sq / 2.0 - mu / r;
+        let a = if energy.abs() > ELEMENT_EPSILON { -mu / (2.0 * energy) } else { std::f64::INFINITY };
+
+        let inclination = clamped_acos(h.z() / h_len);
+        let equatorial = n_len < ELEMENT_EPSILON * h_len;
+        let circular = e < ELEMENT_EPSILON;
+
+        let longitude_of_ascending_node = if equatorial { 0.0 } else { kepler::normalise_angle(n.y().atan2(n.x())) };
+
+        let argument_of_periapsis = if circular { 0.0 }
+            else if equatorial {
+                let w = e_vec.y().atan2(e_vec.x());
+                kepler::normalise_angle(if h.z() < 0.0 { -w } else { w })
+            }
+            else {
+                let w = clamped_acos(n.dot(&e_vec) / (n_len * e));
+                if e_vec.z() < 0.0 { 2.0 * PI - w } else { w }
+            };
+
+        let true_anomaly = if !circular {
+                let nu = clamped_acos(e_vec.dot(rel_pos) / (e * r));
+                if r_dot_v < 0.0 { 2.0 * PI - nu } else { nu }
+            }
+            else if !equatorial {
+                // Argument of latitude, measured from the ascending node
+                let u = clamped_acos(n.dot(rel_pos) / (n_len * r));
+                if rel_pos.z() < 0.0 { 2.0 * PI - u } else { u }
+            }
+            else {
+                // True longitude
+                kepler::normalise_angle(rel_pos.y().atan2(rel_pos.x()))
+            };
+
+        let elements = OrbitalElements {
+            semi_major_axis: a,
+            eccentricity: e,
+            inclination,
+            longitude_of_ascending_node,
+            argument_of_periapsis,
+            true_anomaly
+        };
+
+        Self {
+            elements,
+            mean_anomaly: kepler::true_to_mean_anomaly(true_anomaly, e),
+            period: if e < 1.0 && a > 0.0 { Some(2.0 * PI * (a * a * a / mu).sqrt()) } else { None }
+        }
+    }
+}
+
+// Determines osculating elements for every body in the state.  Bodies with no defined orbit under the
+// given reference (the primary itself, or in Jacobi coordinates the first body and any body preceded only by
+// massless bodies) are returned as None
+pub fn compute_elements<TNum>(state: &State<TNum>, gravitational_constant: f64, reference: ElementsReference) -> Vec<Option<OsculatingElements>>
+    where TNum: Numeric {
+
+    (0..state.entity_count())
+        .map(|i| compute_body_elements(state, gravitational_constant, i, reference))
+        .collect()
+}
+
+pub fn compute_body_elements<TNum>(state: &State<TNum>, gravitational_constant: f64, index: usize,
+                                   reference: ElementsReference) -> Option<OsculatingElements>
+    where TNum: Numeric {
+
+    let (central_mass, central_pos, central_vel) = match reference {
+        ElementsReference::Primary(primary) if primary != index && primary < state.entity_count() => (
+            state.mass(primary).into_f64(),
+            state.position(primary).to_f64(),
+            state.velocity(primary).to_f64()
+        ),
+        ElementsReference::Jacobi if index > 0 => interior_barycentre(state, index)?,
+        _ => return None
+    };
+
+    let rel_pos = &state.position(index).to_f64() - &central_pos;
+    let rel_vel = &state.velocity(index).to_f64() - &central_vel;
+    if rel_pos.length_sq() == 0.0 { return None; }
+
+    let mu = gravitational_constant * (central_mass + state.mass(index).into_f64());
+    Some(OsculatingElements::from_relative_state(&rel_pos, &rel_vel, mu))
+}
+
+// Returns the total mass, position and velocity of the barycentre of all bodies preceding 'index', which is
+// undefined if their total mass is not positive, e.g. where they are test particles
+fn interior_barycentre<TNum>(state: &State<TNum>, index: usize) -> Option<(f64, Vec3<f64>, Vec3<f64>)>
+    where TNum: Numeric {
+
+    let total_mass: f64 = state.masses().iter().take(index).map(|m| m.into_f64()).sum();
+    if total_mass <= 0.0 { return None; }
+
+    let (pos, vel) = (0..index)
+        .fold((Vec3::zero(), Vec3::zero()), |(pos, vel), j| {
+            let weight = state.mass(j).into_f64() / total_mass;
+            (pos + state.position(j).to_f64().scale(weight), vel + state.velocity(j).to_f64().scale(weight))
+        });
+
+    Some((total_mass, pos, vel))
+}
+
+fn clamped_acos(x: f64) -> f64 {
+    x.max(-1.0).min(1.0).acos()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOLERANCE: f64 = 1e-10;
+
+    fn state(bodies: &[(f64, [f64; 3], [f64; 3])]) -> State<f64> {
+        let mut state = State::new();
+        for (i, &(mass, position, velocity)) in bodies.iter().enumerate() {
+            state.add_entity(format!("body-{}", i), mass, Vec3::new(position), Vec3::new(velocity), Vec3::zero());
+        }
+        state
+    }
+
+    #[test]
+    fn two_body_elements_at_periapsis() {
+        // Planet at the periapsis of an orbit with a = 2 and e = 0.5, inclined by 90 degrees about the x axis
+        let (g, star_mass, planet_mass): (f64, f64, f64) = (1.0, 1.0, 1e-3);
+        let mu = g * (star_mass + planet_mass);
+        let (a, e): (f64, f64) = (2.0, 0.5);
+        let periapsis_speed = (mu * (1.0 + e) / (a * (1.0 - e))).sqrt();
+        let state = state(&[(star_mass, [0.0; 3], [0.0; 3]), (planet_mass, [a * (1.0 - e), 0.0, 0.0], [0.0, 0.0, periapsis_speed])]);
+
+        for &reference in [ElementsReference::Primary(0), ElementsReference::Jacobi].iter() {
+            let el = compute_body_elements(&state, g, 1, reference).expect("No elements");
+            let x = &el.elements;
+
+            assert!((x.semi_major_axis - a).abs() < TOLERANCE);
+            assert!((x.eccentricity - e).abs() < TOLERANCE);
+            assert!((x.inclination - PI / 2.0).abs() < TOLERANCE);
+            assert!(x.longitude_of_ascending_node.abs() < TOLERANCE);
+            assert!(x.argument_of_periapsis.abs() < TOLERANCE);
+            assert!(x.true_anomaly.abs() < TOLERANCE && el.mean_anomaly.abs() < TOLERANCE);
+            assert!((el.period.expect("No period") - 2.0 * PI * (a * a * a / mu).sqrt()).abs() < TOLERANCE);
+        }
+
+        assert!(compute_body_elements(&state, g, 0, ElementsReference::Primary(0)).is_none());
+        assert!(compute_body_elements(&state, g, 0, ElementsReference::Jacobi).is_none());
+    }
+
+    #[test]
+    fn jacobi_elements_undefined_about_massless_bodies() {
+        let state = state(&[(0.0, [0.0; 3], [0.0; 3]), (1.0, [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]), (1e-3, [3.0, 0.0, 0.0], [0.0, 0.8, 0.0])]);
+        let elements = compute_elements(&state, 1.0, ElementsReference::Jacobi);
+
+        assert!(elements[1].is_none());
+        assert!(elements[2].map(|x| x.elements.semi_major_axis.is_finite() && x.elements.eccentricity.is_finite()).unwrap_or(false));
+    }
+}
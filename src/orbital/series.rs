@@ -0,0 +1,71 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use crate::core::types::{Numeric, Time};
+use crate::state::State;
+use crate::units::UnitSystem;
+use crate::units::quantity::Dimension;
+use crate::io::csv;
+use super::osculating::{self, ElementsReference};
+
+// Writes the osculating elements of every body to a CSV time series, at a fixed step interval
+pub struct ElementsSeriesWriter {
+    writer: BufWriter<File>,
+    interval: usize,
+    reference: ElementsReference,
+    gravitational_constant: f64,
+    units: UnitSystem,
+    output_units: UnitSystem
+}
+
+#[derive(Debug, Clone)]
+pub struct ElementsSeriesOptions {
+    pub path: String,
+    pub interval: usize,
+    pub reference: ElementsReference,
+    pub output_units: Option<UnitSystem>     // Defaults to the units of the system
+}
+
+impl ElementsSeriesWriter {
+    pub fn create(options: &ElementsSeriesOptions, gravitational_constant: f64, units: UnitSystem) -> std::io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(&options.path)?);
+        writeln!(writer, "step,time,id,semi_major_axis,eccentricity,inclination,longitude_of_ascending_node,\
+                          argument_of_periapsis,true_anomaly,mean_anomaly,period")?;
+
+        Ok(Self {
+            writer,
+            interval: options.interval.max(1),
+            reference: options.reference,
+            gravitational_constant,
+            units,
+            output_units: options.output_units.unwrap_or(units)
+        })
+    }
+
+    pub fn record<TNum>(&mut self, step: usize, time: Time, state: &State<TNum>) -> std::io::Result<()>
+        where TNum: Numeric {
+        if step % self.interval != 0 { return Ok(()); }
+
+        let (units, output_units) = (self.units, self.output_units);
+        let (length, duration) = (
+            |x: f64| units.convert(x, Dimension::Length, &output_units),
+            |x: f64| units.convert(x, Dimension::Time, &output_units));
+
+        let elements = osculating::compute_elements(state, self.gravitational_constant, self.reference);
+        for (i, el) in elements.iter().enumerate() {
+            if let Some(el) = el {
+                let x = &el.elements;
+                writeln!(self.writer, "{},{},{},{},{},{},{},{},{},{},{}",
+                         step, duration(time), csv::quote_field(state.id(i)), length(x.semi_major_axis), x.eccentricity,
+                         x.inclination.to_degrees(), x.longitude_of_ascending_node.to_degrees(),
+                         x.argument_of_periapsis.to_degrees(), x.true_anomaly.to_degrees(),
+                         el.mean_anomaly.to_degrees(), el.period.map(|p| duration(p).to_string()).unwrap_or_default())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
@@ -0,0 +1,182 @@
+use std::f64::consts::PI;
+use crate::core::types::Numeric;
+use crate::math::vec3::Vec3;
+use crate::state::State;
+use crate::frames;
+
+pub const DEFAULT_VIEW_HEIGHT: f64 = 57.2;
+const FIELD_OF_VIEW: f64 = PI / 4.0;
+const NEAR_PLANE_FRACTION: f64 = 0.01;      // Of the camera distance; points closer than this are not drawn
+const MIN_VIEW_HEIGHT: f64 = 1e-9;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    Perspective,
+    Orthographic
+}
+
+// Screen position of a point, in [0 1] window coordinates with y downwards, along with its distance in
+// front of the camera and the relative scale at which it appears
+#[derive(Debug, Clone, Copy)]
+pub struct ProjectedPoint {
+    pub screen: [f64; 2],
+    pub depth: f64,
+    pub scale: f64
+}
+
+// Point kept centred in the view when following part of the system
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CameraFocus {
+    Entity(usize),
+    Subsystem(usize),       // Barycentre of a body and the bodies within its Hill sphere about body 0
+    Barycentre              // Barycentre of the whole system
+}
+
+impl CameraFocus {
+    pub fn position<TNum>(&self, state: &State<TNum>) -> Option<Vec3<f64>>
+        where TNum: Numeric {
+        match *self {
+            CameraFocus::Entity(ix) if ix < state.entity_count() => Some(state.position(ix).to_f64()),
+            CameraFocus::Subsystem(ix) if ix < state.entity_count() =>
+                Some(frames::centre_of_mass(state, frames::subsystem(state, ix, 0).into_iter()).1),
+            CameraFocus::Barycentre => Some(frames::system_centre_of_mass(state).1),
+            _ => None
+        }
+    }
+
+    pub fn description<TNum>(&self, state: &State<TNum>) -> String
+        where TNum: Numeric {
+        let id = |ix: usize| if ix < state.entity_count() { state.id(ix).clone() } else { ix.to_string() };
+        match *self {
+            CameraFocus::Entity(ix) => format!("following {}", id(ix)),
+            CameraFocus::Subsystem(ix) => format!("following {} subsystem", id(ix)),
+            CameraFocus::Barycentre => "following barycentre".to_string()
+        }
+    }
+}
+
+// Orbiting camera looking at a target point.  The default orientation looks down the z axis onto the
+// xy plane, with +y up the screen.  'yaw' rotates the view about the world z axis and 'tilt' about the
+// screen's horizontal axis.  The view height is the world extent visible vertically through the target,
+// so that zooming behaves identically in either projection
+#[derive(Debug, Clone, PartialEq)]
+pub struct Camera {
+    target: Vec3<f64>,
+    view_height: f64,
+    yaw: f64,
+    tilt: f64,
+    projection: Projection,
+    aspect: f64
+}
+
+impl Camera {
+    pub fn new(aspect: f64) -> Self {
+        Self {
+            target: Vec3::zero(),
+            view_height: DEFAULT_VIEW_HEIGHT,
+            yaw: 0.0,
+            tilt: 0.0,
+            projection: Projection::Perspective,
+            aspect
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self { projection: self.projection, ..Self::new(self.aspect) };
+    }
+
+    pub fn target(&self) -> &Vec3<f64> { &self.target }
+    pub fn set_target(&mut self, target: Vec3<f64>) { self.target = target; }
+
+    pub fn view_height(&self) -> f64 { self.view_height }
+    pub fn view_width(&self) -> f64 { self.view_height * self.aspect }
+    pub fn set_view_height(&mut self, view_height: f64) { self.view_height = view_height.max(MIN_VIEW_HEIGHT); }
+
+    pub fn aspect(&self) -> f64 { self.aspect }
+    pub fn set_aspect(&mut self, aspect: f64) { self.aspect = aspect; }
+
+    pub fn projection(&self) -> Projection { self.projection }
+    pub fn toggle_projection(&mut self) {
+        self.projection = match self.projection {
+            Projection::Perspective => Projection::Orthographic,
+            Projection::Orthographic => Projection::Perspective
+        };
+    }
+
+    // Rotates the view by a drag across the given fraction of the screen; a full-width drag turns by 180 degrees
+    pub fn rotate(&mut self, drag: [f64; 2]) {
+        self.yaw = (self.yaw - drag[0] * PI) % (2.0 * PI);
+        self.tilt = (self.tilt - drag[1] * PI) % (2.0 * PI);
+    }
+
+    // Camera right, up and backward (towards the viewer) unit vectors in world space
+    pub fn basis(&self) -> (Vec3<f64>, Vec3<f64>, Vec3<f64>) {
+        let (sy, cy) = self.yaw.sin_cos();
+        let (st, ct) = self.tilt.sin_cos();
+
+        (Vec3::new([cy, sy, 0.0]),
+         Vec3::new([-sy * ct, cy * ct, st]),
+         Vec3::new([sy * st, -cy * st, ct]))
+    }
+
+    // Distance from the target at which the perspective view spans the view height
+    pub fn distance(&self) -> f64 {
+        self.view_height / (2.0 * (FIELD_OF_VIEW / 2.0).tan())
+    }
+
+    pub fn position(&self) -> Vec3<f64> {
+        let (_, _, back) = self.basis();
+        self.target.clone() + back.scale(self.distance())
+    }
+
+    pub fn project(&self, point: &Vec3<f64>) -> Option<ProjectedPoint> {
+        let (right, up, back) = self.basis();
+        let relative = point - &self.target;
+        let (x, y) = (relative.dot(&right), relative.dot(&up));
+        let depth = self.distance() - relative.dot(&back);
+
+        let scale = match self.projection {
+            Projection::Orthographic => 1.0,
+            Projection::Perspective if depth > self.distance() * NEAR_PLANE_FRACTION => self.distance() / depth,
+            Projection::Perspective => return None
+        };
+
+        Some(ProjectedPoint {
+            screen: [0.5 + x * scale / self.view_width(), 0.5 - y * scale / self.view_height],
+            depth,
+            scale
+        })
+    }
+
+    // World position under a screen point, on the plane through the target facing the camera
+    pub fn unproject(&self, screen: [f64; 2]) -> Vec3<f64> {
+        let (right, up, _) = self.basis();
+        self.target.clone()
+            + right.scale((screen[0] - 0.5) * self.view_width())
+            + up.scale((0.5 - screen[1]) * self.view_height)
+    }
+
+    // Moves the view by a fraction of the screen, so that content follows the cursor
+    pub fn pan(&mut self, pan: [f64; 2]) {
+        let (right, up, _) = self.basis();
+        self.target = self.target.clone()
+            + right.scale(pan[0] * self.view_width())
+            + up.scale(-pan[1] * self.view_height);
+    }
+
+    // Zooms in by the given factor, keeping the point under the given screen position fixed
+    pub fn zoom_at(&mut self, factor: f64, screen: [f64; 2]) {
+        let anchor = self.unproject(screen);
+        self.set_view_height(self.view_height / factor);
+
+        let drift = &anchor - &self.unproject(screen);
+        self.target = self.target.clone() + drift;
+    }
+
+    // Fits the view to a screen rectangle [x y width height], given in [0 1] window coordinates
+    pub fn zoom_to(&mut self, rect: &[f64; 4]) {
+        let centre = [rect[0] + rect[2] / 2.0, rect[1] + rect[3] / 2.0];
+        self.target = self.unproject(centre);
+        self.set_view_height(self.view_height * rect[2].abs().max(rect[3].abs()));
+    }
+}
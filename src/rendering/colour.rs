@@ -6,3 +6,73 @@ pub const GREEN: Rgba<u8> = Rgba([0, 255, 0, 255]);
 pub const BLUE: Rgba<u8> = Rgba([0, 0, 255, 255]);
 
 pub const COLOUR_SELECTION: [f32; 4] = [152.0/255.0, 250.0/255.0, 161.0/255.0, 0.5];
+pub const COLOUR_TIMELINE: [f32; 4] = [0.0, 1.0, 0.0, 0.35];
+pub const COLOUR_HIGHLIGHT: [f32; 4] = [1.0, 1.0, 0.0, 0.9];
+pub const COLOUR_OVERLAY: [f32; 4] = [0.8, 0.8, 0.8, 0.9];
+pub const COLOUR_GRID: [f32; 4] = [0.5, 0.5, 0.5, 0.2];
+pub const COLOUR_AXIS: [f32; 4] = [0.6, 0.6, 0.6, 0.45];
+pub const COLOUR_CONTOUR: [f32; 4] = [1.0, 1.0, 1.0, 0.8];
+pub const COLOUR_WARNING: [f32; 4] = [1.0, 0.3, 0.3, 1.0];
+
+// Distinct colours for bodies without a declared colour, assigned in turn
+pub const PALETTE_CATEGORICAL: [[f32; 4]; 10] = [
+    [0.12, 0.47, 0.71, 1.0], [1.00, 0.50, 0.05, 1.0], [0.17, 0.63, 0.17, 1.0], [0.84, 0.15, 0.16, 1.0],
+    [0.58, 0.40, 0.74, 1.0], [0.55, 0.34, 0.29, 1.0], [0.89, 0.47, 0.76, 1.0], [0.50, 0.50, 0.50, 1.0],
+    [0.74, 0.74, 0.13, 1.0], [0.09, 0.75, 0.81, 1.0]
+];
+
+// Sequential scale from low (dark blue) to high (yellow) values, for use with 'gradient'
+pub const PALETTE_VIRIDIS: [[f32; 4]; 5] = [
+    [0.27, 0.00, 0.33, 1.0], [0.23, 0.32, 0.55, 1.0], [0.13, 0.57, 0.55, 1.0], [0.37, 0.79, 0.38, 1.0],
+    [0.99, 0.91, 0.14, 1.0]
+];
+
+// Diverging scale from negative (blue) through zero (white) to positive (red) values
+pub const PALETTE_DIVERGING: [[f32; 4]; 3] = [
+    [0.23, 0.30, 0.75, 1.0], [0.87, 0.87, 0.87, 1.0], [0.71, 0.02, 0.15, 1.0]
+];
+
+const NAMED_COLOURS: [(&str, [f32; 4]); 11] = [
+    ("white", [1.0, 1.0, 1.0, 1.0]), ("grey", [0.5, 0.5, 0.5, 1.0]), ("red", [1.0, 0.0, 0.0, 1.0]),
+    ("orange", [1.0, 0.6, 0.0, 1.0]), ("yellow", [1.0, 1.0, 0.0, 1.0]), ("green", [0.0, 1.0, 0.0, 1.0]),
+    ("cyan", [0.0, 1.0, 1.0, 1.0]), ("blue", [0.0, 0.0, 1.0, 1.0]), ("purple", [0.6, 0.2, 0.8, 1.0]),
+    ("brown", [0.6, 0.4, 0.2, 1.0]), ("pink", [1.0, 0.6, 0.8, 1.0])
+];
+
+// Parses "#rrggbb", "#rrggbbaa" or a named colour
+pub fn parse(colour: &str) -> Result<[f32; 4], String> {
+    let text = colour.trim().to_lowercase();
+    if let Some((_, value)) = NAMED_COLOURS.iter().find(|(name, _)| *name == text) {
+        return Ok(*value);
+    }
+
+    let hex = text.trim_start_matches('#');
+    if !text.starts_with('#') || (hex.len() != 6 && hex.len() != 8) {
+        return Err(format!("Invalid colour \"{}\"", colour));
+    }
+
+    let component = |i: usize| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+        .map(|x| x as f32 / 255.0)
+        .map_err(|_| format!("Invalid colour \"{}\"", colour));
+
+    Ok([component(0)?, component(1)?, component(2)?, if hex.len() == 8 { component(3)? } else { 1.0 }])
+}
+
+// Interpolates linearly along a palette, for a value in the range [0 1]
+pub fn gradient(palette: &[[f32; 4]], value: f64) -> [f32; 4] {
+    let position = value.max(0.0).min(1.0) * (palette.len() - 1) as f64;
+    let (index, fraction) = (position.floor() as usize, (position - position.floor()) as f32);
+    let (from, to) = (palette[index], palette[(index + 1).min(palette.len() - 1)]);
+
+    [0, 1, 2, 3].iter().fold([0.0; 4], |mut c, &i| { c[i] = from[i] + (to[i] - from[i]) * fraction; c })
+}
+
+pub fn with_alpha(colour: [f32; 4], alpha: f32) -> [f32; 4] {
+    [colour[0], colour[1], colour[2], alpha]
+}
+
+// Converts a colour for drawing into the backbuffer
+pub fn to_rgba(colour: [f32; 4]) -> Rgba<u8> {
+    let channel = |x: f32| (x.max(0.0).min(1.0) * 255.0).round() as u8;
+    Rgba([channel(colour[0]), channel(colour[1]), channel(colour[2]), channel(colour[3])])
+}
@@ -0,0 +1,311 @@
+use std::time::Instant;
+use crate::core::types::Numeric;
+use crate::math::vec3::Vec3;
+use crate::state::State;
+use crate::frames::{self, ReferenceFrame, RotatingFrame};
+use super::camera::Camera;
+use super::{colour, BackBuffer, RenderOptions};
+
+const FIELD_CELL_SIZE: u32 = 4;                 // Pixels between the points at which the field is evaluated; values are interpolated between them
+const FIELD_REFRESH_INTERVAL: f64 = 0.1;        // Seconds between updates while the view is unchanged, as the bodies move
+const FIELD_ALPHA: f32 = 0.6;
+const FORBIDDEN_SHADE: f32 = 0.35;              // Brightness of the region that the selected body cannot reach
+const LAGRANGE_SEARCH_ITERATIONS: usize = 60;
+const LAGRANGE_SEARCH_MARGIN: f64 = 1e-6;       // Fraction of the primary-secondary separation excluded around each body
+
+// Quantity shown as a heat-map beneath the system
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FieldQuantity {
+    Potential,          // Gravitational potential, including the centrifugal potential in a rotating frame
+    Acceleration        // Magnitude of the gravitational acceleration, including the centrifugal acceleration in a rotating frame
+}
+
+impl FieldQuantity {
+    // Cycles between no field, potential and acceleration
+    pub fn cycle(current: Option<Self>) -> Option<Self> {
+        match current {
+            None => Some(FieldQuantity::Potential),
+            Some(FieldQuantity::Potential) => Some(FieldQuantity::Acceleration),
+            Some(FieldQuantity::Acceleration) => None
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            FieldQuantity::Potential => "potential",
+            FieldQuantity::Acceleration => "acceleration"
+        }
+    }
+}
+
+// View for which the backbuffer was last prepared
+#[derive(PartialEq)]
+struct FieldView {
+    camera: Camera,
+    size: (u32, u32),
+    quantity: Option<FieldQuantity>,
+    frame: ReferenceFrame,
+    selected: Option<usize>
+}
+
+// Decides when the backbuffer must be redrawn: as soon as the view changes, and otherwise periodically while a
+// field is shown, so that it follows the bodies.  Without a field the buffer is only cleared, once per size
+pub struct FieldMap {
+    view: Option<FieldView>,
+    last_update: Option<Instant>,
+    cleared_size: Option<(u32, u32)>        // Size of the buffer when last cleared, while no field has been drawn since
+}
+
+impl Default for FieldMap {
+    fn default() -> Self { Self::new() }
+}
+
+impl FieldMap {
+    pub fn new() -> Self {
+        Self { view: None, last_update: None, cleared_size: None }
+    }
+
+    pub fn needs_update(&mut self, camera: &Camera, size: (u32, u32), options: &RenderOptions) -> bool {
+        if options.field.is_none() {
+            let cleared = self.cleared_size == Some(size);
+            self.cleared_size = Some(size);
+            self.view = None;
+            return !cleared;
+        }
+        self.cleared_size = None;
+
+        let view = FieldView { camera: camera.clone(), size, quantity: options.field, frame: options.frame, selected: options.selected };
+        let now = Instant::now();
+
+        let changed = self.view.as_ref() != Some(&view);
+        let due = options.field.is_some() && self.last_update
+            .map(|x| now.duration_since(x))
+            .map(|x| x.as_secs() as f64 + x.subsec_nanos() as f64 * 1e-9 >= FIELD_REFRESH_INTERVAL)
+            .unwrap_or(true);
+
+        if changed || due {
+            self.view = Some(view);
+            self.last_update = Some(now);
+        }
+        changed || due
+    }
+}
+
+// Gravitational field of a set of point masses, in a frame rotating about its z axis at the given rate, where the
+// centrifugal terms are included.  Softening keeps values finite close to each body
+struct GravityField {
+    bodies: Vec<(f64, Vec3<f64>)>,      // Gravitational parameter and position of each body
+    spin: f64,
+    softening: f64
+}
+
+impl GravityField {
+    fn new<TNum>(state: &State<TNum>, bodies: impl Iterator<Item = usize>, gravitational_constant: f64, spin: f64, softening: f64) -> Self
+        where TNum: Numeric {
+        Self {
+            bodies: bodies.map(|i| (gravitational_constant * state.mass(i).into_f64(), state.position(i).to_f64())).collect(),
+            spin,
+            softening
+        }
+    }
+
+    fn potential(&self, point: &Vec3<f64>) -> f64 {
+        let [x, y, _] = *point.get_data();
+        self.bodies.iter()
+            .map(|(mu, pos)| -mu / ((pos - point).length_sq() + self.softening * self.softening).sqrt())
+            .sum::<f64>() - 0.5 * self.spin * self.spin * (x * x + y * y)
+    }
+
+    fn acceleration(&self, point: &Vec3<f64>) -> Vec3<f64> {
+        let [x, y, _] = *point.get_data();
+        let centrifugal = Vec3::new([x, y, 0.0]).scale(self.spin * self.spin);
+
+        self.bodies.iter().fold(centrifugal, |acc, (mu, pos)| {
+            let offset = pos - point;
+            let distance_sq = offset.length_sq() + self.softening * self.softening;
+            acc + offset.scale(mu / (distance_sq * distance_sq.sqrt()))
+        })
+    }
+}
+
+// Points at which the field is evaluated: the corners of square cells of FIELD_CELL_SIZE pixels covering the buffer
+struct FieldGrid {
+    columns: usize,
+    rows: usize
+}
+
+impl FieldGrid {
+    fn new(width: u32, height: u32) -> Self {
+        Self { columns: (width / FIELD_CELL_SIZE) as usize + 2, rows: (height / FIELD_CELL_SIZE) as usize + 2 }
+    }
+
+    // Evaluates a function over the plane through the camera target facing the camera
+    fn evaluate(&self, camera: &Camera, width: u32, height: u32, f: impl Fn(&Vec3<f64>) -> f64) -> Vec<f64> {
+        let cell = FIELD_CELL_SIZE as f64;
+        (0..self.rows)
+            .flat_map(|j| (0..self.columns).map(move |i| [i as f64 * cell / width as f64, j as f64 * cell / height as f64]))
+            .map(|screen| f(&camera.unproject(screen)))
+            .collect()
+    }
+
+    // Interpolates bilinearly between the values at the corners of the cell containing a pixel
+    fn interpolate(&self, values: &[f64], x: u32, y: u32) -> f64 {
+        let (i, j) = ((x / FIELD_CELL_SIZE) as usize, (y / FIELD_CELL_SIZE) as usize);
+        let (tx, ty) = ((x % FIELD_CELL_SIZE) as f64 / FIELD_CELL_SIZE as f64, (y % FIELD_CELL_SIZE) as f64 / FIELD_CELL_SIZE as f64);
+        let at = |i: usize, j: usize| values[j * self.columns + i];
+
+        (at(i, j) * (1.0 - tx) + at(i + 1, j) * tx) * (1.0 - ty) + (at(i, j + 1) * (1.0 - tx) + at(i + 1, j + 1) * tx) * ty
+    }
+}
+
+// Draws the field of the state over the plane through the camera target, coloured by the rank of each value within
+// the view so that its structure is visible at any scale.  In a rotating frame, the zero-velocity curves of the
+// restricted problem of the primary and secondary are drawn through their L1 and L2 points, bounding the Hill region
+// of each, and at the Jacobi energy of a selected third body, with the region it cannot reach shaded
+pub fn render_field<TNum>(buffer: &mut BackBuffer, camera: &Camera, state: &State<TNum>, gravitational_constant: f64,
+                          quantity: FieldQuantity, options: &RenderOptions)
+    where TNum: Numeric {
+    let (width, height) = buffer.dimensions();
+    let display = frames::transform_state(state, options.frame);
+    let n = display.entity_count();
+
+    let rotation = match options.frame {
+        ReferenceFrame::Rotating { primary, secondary } if primary < n && secondary < n && primary != secondary =>
+            RotatingFrame::from_state(state, primary, secondary).map(|x| (primary, secondary, x.angular_speed())),
+        _ => None
+    };
+    let spin = rotation.map(|(_, _, spin)| spin).unwrap_or(0.0);
+
+    // Values are softened over a cell, since they are not resolved more finely
+    let grid = FieldGrid::new(width, height);
+    let softening = camera.view_height() * FIELD_CELL_SIZE as f64 / height as f64;
+    let field = GravityField::new(&display, 0..n, gravitational_constant, spin, softening);
+    let ranks = ranks(&grid.evaluate(camera, width, height, |p| match quantity {
+        FieldQuantity::Potential => field.potential(p),
+        FieldQuantity::Acceleration => field.acceleration(p).length()
+    }));
+
+    let contours = rotation.map(|(primary, secondary, spin)| {
+        let restricted = GravityField::new(&display, vec![primary, secondary].into_iter(), gravitational_constant, spin, 0.0);
+        let mut levels = lagrange_points(&restricted, display.position(primary).to_f64().x(), display.position(secondary).to_f64().x())
+            .iter()
+            .map(|x| (restricted.potential(x), colour::COLOUR_CONTOUR))
+            .collect::<Vec<_>>();
+
+        let jacobi = options.selected
+            .filter(|&s| s < n && s != primary && s != secondary)
+            .map(|s| 0.5 * display.velocity(s).to_f64().length_sq() + restricted.potential(&display.position(s).to_f64()));
+        levels.extend(jacobi.map(|x| (x, colour::COLOUR_HIGHLIGHT)));
+
+        let values = grid.evaluate(camera, width, height, |p| restricted.potential(p));
+        let pixels = (0..height).flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| grid.interpolate(&values, x, y))
+            .collect::<Vec<_>>();
+
+        (pixels, levels, jacobi)
+    });
+
+    for y in 0..height {
+        for x in 0..width {
+            let mut pixel = colour::with_alpha(colour::gradient(&colour::PALETTE_VIRIDIS, grid.interpolate(&ranks, x, y)), FIELD_ALPHA);
+
+            if let Some((values, levels, jacobi)) = &contours {
+                let at = |x: u32, y: u32| values[(y * width + x) as usize];
+                let value = at(x, y);
+
+                if jacobi.map(|level| value > level).unwrap_or(false) {
+                    pixel = [pixel[0] * FORBIDDEN_SHADE, pixel[1] * FORBIDDEN_SHADE, pixel[2] * FORBIDDEN_SHADE, pixel[3]];
+                }
+
+                // A contour passes through each pixel where the value crosses its level before the next pixel
+                let crosses = |level: f64| [(x + 1, y), (x, y + 1)].iter()
+                    .filter(|&&(x, y)| x < width && y < height)
+                    .any(|&(x, y)| (at(x, y) > level) != (value > level));
+                if let Some((_, contour)) = levels.iter().find(|(level, _)| crosses(*level)) {
+                    pixel = *contour;
+                }
+            }
+
+            buffer.put_pixel(x, y, colour::to_rgba(pixel));
+        }
+    }
+}
+
+// Fraction of the values below each value, so that colours spread evenly over the view whatever the range of the field
+fn ranks(values: &[f64]) -> Vec<f64> {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let scale = 1.0 / (sorted.len().max(2) - 1) as f64;
+
+    values.iter()
+        .map(|v| match sorted.binary_search_by(|x| x.partial_cmp(v).unwrap_or(std::cmp::Ordering::Equal)) {
+            Ok(i) | Err(i) => i as f64 * scale
+        })
+        .collect()
+}
+
+// Returns the L1 and L2 points of a rotating two-body field, where the acceleration along the x axis vanishes
+// between the bodies and beyond the secondary, given the positions of the primary and secondary along the axis
+fn lagrange_points(field: &GravityField, primary: f64, secondary: f64) -> Vec<Vec3<f64>> {
+    let separation = secondary - primary;
+    let margin = separation * LAGRANGE_SEARCH_MARGIN;
+    let acceleration = |x: f64| field.acceleration(&Vec3::new([x, 0.0, 0.0])).x();
+
+    [(primary + margin, secondary - margin), (secondary + margin, secondary + separation)].iter()
+        .filter_map(|&(low, high)| find_root(&acceleration, low, high))
+        .map(|x| Vec3::new([x, 0.0, 0.0]))
+        .collect()
+}
+
+// Bisects for a root of a function that changes sign between the bounds
+fn find_root(f: &impl Fn(f64) -> f64, mut low: f64, mut high: f64) -> Option<f64> {
+    let low_positive = f(low) > 0.0;
+    if low_positive == (f(high) > 0.0) { return None; }
+
+    for _ in 0..LAGRANGE_SEARCH_ITERATIONS {
+        let mid = 0.5 * (low + high);
+        if (f(mid) > 0.0) == low_positive { low = mid; } else { high = mid; }
+    }
+    Some(0.5 * (low + high))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rendering::trails::TrailOptions;
+    use crate::units::UnitSystem;
+
+    fn options(field: Option<FieldQuantity>) -> RenderOptions {
+        RenderOptions {
+            frame: ReferenceFrame::Inertial,
+            trail_focus: None,
+            selected: None,
+            trails: TrailOptions::default(),
+            display_units: UnitSystem::default(),
+            grid: false,
+            field
+        }
+    }
+
+    #[test]
+    fn clears_once_while_no_field_is_shown() {
+        let mut map = FieldMap::new();
+        let mut camera = Camera::new(1.0);
+
+        assert!(map.needs_update(&camera, (100, 100), &options(None)));
+        camera.pan([0.1, 0.0]);
+        assert!(!map.needs_update(&camera, (100, 100), &options(None)));
+        assert!(map.needs_update(&camera, (200, 100), &options(None)));
+    }
+
+    #[test]
+    fn redraws_when_field_is_shown_or_hidden() {
+        let mut map = FieldMap::new();
+        let camera = Camera::new(1.0);
+
+        assert!(map.needs_update(&camera, (100, 100), &options(None)));
+        assert!(map.needs_update(&camera, (100, 100), &options(Some(FieldQuantity::Potential))));
+        assert!(map.needs_update(&camera, (100, 100), &options(None)));
+        assert!(!map.needs_update(&camera, (100, 100), &options(None)));
+    }
+}
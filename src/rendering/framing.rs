@@ -0,0 +1,119 @@
+use std::time::Instant;
+use crate::core::types::Numeric;
+use crate::math::vec3::Vec3;
+use crate::state::State;
+use crate::frames;
+use super::camera::Camera;
+
+const FIT_PERCENTILE: f64 = 0.9;
+const OUTLIER_FACTOR: f64 = 3.0;        // Bodies beyond this multiple of the FIT_PERCENTILE distance, e.g. once ejected, are not fitted
+const FIT_MARGIN: f64 = 1.15;           // View extent relative to the furthest fitted body
+const FIT_TIME_CONSTANT: f64 = 0.4;     // Seconds for the view to cover ~63% of the way to a new fit
+const MAX_FIT_INTERVAL: f64 = 0.1;      // Longer pauses between updates, e.g. while the window is moved, are not caught up
+
+// Keeps the camera fitted to the bodies of the system, easing towards each new fit.  The view is centred on the
+// fitted bodies, unless the camera is following a focus, and sized so that they fit within both the width and
+// height of the window
+pub struct AutoFrame {
+    enabled: bool,
+    snap: bool,                         // Moves directly to the next fit, rather than easing towards it
+    last_update: Option<Instant>
+}
+
+impl Default for AutoFrame {
+    fn default() -> Self { Self::new() }
+}
+
+impl AutoFrame {
+    pub fn new() -> Self {
+        Self { enabled: true, snap: true, last_update: None }
+    }
+
+    pub fn is_enabled(&self) -> bool { self.enabled }
+
+    pub fn enable(&mut self) {
+        self.enabled = true;
+        self.last_update = None;
+    }
+
+    pub fn disable(&mut self) { self.enabled = false; }
+
+    pub fn toggle(&mut self) {
+        if self.enabled { self.disable(); } else { self.enable(); }
+    }
+
+    // Moves the camera towards the fit of a state given in the display frame.  If 'follow' is set the camera
+    // target is left unchanged, and the view is sized about it
+    pub fn update<TNum>(&mut self, camera: &mut Camera, state: &State<TNum>, follow: bool)
+        where TNum: Numeric {
+        if !self.enabled { return; }
+
+        let (target, view_height) = match AutoFrame::fit(camera, state, if follow { Some(camera.target().clone()) } else { None }) {
+            Some(fit) => fit,
+            None => return
+        };
+
+        let now = Instant::now();
+        let blend = if self.snap { 1.0 } else {
+            let elapsed = self.last_update.map(|x| now.duration_since(x)).map(|x| x.as_secs() as f64 + x.subsec_nanos() as f64 * 1e-9)
+                .unwrap_or(0.0)
+                .min(MAX_FIT_INTERVAL);
+            1.0 - (-elapsed / FIT_TIME_CONSTANT).exp()
+        };
+        self.snap = false;
+        self.last_update = Some(now);
+
+        // Heights are eased in proportion, so that large changes of scale take no longer than small ones
+        camera.set_view_height(camera.view_height() * (view_height / camera.view_height()).powf(blend));
+        if !follow {
+            let offset = &target - camera.target();
+            camera.set_target(camera.target().clone() + offset.scale(blend));
+        }
+    }
+
+    // Returns the target and view height that fit the state, about the given centre or otherwise about the
+    // centre of mass of the fitted bodies
+    fn fit<TNum>(camera: &Camera, state: &State<TNum>, centre: Option<Vec3<f64>>) -> Option<(Vec3<f64>, f64)>
+        where TNum: Numeric {
+        if state.entity_count() == 0 { return None; }
+
+        let fitted = match &centre {
+            Some(centre) => AutoFrame::exclude_outliers(camera, state, centre),
+            None => AutoFrame::exclude_outliers(camera, state, &frames::system_centre_of_mass(state).1)
+        };
+
+        let centre = centre.unwrap_or_else(|| frames::centre_of_mass(state, fitted.iter().cloned()).1);
+        let extent = fitted.iter()
+            .map(|&i| AutoFrame::screen_extent(camera, &state.position(i).to_f64(), &centre))
+            .fold(0.0, f64::max);
+
+        if extent > 0.0 { Some((centre, 2.0 * extent * FIT_MARGIN)) } else { None }
+    }
+
+    // Returns the bodies within OUTLIER_FACTOR times the FIT_PERCENTILE distance from the centre
+    fn exclude_outliers<TNum>(camera: &Camera, state: &State<TNum>, centre: &Vec3<f64>) -> Vec<usize>
+        where TNum: Numeric {
+        let extents = state.positions().iter()
+            .map(|pos| AutoFrame::screen_extent(camera, &pos.to_f64(), centre))
+            .collect::<Vec<_>>();
+
+        let mut sorted = extents.clone();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        let percentile = sorted[((sorted.len() as f64 * FIT_PERCENTILE).ceil() as usize).max(1) - 1];
+
+        extents.iter()
+            .enumerate()
+            .filter(|(_, &extent)| extent <= percentile * OUTLIER_FACTOR)
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    // Half-height of the view needed to show a point, in the plane of the screen through the centre, allowing
+    // for the aspect ratio of the window
+    fn screen_extent(camera: &Camera, point: &Vec3<f64>, centre: &Vec3<f64>) -> f64 {
+        let (right, up, _) = camera.basis();
+        let relative = point - centre;
+
+        (relative.dot(&right).abs() / camera.aspect()).max(relative.dot(&up).abs())
+    }
+}
@@ -1,111 +1,161 @@
 #![allow(dead_code)] pub mod colour;
 pub mod screenshot;
+pub mod camera;
+pub mod style;
+pub mod trails;
+pub mod framing;
+pub mod overlay;
+pub mod field;
 
 use std::ops::{Add, Sub, Mul, Div, AddAssign};
 use core::iter::Sum;
-use ::core::cell::{Ref};
 use ::image;
 use piston_window::*;
 use image::Rgba;
 use crate::nbody::nbody_system::NBodySystem;
 use crate::state::State;
 use crate::core::types::Numeric;
-use itertools::Itertools;
 use crate::math::vec3::Vec3;
+use crate::frames::{self, ReferenceFrame};
+use camera::{Camera, CameraFocus, ProjectedPoint};
+use style::{BodyStyles, BodyStyle};
+use trails::TrailOptions;
+use field::{FieldMap, FieldQuantity};
+use crate::entities::display::MarkerShape;
+use crate::units::UnitSystem;
 
 pub type BackBuffer = image::ImageBuffer<image::Rgba<u8>, Vec<u8>>;
 
-const DYNAMIC_BOUNDS : bool = false;
-const RENDER_MOTION_HISTORY_COUNT: usize = 40;
+const MAX_BODY_RENDER_SCALE: f64 = 10.0;
+const MARKER_LINE_WIDTH: f64 = 0.0015;
+const HIGHLIGHT_RING_SCALE: f64 = 1.8;
+const HIGHLIGHT_RING_WIDTH: f64 = 0.0015;
 
 pub struct Renderer {
-    bounds: (f64, f64)
+    styles: BodyStyles,
+    field: FieldMap
 }
 
-impl Renderer {
-    pub fn new() -> Self {
-        Self { bounds: (0.0, 0.0) }
-    }
+// Text to be drawn over the rendered system, at a position in [0 1] window coordinates
+pub struct ScreenLabel {
+    pub text: String,
+    pub position: [f64; 2],
+    pub colour: [f32; 4]
+}
 
-    pub fn prepare_backbuffer(buffer: &mut BackBuffer, draw_size: &[u32; 2], zoom_level: f64, view_origin: [f64; 2]) {
-        Renderer::clear_backbuffer(buffer);
+// Display settings applied on each render
+#[derive(Debug, Clone, Copy)]
+pub struct RenderOptions {
+    pub frame: ReferenceFrame,
+    pub trail_focus: Option<CameraFocus>,      // Draws trails relative to this point, rather than in the display frame
+    pub selected: Option<usize>,               // Body to highlight, if any
+    pub trails: TrailOptions,
+    pub display_units: UnitSystem,             // Units of the scale bar and grid labels
+    pub grid: bool,
+    pub field: Option<FieldQuantity>           // Heat-map drawn into the backbuffer, beneath the system
+}
 
-        // Prepare backbuffer
-        //let bound = u32::min(draw_size[0], draw_size[1]) - 1;
-        //(0..bound).for_each(|x| buffer.put_pixel(x,  bound - x, colour::BLUE));
+impl Renderer {
+    pub fn new(styles: BodyStyles) -> Self {
+        Self { styles, field: FieldMap::new() }
     }
 
-    pub fn perform_rendering<TNum>(&mut self, g: &mut G2d, context: &Context, render_size: (f64, f64), zoom_level: f64, view_origin: [f64; 2],
-                                   system: &NBodySystem<TNum>)
-        where TNum: Numeric + Add<Output=TNum> + Sub<Output=TNum> + Mul<Output=TNum> + Div<Output=TNum> + AddAssign + Sum {
-        piston_window::clear([0.0, 0.0, 0.0, 1.0], g);
-
-        // Determine window bounds based on current state
-        let states = system.get_state_history(1);
-        self.update_bounds(&states);
-
-        let bounds = (self.bounds.0 * 1.1, self.bounds.1 * 1.1);
-        let zero_offset = bounds.0 * -1.0;      // e.g. if min bound is -ve, this will be a +ve offset
-        let zb_bounds = (0.0, bounds.1 + zero_offset);
-
-        let to_canvas_pos = |x: TNum| (x.into_f64() + zero_offset) / zb_bounds.1;
-        let to_canvas_vec = |v: &Vec3<TNum>| [to_canvas_pos(v.x()), to_canvas_pos(v.y())];
+    pub fn body_styles<TNum>(&self, state: &State<TNum>) -> Vec<BodyStyle>
+        where TNum: Numeric {
+        self.styles.resolve(state)
+    }
 
-        // Render motion history first, so current state is rendered on top
-        let interval = (
-            (system.get_max_state_history_length() as f64) /
-                (RENDER_MOTION_HISTORY_COUNT as f64)) as usize;
+    pub fn get_styles(&self) -> &BodyStyles { &self.styles }
 
-        let full_history = system.get_full_state_history();
-        let history = full_history.iter()
-            .step_by(interval)
-            .map(|x| x.positions())
-            .collect::<Vec<_>>();
+    // Redraws the field shown beneath the system, once the view changes or the field is due to follow the bodies
+    pub fn prepare_backbuffer<TNum>(&mut self, buffer: &mut BackBuffer, camera: &Camera, system: &NBodySystem<TNum>, options: &RenderOptions)
+        where TNum: Numeric {
+        if !self.field.needs_update(camera, buffer.dimensions(), options) { return; }
 
-        for i in 1..history.len() {
-            history[i-1].iter().zip(history[i])
-                .for_each(|(x0, x1)|
-                    line_from_to([0.1, 0.1, 0.1, 0.5], 0.01, to_canvas_vec(x0), to_canvas_vec(x1), context.transform, g)
-                )
+        match options.field {
+            Some(quantity) => field::render_field(buffer, camera, &*system.get_current_state(),
+                                                  system.get_gravitational_constant().into_f64(), quantity, options),
+            None => Renderer::clear_backbuffer(buffer)
         }
-
-        // Render current state
-        states[0].positions().iter().for_each(|pos| {
-            let canvas_pos = (to_canvas_pos(pos.x()), to_canvas_pos(pos.y()));
-
-            let sz = 0.01;
-            ellipse_from_to([0.0, 1.0, 0.0, 1.0], [canvas_pos.0 - sz, canvas_pos.1 - sz],
-                            [canvas_pos.0 + sz, canvas_pos.1 + sz], context.transform, g);
-        });
     }
 
-    fn update_bounds<TNum>(&mut self, states: &Vec<Ref<State<TNum>>>)
-        where TNum: Numeric {
+    // Renders the system over the backbuffer texture, if given, returning the labels of labelled bodies, the grid and
+    // the scale bar to be drawn over it
+    pub fn perform_rendering<TNum>(&mut self, g: &mut G2d, context: &Context, camera: &Camera, system: &NBodySystem<TNum>,
+                                   background: Option<&G2dTexture>, options: &RenderOptions) -> Vec<ScreenLabel>
+        where TNum: Numeric + Add<Output=TNum> + Sub<Output=TNum> + Mul<Output=TNum> + Div<Output=TNum> + AddAssign + Sum {
+        piston_window::clear([0.0, 0.0, 0.0, 1.0], g);
+        if let Some(texture) = background {
+            image(texture, context.scale(1.0 / texture.get_width() as f64, 1.0 / texture.get_height() as f64).transform, g);
+        }
 
-        let new_bounds = Self::determine_bounds(states);
-        self.bounds = (
-            self.bounds.0.min(new_bounds.0),
-            self.bounds.1.max(new_bounds.1)
-        );
+        let frame = options.frame;
+        let states = system.get_state_history(1);
+        let current = frames::transform_state(&*states[0], frame);
+        let project = |v: &Vec3<TNum>| camera.project(&v.to_f64());
+
+        let mut labels = if options.grid {
+            overlay::render_grid(g, context, camera, system.get_units(), options.display_units)
+        } else {
+            vec![]
+        };
+
+        // Render trails first, so current state is rendered on top
+        let styles = self.styles.resolve(&current);
+        trails::render_trails(g, context, camera, system, &current, &styles, options);
+
+        // Render current state, furthest bodies first so that nearer bodies are drawn over them
+        let mut bodies = current.positions().iter()
+            .enumerate()
+            .filter_map(|(i, pos)| project(pos).map(|p| (i, p)))
+            .collect::<Vec<_>>();
+        bodies.sort_by(|(_, a), (_, b)| b.depth.partial_cmp(&a.depth).unwrap_or(std::cmp::Ordering::Equal));
+
+        labels.extend(bodies.iter()
+            .filter_map(|(i, body)| {
+                let style = &styles[*i];
+
+                // Sizes are relative to window height, so are scaled horizontally to remain circular
+                let sz = Renderer::body_screen_radius(style.size, body);
+                let (sx, sy) = (sz / camera.aspect(), sz);
+                Renderer::draw_marker(style.marker, style.colour, body.screen, [sx, sy], context, g);
+
+                // Highlight the selected body with a ring, so it remains visible when drawn very small
+                if options.selected == Some(*i) {
+                    let (rx, ry) = (sx * HIGHLIGHT_RING_SCALE + HIGHLIGHT_RING_WIDTH, sy * HIGHLIGHT_RING_SCALE + HIGHLIGHT_RING_WIDTH);
+                    Ellipse::new_border(colour::COLOUR_HIGHLIGHT, HIGHLIGHT_RING_WIDTH)
+                        .draw([body.screen[0] - rx, body.screen[1] - ry, rx * 2.0, ry * 2.0], &context.draw_state, context.transform, g);
+                }
+
+                style.label.as_ref().map(|text| ScreenLabel {
+                    text: text.clone(),
+                    position: [body.screen[0] + sx, body.screen[1] - sy],
+                    colour: style.colour
+                })
+            }));
+
+        labels.push(overlay::render_scale_bar(g, context, camera, system.get_units(), options.display_units));
+        labels
     }
 
-    fn determine_bounds<TNum>(states: &Vec<Ref<State<TNum>>>) -> (f64, f64)
-        where TNum: Numeric {
-        if !DYNAMIC_BOUNDS { (-2.6e1, 2.6e1) } else {
-            states.iter()
-                .map(Renderer::determine_state_bounds)
-                .fold1(|(mn, mx), (next_mn, next_mx)| (mn.min(next_mn), mx.max(next_mx)))
-                .unwrap_or_else(|| panic!("Failed to determine state bounds for rendering"))
+    fn draw_marker(marker: MarkerShape, colour: [f32; 4], centre: [f64; 2], size: [f64; 2], context: &Context, g: &mut G2d) {
+        let ([cx, cy], [sx, sy]) = (centre, size);
+        match marker {
+            MarkerShape::Circle => ellipse_from_to(colour, [cx - sx, cy - sy], [cx + sx, cy + sy], context.transform, g),
+            MarkerShape::Ring => Ellipse::new_border(colour, MARKER_LINE_WIDTH)
+                .draw([cx - sx, cy - sy, sx * 2.0, sy * 2.0], &context.draw_state, context.transform, g),
+            MarkerShape::Square => rectangle(colour, [cx - sx, cy - sy, sx * 2.0, sy * 2.0], context.transform, g),
+            MarkerShape::Diamond => polygon(colour, &[[cx, cy - sy], [cx + sx, cy], [cx, cy + sy], [cx - sx, cy]], context.transform, g),
+            MarkerShape::Cross => {
+                line_from_to(colour, MARKER_LINE_WIDTH, [cx - sx, cy - sy], [cx + sx, cy + sy], context.transform, g);
+                line_from_to(colour, MARKER_LINE_WIDTH, [cx - sx, cy + sy], [cx + sx, cy - sy], context.transform, g);
+            }
         }
     }
 
-    fn determine_state_bounds<TNum>(state: &Ref<State<TNum>>) -> (f64, f64)
-        where TNum: Numeric {
-        state.positions().iter()
-            .fold((1e200, -1e200), |(mn, mx), pos| (
-                mn.min(pos.x().into_f64()).min(pos.y().into_f64()),
-                mx.max(pos.x().into_f64()).max(pos.y().into_f64())
-            ))
+    // Radius at which a body is drawn, as a fraction of the window height
+    pub fn body_screen_radius(size: f64, body: &ProjectedPoint) -> f64 {
+        size * body.scale.min(MAX_BODY_RENDER_SCALE)
     }
 
     fn clear_backbuffer(canvas: &mut BackBuffer) {
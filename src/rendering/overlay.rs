@@ -0,0 +1,102 @@
+use piston_window::*;
+use crate::math::vec3::Vec3;
+use crate::units::UnitSystem;
+use crate::units::quantity::Dimension;
+use super::camera::Camera;
+use super::{colour, ScreenLabel};
+
+const SCALE_BAR_FRACTION: f64 = 0.2;        // Longest scale bar, as a fraction of the window width
+const SCALE_BAR_POSITION: [f64; 2] = [0.97, 0.95];     // Right-hand end of the bar, in [0 1] window coordinates
+const SCALE_BAR_TICK: f64 = 0.01;
+const GRID_DIVISIONS: f64 = 5.0;            // Approximate number of grid cells spanning the view height
+const GRID_EXTENT: f64 = 1.5;               // Grid half-width, relative to the larger dimension of the view
+const GRID_LINE_SEGMENTS: usize = 16;       // Grid lines are drawn in segments, so that perspective is followed
+const OVERLAY_LINE_WIDTH: f64 = 0.001;
+
+// Draws a bar of a round length in display units at the foot of the window, returning its label.  Lengths
+// are those in the plane through the camera target, in the perspective projection
+pub fn render_scale_bar(g: &mut G2d, context: &Context, camera: &Camera, units: UnitSystem, display_units: UnitSystem) -> ScreenLabel {
+    let per_unit = units.convert(1.0, Dimension::Length, &display_units);
+    let length = round_length(camera.view_width() * SCALE_BAR_FRACTION * per_unit);
+    let width = length / per_unit / camera.view_width();
+
+    let [x1, y] = SCALE_BAR_POSITION;
+    let x0 = x1 - width;
+    line_from_to(colour::COLOUR_OVERLAY, OVERLAY_LINE_WIDTH, [x0, y], [x1, y], context.transform, g);
+    line_from_to(colour::COLOUR_OVERLAY, OVERLAY_LINE_WIDTH, [x0, y - SCALE_BAR_TICK], [x0, y + SCALE_BAR_TICK], context.transform, g);
+    line_from_to(colour::COLOUR_OVERLAY, OVERLAY_LINE_WIDTH, [x1, y - SCALE_BAR_TICK], [x1, y + SCALE_BAR_TICK], context.transform, g);
+
+    ScreenLabel {
+        text: format!("{} {}", format_length(length, length), display_units.length_label()),
+        position: [x0, y - SCALE_BAR_TICK * 1.5],
+        colour: colour::COLOUR_OVERLAY
+    }
+}
+
+// Draws a grid over the xy plane of the display frame, with lines at round intervals in display units,
+// returning the labels of the lines through the centre of the grid
+pub fn render_grid(g: &mut G2d, context: &Context, camera: &Camera, units: UnitSystem, display_units: UnitSystem) -> Vec<ScreenLabel> {
+    let per_unit = units.convert(1.0, Dimension::Length, &display_units);
+    let spacing_display = round_length(camera.view_height() * per_unit / GRID_DIVISIONS);
+    let spacing = spacing_display / per_unit;
+
+    let target = camera.target().get_data();
+    let extent = camera.view_width().max(camera.view_height()) * GRID_EXTENT;
+    let lines = |centre: f64| ((centre - extent) / spacing).floor() as i64..=((centre + extent) / spacing).ceil() as i64;
+    let (xs, ys) = (lines(target[0]), lines(target[1]));
+    let (x_range, y_range) = ((*xs.start() as f64 * spacing, *xs.end() as f64 * spacing), (*ys.start() as f64 * spacing, *ys.end() as f64 * spacing));
+
+    // Lines of constant x, then of constant y, with the coordinate axes drawn more strongly
+    xs.clone().map(|i| (i, [i as f64 * spacing, y_range.0], [i as f64 * spacing, y_range.1]))
+        .chain(ys.clone().map(|i| (i, [x_range.0, i as f64 * spacing], [x_range.1, i as f64 * spacing])))
+        .for_each(|(i, from, to)| {
+            let colour = if i == 0 { colour::COLOUR_AXIS } else { colour::COLOUR_GRID };
+            (0..GRID_LINE_SEGMENTS)
+                .map(|s| (interpolate(from, to, s as f64 / GRID_LINE_SEGMENTS as f64), interpolate(from, to, (s + 1) as f64 / GRID_LINE_SEGMENTS as f64)))
+                .filter_map(|(p0, p1)| camera.project(&p0).and_then(|p0| camera.project(&p1).map(|p1| (p0, p1))))
+                .for_each(|(p0, p1)| line_from_to(colour, OVERLAY_LINE_WIDTH, p0.screen, p1.screen, context.transform, g));
+        });
+
+    // Each line is labelled with its coordinate where it crosses the central line of the other axis
+    let centre = [(target[0] / spacing).round() as i64, (target[1] / spacing).round() as i64];
+    let label = |value: f64, point: Vec3<f64>| camera.project(&point)
+        .filter(|p| p.screen.iter().all(|x| (0.0..=1.0).contains(x)))
+        .map(|p| ScreenLabel {
+            text: format_length(value * per_unit, spacing_display),
+            position: p.screen,
+            colour: colour::COLOUR_OVERLAY
+        });
+
+    let (centre_x, centre_y) = (centre[0] as f64 * spacing, centre[1] as f64 * spacing);
+    xs.map(|i| i as f64 * spacing).filter_map(|x| label(x, Vec3::new([x, centre_y, 0.0])))
+        .chain(ys.filter(|&i| i != centre[1]).map(|i| i as f64 * spacing).filter_map(|y| label(y, Vec3::new([centre_x, y, 0.0]))))
+        .collect()
+}
+
+// Largest length of the form 1, 2 or 5 times a power of ten that does not exceed the given length
+pub fn round_length(length: f64) -> f64 {
+    if length <= 0.0 { return 0.0; }
+
+    let power = 10f64.powf(length.log10().floor());
+    [10.0, 5.0, 2.0, 1.0].iter()
+        .map(|x| x * power)
+        .find(|&x| x <= length)
+        .unwrap_or(power)
+}
+
+// Formats a length to the precision of the given step, in scientific notation if very large or small
+fn format_length(value: f64, step: f64) -> String {
+    let magnitude = step.log10().floor();
+    if magnitude.abs() >= 4.0 && value != 0.0 {
+        let digits = (value.abs().log10().floor() - magnitude).max(0.0);
+        format!("{:.*e}", digits as usize, value)
+    } else if magnitude.abs() >= 4.0 {
+        "0".to_string()
+    } else {
+        format!("{:.*}", (-magnitude).max(0.0) as usize, value)
+    }
+}
+
+fn interpolate(from: [f64; 2], to: [f64; 2], fraction: f64) -> Vec3<f64> {
+    Vec3::new([from[0] + (to[0] - from[0]) * fraction, from[1] + (to[1] - from[1]) * fraction, 0.0])
+}
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use crate::core::types::Numeric;
+use crate::state::State;
+use crate::entities::system::System;
+use crate::entities::display::{EntityDisplay, MarkerShape};
+use crate::nbody::checkpoint::DisplayCheckpoint;
+use super::colour;
+use super::trails::TRAIL_ALPHA;
+
+const MIN_BODY_SIZE: f64 = 0.004;       // Sizes are fractions of the window height, before perspective scaling
+const MAX_BODY_SIZE: f64 = 0.02;
+const MASS_SIZE_DECADES: f64 = 8.0;     // Bodies this many orders of magnitude below the largest are drawn at the minimum size
+const RADIUS_SIZE_DECADES: f64 = 3.0;
+
+// Display attributes of a body, as rendered
+#[derive(Debug, Clone)]
+pub struct BodyStyle {
+    pub colour: [f32; 4],
+    pub trail_colour: [f32; 4],
+    pub size: f64,
+    pub label: Option<String>,
+    pub marker: MarkerShape
+}
+
+// Declared display attributes and physical radius of each entity, by id.  Ids are used, rather than
+// indices, so that styles follow bodies as others are added or removed; likewise each loaded entity keeps
+// the palette slot of its position at load time
+#[derive(Debug, Clone, Default)]
+pub struct BodyStyles {
+    declared: HashMap<String, DeclaredStyle>,
+    slots: HashMap<String, usize>
+}
+
+#[derive(Debug, Clone)]
+struct DeclaredStyle {
+    colour: Option<[f32; 4]>,
+    trail_colour: Option<[f32; 4]>,
+    display: Option<EntityDisplay>,
+    radius: Option<f64>
+}
+
+impl BodyStyles {
+    pub fn new() -> Self {
+        Self { declared: HashMap::new(), slots: HashMap::new() }
+    }
+
+    pub fn from_system(system: &System) -> Self {
+        let entities = system.get_entities().iter()
+            .map(|x| x.in_system_units(&system.get_units()))
+            .collect::<Vec<_>>();
+        Self::from_declared(entities.iter().map(|x| (&x.id, &x.display, x.radius)))
+    }
+
+    // Styles of a restored run, as declared by the system it was started from
+    pub fn from_checkpoint(displays: &[DisplayCheckpoint]) -> Self {
+        Self::from_declared(displays.iter().map(|x| (&x.id, &x.display, x.radius)))
+    }
+
+    // Palette slots of bodies without declared styles, such as those of a recording
+    pub fn from_ids(ids: &[String]) -> Self {
+        Self::from_declared(ids.iter().map(|x| (x, &None, None)))
+    }
+
+    fn from_declared<'a>(entities: impl Iterator<Item = (&'a String, &'a Option<EntityDisplay>, Option<f64>)>) -> Self {
+        let mut styles = Self::new();
+        for (slot, (id, display, radius)) in entities.enumerate() {
+            let parse = |c: &String| colour::parse(c).unwrap_or_else(|e| panic!("Invalid display colour for entity '{}' ({})", id, e));
+            let colour = display.as_ref().and_then(|d| d.colour.as_ref()).map(parse);
+            let trail_colour = display.as_ref().and_then(|d| d.trail_colour.as_ref()).map(parse);
+
+            styles.declared.insert(id.clone(), DeclaredStyle { colour, trail_colour, display: display.clone(), radius });
+            styles.slots.insert(id.clone(), slot);
+        }
+        styles
+    }
+
+    // Resolves the style of every body in the state.  Undeclared colours are taken from the categorical palette,
+    // by the slot of the body at load time or otherwise a hash of its id, trails take the body colour if not declared, and undeclared sizes follow a log scale of physical radius, where every body has one, or
+    // otherwise of mass, relative to the largest body
+    pub fn resolve<TNum>(&self, state: &State<TNum>) -> Vec<BodyStyle>
+        where TNum: Numeric {
+
+        let declared = state.ids().iter().map(|id| self.declared.get(id)).collect::<Vec<_>>();
+        let radii = declared.iter().map(|x| x.and_then(|x| x.radius)).collect::<Option<Vec<f64>>>();
+        let (scale, decades) = match radii {
+            Some(radii) if !radii.is_empty() => (radii, RADIUS_SIZE_DECADES),
+            _ => (state.masses().iter().map(|x| x.into_f64()).collect(), MASS_SIZE_DECADES)
+        };
+        let largest = scale.iter().cloned().fold(0.0, f64::max);
+
+        state.ids().iter().zip(declared.iter().zip(scale.iter()))
+            .map(|(id, (declared, &x))| {
+                let display = declared.and_then(|d| d.display.as_ref());
+                let slot = self.slots.get(id).cloned().unwrap_or_else(|| hashed_slot(id));
+                let colour = declared.and_then(|d| d.colour).unwrap_or(colour::PALETTE_CATEGORICAL[slot % colour::PALETTE_CATEGORICAL.len()]);
+                BodyStyle {
+                    colour,
+                    trail_colour: declared.and_then(|d| d.trail_colour).unwrap_or_else(|| colour::with_alpha(colour, TRAIL_ALPHA)),
+                    size: display.and_then(|d| d.radius).unwrap_or_else(|| BodyStyles::log_size(x, largest, decades)),
+                    label: display.and_then(|d| d.label.clone()),
+                    marker: display.and_then(|d| d.marker).unwrap_or_default()
+                }
+            })
+            .collect()
+    }
+
+    // Copies declared display attributes and physical radii onto the entities of a system, by id
+    pub fn apply_to(&self, system: &mut System) {
+        system.get_entities_mut().iter_mut()
+            .for_each(|entity| if let Some(declared) = self.declared.get(&entity.id) {
+                entity.display = declared.display.clone();
+                entity.radius = declared.radius;
+            });
+    }
+
+    fn log_size(x: f64, largest: f64, decades: f64) -> f64 {
+        if largest <= 0.0 || x <= 0.0 { return MIN_BODY_SIZE; }
+
+        let fraction = (1.0 + (x / largest).log10() / decades).max(0.0).min(1.0);
+        MIN_BODY_SIZE + (MAX_BODY_SIZE - MIN_BODY_SIZE) * fraction
+    }
+}
+
+// Palette slot of a body added since load, from an FNV-1a hash of its id, so that it is stable between runs
+fn hashed_slot(id: &str) -> usize {
+    id.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::entity::Entity;
+    use crate::nbody::nbody_system::NBodySystem;
+    use crate::nbody::checkpoint::Checkpoint;
+    use crate::units::UnitSystem;
+
+    fn test_system() -> System {
+        let mut entities = ["star", "inner", "outer"].iter().enumerate()
+            .map(|(i, id)| Entity::new_cartesian(id.to_string(), 1.0 / (1.0 + i as f64), [i as f64, 0.0, 0.0], [0.0; 3]))
+            .collect::<Vec<_>>();
+        entities[2].display = Some(EntityDisplay { colour: Some("pink".to_string()), label: Some("Outer".to_string()), ..EntityDisplay::default() });
+
+        System::new("styles".to_string(), UnitSystem::default(), None, 0.0, None, entities)
+    }
+
+    fn colours(styles: &BodyStyles, state: &State<f64>) -> Vec<[f32; 4]> {
+        styles.resolve(state).iter().map(|x| x.colour).collect()
+    }
+
+    #[test]
+    fn palette_colours_follow_ids_when_bodies_are_removed() {
+        let system = test_system();
+        let styles = BodyStyles::from_system(&system);
+        let mut state = system.generate_state::<f64>();
+        let before = colours(&styles, &state);
+
+        state.remove_entity(0);
+        assert_eq!(colours(&styles, &state), before[1..].to_vec());
+        assert_eq!(before[2], colour::parse("pink").unwrap());
+    }
+
+    #[test]
+    fn restored_checkpoints_keep_declared_styles() {
+        let system = test_system();
+        let nbody = NBodySystem::<f64>::new(&system, 4);
+        let checkpoint: Checkpoint = serde_json::from_str(&serde_json::to_string(&nbody.to_checkpoint()).unwrap()).unwrap();
+        let restored = NBodySystem::<f64>::from_checkpoint(&checkpoint).unwrap();
+
+        let state = system.generate_state::<f64>();
+        let original = BodyStyles::from_system(&system).resolve(&state);
+        let restored = BodyStyles::from_checkpoint(restored.get_displays()).resolve(&state);
+        assert_eq!(original.iter().map(|x| x.colour).collect::<Vec<_>>(), restored.iter().map(|x| x.colour).collect::<Vec<_>>());
+        assert_eq!(restored[2].label.as_deref(), Some("Outer"));
+    }
+}
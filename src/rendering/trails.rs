@@ -0,0 +1,201 @@
+use std::ops::{Add, Sub, Mul, Div, AddAssign};
+use core::iter::Sum;
+use piston_window::*;
+use crate::core::types::{Numeric, Time};
+use crate::nbody::nbody_system::NBodySystem;
+use crate::nbody::history::HistoryStamp;
+use crate::state::State;
+use crate::math::vec3::Vec3;
+use crate::frames::{self, ReferenceFrame};
+use super::camera::{Camera, CameraFocus};
+use super::style::BodyStyle;
+use super::{colour, RenderOptions};
+
+pub const TRAIL_ALPHA: f32 = 0.6;
+const TRAIL_SAMPLE_COUNT: usize = 100;          // Trails are drawn through at most this many past states
+const TRAIL_LINE_WIDTH: f64 = 0.002;
+const MIN_FADE_ALPHA: f32 = 0.05;               // Fraction of the full alpha at the oldest end of a faded trail
+
+// How trail segments are coloured
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrailColouring {
+    Body,           // The trail colour of each body
+    Speed,          // Speed in the frame of the trail, scaled over each trail
+    Energy          // Specific orbital energy, scaled over each trail
+}
+
+impl TrailColouring {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.to_lowercase().as_str() {
+            "body" => Ok(TrailColouring::Body),
+            "speed" => Ok(TrailColouring::Speed),
+            "energy" => Ok(TrailColouring::Energy),
+            other => Err(format!("Unknown trail colouring \"{}\"", other))
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        match self {
+            TrailColouring::Body => TrailColouring::Speed,
+            TrailColouring::Speed => TrailColouring::Energy,
+            TrailColouring::Energy => TrailColouring::Body
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            TrailColouring::Body => "body",
+            TrailColouring::Speed => "speed",
+            TrailColouring::Energy => "energy"
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TrailOptions {
+    pub length: Option<Time>,               // Simulation time spanned by trails, drawing on any archived history where
+                                            // the in-memory history is too short; the full in-memory history if not set
+    pub fade: bool,                         // Fades each trail towards its oldest end
+    pub colouring: TrailColouring,
+    pub frame: Option<ReferenceFrame>       // Draws trails as seen in this frame, rather than the display frame
+}
+
+impl Default for TrailOptions {
+    fn default() -> Self {
+        Self { length: None, fade: true, colouring: TrailColouring::Body, frame: None }
+    }
+}
+
+// Past state to be drawn as part of the trails, with its age as a fraction of the trail length
+struct TrailSample<TNum>
+    where TNum: Numeric {
+    state: State<TNum>,
+    age: f64,
+    values: Vec<f64>            // Quantity determining the colour of each body, if not coloured by body
+}
+
+// Draws the trail of each body through its recent history.  Past states are shown in the display frame at
+// their own time or, where a trail frame is set, in the trail frame at their own time and then re-expressed in
+// the display frame of the current state
+pub fn render_trails<TNum>(g: &mut G2d, context: &Context, camera: &Camera, system: &NBodySystem<TNum>,
+                           current: &State<TNum>, styles: &[BodyStyle], options: &RenderOptions)
+    where TNum: Numeric + Add<Output=TNum> + Sub<Output=TNum> + Mul<Output=TNum> + Div<Output=TNum> + AddAssign + Sum {
+
+    let trails = options.trails;
+    let history = system.get_full_state_history();
+    let inertial = &*history[0];
+    let gravitational_constant = system.get_gravitational_constant().into_f64();
+
+    // Select states within the trail length, by age, and sample them evenly.  Where the trail reaches beyond the
+    // in-memory history, the retained states in range are taken from the archive as well
+    let now = system.get_clock().elapsed();
+    let stamps = system.get_full_state_stamps();
+    let archived = match trails.length {
+        Some(length) if system.get_history_archive().is_some() && !covers_length(&stamps, now, length) =>
+            system.get_state_history_range(now - length, now + length).unwrap_or_default(),
+        _ => vec![]
+    };
+    let past: Vec<(&State<TNum>, Option<f64>)> = match trails.length {
+        Some(length) if !archived.is_empty() => archived.iter().rev()
+            .map(|x| (&x.state, Some((now - x.stamp.time).abs() / length)))
+            .collect(),
+        Some(length) => history.iter().zip(stamps.iter())
+            .map(|(state, stamp)| (&**state, stamp.map(|x| (now - x.time).abs() / length).filter(|&age| age <= 1.0)))
+            .take_while(|(_, age)| age.is_some())
+            .collect(),
+        None => history.iter().enumerate()
+            .map(|(i, state)| (&**state, Some(i as f64 / (history.len() - 1).max(1) as f64)))
+            .collect()
+    };
+
+    let interval = (past.len() / TRAIL_SAMPLE_COUNT).max(1);
+    let samples = past.iter()
+        .step_by(interval)
+        .filter_map(|&(state, age)| age.map(|age| (state, age)))
+        .map(|(past, age)| {
+            let state = match trails.frame {
+                Some(frame) if frame != options.frame => frames::transform_state(past, frame),
+                _ => frames::transform_state(past, options.frame)
+            };
+            let values = match trails.colouring {
+                TrailColouring::Body => vec![],
+                TrailColouring::Speed => state.velocities().iter().map(|x| x.to_f64().length()).collect(),
+                TrailColouring::Energy => specific_energies(&frames::transform_state(past, ReferenceFrame::Barycentric), gravitational_constant)
+            };
+            let state = match trails.frame {
+                Some(frame) if frame != options.frame => frames::reexpress_state(&state, inertial, frame, options.frame),
+                _ => state
+            };
+            let state = match options.trail_focus {
+                Some(focus) => relative_to_focus(state, &focus, current),
+                None => state
+            };
+
+            TrailSample { state, age, values }
+        })
+        .collect::<Vec<_>>();
+
+    // Scale the colouring quantity over the length of each trail, so that its variation around an orbit is visible
+    let ranges = (0..current.entity_count())
+        .map(|i| samples.iter()
+            .filter_map(|x| x.values.get(i).cloned())
+            .fold((std::f64::MAX, std::f64::MIN), |(mn, mx), x| (mn.min(x), mx.max(x))))
+        .collect::<Vec<_>>();
+
+    let project = |state: &State<TNum>, i: usize| camera.project(&state.position(i).to_f64());
+    for pair in samples.windows(2) {
+        let (newer, older) = (&pair[0], &pair[1]);
+        let fade = if trails.fade { 1.0 - (1.0 - MIN_FADE_ALPHA) * older.age.min(1.0) as f32 } else { 1.0 };
+
+        (0..current.entity_count().min(newer.state.entity_count()).min(older.state.entity_count()))
+            .filter_map(|i| project(&newer.state, i).and_then(|p0| project(&older.state, i).map(|p1| (i, p0, p1))))
+            .for_each(|(i, p0, p1)| {
+                let colour = match (trails.colouring, newer.values.get(i)) {
+                    (TrailColouring::Body, _) | (_, None) => styles[i].trail_colour,
+                    (_, Some(&value)) => {
+                        let (mn, mx) = ranges[i];
+                        let scaled = if mx > mn { (value - mn) / (mx - mn) } else { 0.5 };
+                        colour::with_alpha(colour::gradient(&colour::PALETTE_VIRIDIS, scaled), TRAIL_ALPHA)
+                    }
+                };
+
+                line_from_to(colour::with_alpha(colour, colour[3] * fade), TRAIL_LINE_WIDTH, p0.screen, p1.screen, context.transform, g);
+            });
+    }
+}
+
+// Whether the in-memory history, given newest first, reaches back at least the given time from now
+fn covers_length(stamps: &[Option<HistoryStamp>], now: Time, length: Time) -> bool {
+    stamps.iter().rev().find_map(|x| *x).map(|oldest| (now - oldest.time).abs() >= length).unwrap_or(false)
+}
+
+// Kinetic plus potential energy per unit mass of each body, with velocities as given in the state, which
+// should be barycentric
+fn specific_energies<TNum>(state: &State<TNum>, gravitational_constant: f64) -> Vec<f64>
+    where TNum: Numeric {
+    let positions = state.positions().iter().map(|x| x.to_f64()).collect::<Vec<_>>();
+    let masses = state.masses().iter().map(|x| x.into_f64()).collect::<Vec<_>>();
+
+    state.velocities().iter()
+        .enumerate()
+        .map(|(i, vel)| {
+            let potential = positions.iter().zip(masses.iter())
+                .enumerate()
+                .filter(|(j, _)| i != *j)
+                .map(|(_, (pos, mass))| -gravitational_constant * mass / (pos - &positions[i]).length())
+                .sum::<f64>();
+
+            0.5 * vel.to_f64().length().powi(2) + potential
+        })
+        .collect()
+}
+
+// Shifts a historical state so that the focus point lies where it is in the current state
+fn relative_to_focus<TNum>(mut state: State<TNum>, focus: &CameraFocus, current: &State<TNum>) -> State<TNum>
+    where TNum: Numeric {
+    if let (Some(then), Some(now)) = (focus.position(&state), focus.position(current)) {
+        let offset = &now - &then;
+        state.positions_mut().iter_mut().for_each(|p| *p = Vec3::from(*(p.to_f64() + offset.clone()).get_data()));
+    }
+    state
+}
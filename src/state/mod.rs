@@ -16,6 +16,11 @@ pub struct State<TNum>
 impl <TNum> State<TNum>
     where TNum: Numeric {
 
+    pub fn from_parts(id: Vec<String>, mass: Scalars<TNum>, position: Vectors<TNum>, velocity: Vectors<TNum>,
+                      acceleration: Vectors<TNum>) -> Self {
+        Self { id, mass, position, velocity, acceleration }
+    }
+
     pub fn new() -> Self {
         Self {
             id: vec![],
@@ -46,6 +51,9 @@ pub fn set_id(&mut self, index: usize, id: String) { self.id[index] = id; }
     pub fn accelerations(&self) -> &Vectors<TNum> { &self.acceleration }
     pub fn accelerations_mut(&mut self) -> &mut Vectors<TNum> { &mut self.acceleration }
 
+    pub fn entity_count(&self) -> usize { self.id.len() }
+    pub fn index_of(&self, id: &str) -> Option<usize> { self.id.iter().position(|x| x == id) }
+
     pub fn add_entity(&mut self, id: String, mass: TNum, position: Vec3<TNum>, velocity: Vec3<TNum>,
                       acceleration: Vec3<TNum>) {
 
@@ -55,6 +63,14 @@ pub fn set_id(&mut self, index: usize, id: String) { self.id[index] = id; }
         self.velocity.push(velocity);
         self.acceleration.push(acceleration);
     }
+
+    pub fn remove_entity(&mut self, index: usize) {
+        self.id.remove(index);
+        self.mass.remove(index);
+        self.position.remove(index);
+        self.velocity.remove(index);
+        self.acceleration.remove(index);
+    }
 }
 
 impl <TNum> Clone for State<TNum>
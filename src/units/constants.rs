@@ -0,0 +1,12 @@
+pub const GRAVITATIONAL_CONSTANT_SI: f64 = 6.67430e-11;
+
+pub const ASTRONOMICAL_UNIT_M: f64 = 1.495978707e11;
+pub const PARSEC_M: f64 = 3.0856775814913673e16;
+pub const LIGHT_YEAR_M: f64 = 9.4607304725808e15;
+
+pub const SOLAR_MASS_KG: f64 = 1.98847e30;
+pub const JUPITER_MASS_KG: f64 = 1.89813e27;
+pub const EARTH_MASS_KG: f64 = 5.97217e24;
+
+pub const DAY_S: f64 = 86400.0;
+pub const JULIAN_YEAR_S: f64 = 365.25 * DAY_S;
@@ -0,0 +1,95 @@
+pub mod constants;
+pub mod quantity;
+
+use serde::*;
+use self::constants::*;
+use self::quantity::Dimension;
+
+// Declared unit set of a system.  All values within the system are expressed in the base length, mass
+// and time units of this set
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UnitSystem {
+    Si,
+    AuMsunYr,
+    PcMsunMyr,
+    Henon { total_mass_msun: f64, virial_radius_pc: f64 }     // N-body units with G = M = 1, E = -1/4
+}
+
+impl UnitSystem {
+    pub fn length_si(&self) -> f64 {
+        match self {
+            UnitSystem::Si => 1.0,
+            UnitSystem::AuMsunYr => ASTRONOMICAL_UNIT_M,
+            UnitSystem::PcMsunMyr => PARSEC_M,
+            UnitSystem::Henon { virial_radius_pc, .. } => virial_radius_pc * PARSEC_M
+        }
+    }
+
+    pub fn mass_si(&self) -> f64 {
+        match self {
+            UnitSystem::Si => 1.0,
+            UnitSystem::AuMsunYr | UnitSystem::PcMsunMyr => SOLAR_MASS_KG,
+            UnitSystem::Henon { total_mass_msun, .. } => total_mass_msun * SOLAR_MASS_KG
+        }
+    }
+
+    pub fn time_si(&self) -> f64 {
+        match self {
+            UnitSystem::Si => 1.0,
+            UnitSystem::AuMsunYr => JULIAN_YEAR_S,
+            UnitSystem::PcMsunMyr => JULIAN_YEAR_S * 1e6,
+            UnitSystem::Henon { .. } => (self.length_si().powi(3) / (GRAVITATIONAL_CONSTANT_SI * self.mass_si())).sqrt()
+        }
+    }
+
+    // Size of one unit of the given dimension, in SI
+    pub fn scale_si(&self, dimension: Dimension) -> f64 {
+        let (l, m, t) = dimension.exponents();
+        self.length_si().powi(l) * self.mass_si().powi(m) * self.time_si().powi(t)
+    }
+
+    pub fn gravitational_constant(&self) -> f64 {
+        GRAVITATIONAL_CONSTANT_SI / self.scale_si(Dimension::GravitationalParameter) * self.mass_si()
+    }
+
+    // Converts a value of the given dimension from this unit system into another
+    pub fn convert(&self, value: f64, dimension: Dimension, target: &UnitSystem) -> f64 {
+        value * self.scale_si(dimension) / target.scale_si(dimension)
+    }
+
+    pub fn length_label(&self) -> &'static str {
+        match self {
+            UnitSystem::Si => "m",
+            UnitSystem::AuMsunYr => "AU",
+            UnitSystem::PcMsunMyr => "pc",
+            UnitSystem::Henon { .. } => "[L]"
+        }
+    }
+
+    pub fn mass_label(&self) -> &'static str {
+        match self {
+            UnitSystem::Si => "kg",
+            UnitSystem::AuMsunYr | UnitSystem::PcMsunMyr => "Msun",
+            UnitSystem::Henon { .. } => "[M]"
+        }
+    }
+
+    pub fn time_label(&self) -> &'static str {
+        match self {
+            UnitSystem::Si => "s",
+            UnitSystem::AuMsunYr => "yr",
+            UnitSystem::PcMsunMyr => "Myr",
+            UnitSystem::Henon { .. } => "[T]"
+        }
+    }
+
+    pub fn velocity_label(&self) -> String {
+        format!("{}/{}", self.length_label(), self.time_label())
+    }
+}
+
+impl Default for UnitSystem {
+    // Systems without declared units have historically used AU, solar masses and years
+    fn default() -> Self { UnitSystem::AuMsunYr }
+}
@@ -0,0 +1,129 @@
+use super::UnitSystem;
+use super::constants::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dimension {
+    Length,
+    Mass,
+    Time,
+    Velocity,
+    Acceleration,
+    GravitationalParameter
+}
+
+impl Dimension {
+    // Exponents of (length, mass, time)
+    pub fn exponents(&self) -> (i32, i32, i32) {
+        match self {
+            Dimension::Length => (1, 0, 0),
+            Dimension::Mass => (0, 1, 0),
+            Dimension::Time => (0, 0, 1),
+            Dimension::Velocity => (1, 0, -1),
+            Dimension::Acceleration => (1, 0, -2),
+            Dimension::GravitationalParameter => (3, 0, -2)
+        }
+    }
+}
+
+// A named unit of a single dimension, e.g. "km", "msun" or "au/day"
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unit {
+    dimension: Dimension,
+    scale_si: f64
+}
+
+impl Unit {
+    pub fn dimension(&self) -> Dimension { self.dimension }
+    pub fn scale_si(&self) -> f64 { self.scale_si }
+
+    pub fn parse(unit: &str) -> Result<Self, String> {
+        let unit = unit.trim().to_lowercase();
+        let parts: Vec<&str> = unit.split('/').collect();
+
+        match parts.as_slice() {
+            [single] => Self::parse_base(single)
+                .map(|(dimension, scale_si)| Self { dimension, scale_si }),
+
+            [num, den] => match (Self::parse_base(num)?, Self::parse_base(den)?) {
+                ((Dimension::Length, l), (Dimension::Time, t)) => Ok(Self { dimension: Dimension::Velocity, scale_si: l / t }),
+                _ => Err(format!("Unsupported compound unit \"{}\"", unit))
+            },
+
+            [num, den, den2] => match (Self::parse_base(num)?, Self::parse_base(den)?, Self::parse_base(den2)?) {
+                ((Dimension::Length, l), (Dimension::Time, t0), (Dimension::Time, t1)) =>
+                    Ok(Self { dimension: Dimension::Acceleration, scale_si: l / (t0 * t1) }),
+                _ => Err(format!("Unsupported compound unit \"{}\"", unit))
+            },
+
+            _ => Err(format!("Unsupported compound unit \"{}\"", unit))
+        }
+    }
+
+    fn parse_base(unit: &str) -> Result<(Dimension, f64), String> {
+        match unit.trim() {
+            "m" => Ok((Dimension::Length, 1.0)),
+            "km" => Ok((Dimension::Length, 1e3)),
+            "au" => Ok((Dimension::Length, ASTRONOMICAL_UNIT_M)),
+            "ly" => Ok((Dimension::Length, LIGHT_YEAR_M)),
+            "pc" => Ok((Dimension::Length, PARSEC_M)),
+            "kpc" => Ok((Dimension::Length, PARSEC_M * 1e3)),
+
+            "kg" => Ok((Dimension::Mass, 1.0)),
+            "g" => Ok((Dimension::Mass, 1e-3)),
+            "msun" => Ok((Dimension::Mass, SOLAR_MASS_KG)),
+            "mjup" => Ok((Dimension::Mass, JUPITER_MASS_KG)),
+            "mearth" => Ok((Dimension::Mass, EARTH_MASS_KG)),
+
+            "s" => Ok((Dimension::Time, 1.0)),
+            "min" => Ok((Dimension::Time, 60.0)),
+            "h" => Ok((Dimension::Time, 3600.0)),
+            "day" | "d" => Ok((Dimension::Time, DAY_S)),
+            "yr" => Ok((Dimension::Time, JULIAN_YEAR_S)),
+            "kyr" => Ok((Dimension::Time, JULIAN_YEAR_S * 1e3)),
+            "myr" => Ok((Dimension::Time, JULIAN_YEAR_S * 1e6)),
+
+            _ => Err(format!("Unknown unit \"{}\"", unit))
+        }
+    }
+}
+
+// A value tagged with its unit, convertible into any unit system
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quantity {
+    pub value: f64,
+    pub unit: Unit
+}
+
+impl Quantity {
+    pub fn new(value: f64, unit: Unit) -> Self {
+        Self { value, unit }
+    }
+
+    pub fn in_system(&self, system: &UnitSystem) -> f64 {
+        self.value * self.unit.scale_si / system.scale_si(self.unit.dimension)
+    }
+
+    pub fn in_unit(&self, unit: &Unit) -> f64 {
+        if unit.dimension != self.unit.dimension {
+            panic!("Cannot convert between {:?} and {:?}", self.unit.dimension, unit.dimension);
+        }
+
+        self.value * self.unit.scale_si / unit.scale_si
+    }
+}
+
+// Converts a value expressed in the (optional) named unit into the given unit system, verifying
+// that it has the expected dimension
+pub fn to_system_units(value: f64, unit: &Option<String>, dimension: Dimension, system: &UnitSystem) -> f64 {
+    match unit {
+        None => value,
+        Some(unit) => {
+            let unit = Unit::parse(unit).unwrap_or_else(|e| panic!("{}", e));
+            if unit.dimension() != dimension {
+                panic!("Unit \"{:?}\" is not a {:?}", unit, dimension);
+            }
+
+            Quantity::new(value, unit).in_system(system)
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use std::time::{Duration, SystemTime, SystemTimeError};
-use chrono::{DateTime, Utc, NaiveDateTime};
+use chrono::{DateTime, Utc, NaiveDateTime, NaiveDate};
 use chrono::offset::TimeZone;
 
 pub fn utc_datetime_from_timestamp(timestamp: i64) -> DateTime<Utc> {
@@ -20,4 +20,43 @@ pub fn get_current_timestamp_secs() -> i64{
 
 pub fn _get_current_timestamp_ms() -> i64{
     Utc::now().timestamp_millis()
-}
\ No newline at end of file
+}
+
+const UNIX_EPOCH_JULIAN_DATE: f64 = 2440587.5;
+const SECONDS_PER_DAY: f64 = 86400.0;
+
+pub fn j2000_epoch() -> DateTime<Utc> {
+    Utc.ymd(2000, 1, 1).and_hms(12, 0, 0)
+}
+
+pub fn julian_date_from_datetime(datetime: &DateTime<Utc>) -> f64 {
+    let secs = datetime.timestamp() as f64 + datetime.timestamp_subsec_nanos() as f64 * 1e-9;
+    UNIX_EPOCH_JULIAN_DATE + secs / SECONDS_PER_DAY
+}
+
+pub fn datetime_from_julian_date(julian_date: f64) -> DateTime<Utc> {
+    datetime_from_unix_secs((julian_date - UNIX_EPOCH_JULIAN_DATE) * SECONDS_PER_DAY)
+}
+
+pub fn datetime_from_unix_secs(secs: f64) -> DateTime<Utc> {
+    let whole = secs.floor();
+    Utc.timestamp(whole as i64, ((secs - whole) * 1e9) as u32)
+}
+
+// Parses an epoch given either as an RFC 3339 timestamp, a bare date, or a Julian date prefixed "JD"
+pub fn parse_epoch(epoch: &str) -> Result<DateTime<Utc>, String> {
+    let epoch = epoch.trim();
+    if epoch.starts_with("JD") {
+        epoch[2..].trim().parse::<f64>()
+            .map(datetime_from_julian_date)
+            .map_err(|e| format!("Invalid Julian date \"{}\" ({})", epoch, e))
+    }
+    else if let Ok(date) = NaiveDate::parse_from_str(epoch, "%Y-%m-%d") {
+        Ok(DateTime::<Utc>::from_utc(date.and_hms(0, 0, 0), Utc))
+    }
+    else {
+        DateTime::parse_from_rfc3339(epoch)
+            .map(|x| x.with_timezone(&Utc))
+            .map_err(|e| format!("Invalid epoch \"{}\" ({})", epoch, e))
+    }
+}